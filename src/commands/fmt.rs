@@ -0,0 +1,398 @@
+use std::fs;
+
+use crate::{
+    core::documents::Block,
+    core::formatting::{Formatter, Formatters},
+    parsing::{block_parser::Input, front_matter, processing},
+    rendering::markdown,
+};
+
+const DIFF_CONTEXT: usize = 3;
+
+enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+pub struct FmtOptions {
+    check: bool,
+    paths: Vec<String>,
+}
+
+impl FmtOptions {
+    pub fn parse(args: &[String]) -> Result<FmtOptions, String> {
+        let mut check = false;
+        let mut paths = Vec::<String>::new();
+
+        for arg in args {
+            match arg.as_str() {
+                "--check" => check = true,
+                a if a.starts_with("--") => return Err(format!("Unknown option `{}`", a)),
+                a => paths.push(a.to_string()),
+            }
+        }
+
+        match paths.is_empty() {
+            true => Err("No files specified".to_string()),
+            false => Ok(FmtOptions { check, paths }),
+        }
+    }
+}
+
+// Item text is trimmed again once its marker is removed, so `*   a` and `* a` format the same
+// whatever the default marker patterns leave behind.
+fn formatters() -> Formatters {
+    Formatters::default().with_formatter(Formatter::Trim)
+}
+
+fn parse(markdown: &str) -> Vec<Block> {
+    let tokens = Input::new(markdown.split('\n').collect()).parse_blocks(&formatters());
+
+    processing::process_tokens(tokens)
+}
+
+// Front matter is kept as it is. The formatted document is parsed again and only returned if
+// it reads back as the same blocks, so formatting never changes what a document says.
+pub fn format_markdown(input: &str) -> Result<String, String> {
+    let (_, body) = front_matter::split_front_matter(input);
+    let front_matter = &input[..input.len() - body.len()];
+
+    let blocks = parse(&body);
+    let before = format!("{:?}", blocks);
+    let formatted = markdown::render_document(blocks);
+
+    match format!("{:?}", parse(&formatted)) == before {
+        true => Ok(format!("{}{}", front_matter, formatted)),
+        false => Err("The formatted document would not read back the same".to_string()),
+    }
+}
+
+// Myers' diff, finding the shortest edit script in O((n + m) * d) time for d differences. The
+// furthest reaching path on each diagonal is kept after every round to walk the script back.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffLine<'a>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::<Vec<isize>>::new();
+
+    'rounds: for d in 0..=n + m {
+        for k in (-d..=d).step_by(2) {
+            let mut x = match k == -d
+                || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize])
+            {
+                true => v[(offset + k + 1) as usize],
+                false => v[(offset + k - 1) as usize] + 1,
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[(offset + k) as usize] = x;
+
+            if x >= n && y >= m {
+                trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+                break 'rounds;
+            }
+        }
+
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+    }
+
+    let mut lines = Vec::<DiffLine>::new();
+    let (mut x, mut y) = (n, m);
+
+    // Round d was decided by the paths of round d - 1, stored from diagonal -(d - 1).
+    for d in (1..trace.len() as isize).rev() {
+        let previous = |k: isize| trace[(d - 1) as usize][(k + d - 1) as usize];
+        let k = x - y;
+        let previous_k = match k == -d || (k != d && previous(k - 1) < previous(k + 1)) {
+            true => k + 1,
+            false => k - 1,
+        };
+        let previous_x = previous(previous_k);
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            lines.push(DiffLine::Same(old[(x - 1) as usize]));
+            x -= 1;
+            y -= 1;
+        }
+
+        match x == previous_x {
+            true => lines.push(DiffLine::Added(new[(y - 1) as usize])),
+            false => lines.push(DiffLine::Removed(old[(x - 1) as usize])),
+        }
+
+        x = previous_x;
+        y = previous_y;
+    }
+
+    while x > 0 && y > 0 {
+        lines.push(DiffLine::Same(old[(x - 1) as usize]));
+        x -= 1;
+        y -= 1;
+    }
+
+    lines.reverse();
+    lines
+}
+
+fn count_lines(lines: &[DiffLine]) -> (usize, usize) {
+    lines.iter().fold((0, 0), |(o, n), l| match l {
+        DiffLine::Same(_) => (o + 1, n + 1),
+        DiffLine::Removed(_) => (o + 1, n),
+        DiffLine::Added(_) => (o, n + 1),
+    })
+}
+
+pub fn render_diff(path: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| !matches!(l, DiffLine::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+
+    // Group changes into hunks, merging any whose context would overlap.
+    let mut hunks = Vec::<(usize, usize)>::new();
+
+    for i in changed {
+        let start = i.saturating_sub(DIFF_CONTEXT);
+        let end = (i + DIFF_CONTEXT + 1).min(lines.len());
+
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {} (formatted)\n", path, path);
+
+    for (start, end) in hunks {
+        // Line numbers of the hunk start in each file are 1 based.
+        let (old_before, new_before) = count_lines(&lines[..start]);
+        let (old_count, new_count) = count_lines(&lines[start..end]);
+
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_before + 1,
+            old_count,
+            new_before + 1,
+            new_count
+        ));
+
+        for l in &lines[start..end] {
+            match l {
+                DiffLine::Same(s) => out.push_str(&format!(" {}\n", s)),
+                DiffLine::Removed(s) => out.push_str(&format!("-{}\n", s)),
+                DiffLine::Added(s) => out.push_str(&format!("+{}\n", s)),
+            }
+        }
+    }
+
+    out
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match FmtOptions::parse(args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Usage: rdom fmt [--check] <files...>");
+            return 2;
+        }
+    };
+
+    let mut unformatted = 0;
+    let mut failed = false;
+
+    for path in &options.paths {
+        let raw = match fs::read_to_string(path) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("Could not read `{}`: {}", path, e);
+                failed = true;
+                continue;
+            }
+        };
+
+        let formatted = match format_markdown(&raw) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("Could not format `{}`: {}", path, e);
+                failed = true;
+                continue;
+            }
+        };
+
+        if formatted == raw {
+            continue;
+        }
+
+        unformatted += 1;
+
+        match options.check {
+            true => print!("{}", render_diff(path, &raw, &formatted)),
+            false => {
+                if let Err(e) = fs::write(path, formatted) {
+                    eprintln!("Could not write `{}`: {}", path, e);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    match (failed, options.check && unformatted > 0) {
+        (true, _) => 1,
+        (false, true) => {
+            eprintln!("{} file(s) not formatted", unformatted);
+            1
+        }
+        (false, false) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNFORMATTED: &str =
+        "#  Title\n\n*   one\n*  two\n\n1.  first\n\n{{include file_.md}}\n\nsome *text*  \n";
+
+    fn temp_file(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("rdom-fmt-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let formatted = format_markdown(UNFORMATTED).unwrap();
+
+        assert_eq!(format_markdown(&formatted), Ok(formatted.clone()));
+        assert!(formatted.contains("* one\n* two"), "{}", formatted);
+    }
+
+    #[test]
+    fn include_directives_are_not_escaped() {
+        let formatted = format_markdown(UNFORMATTED).unwrap();
+
+        assert!(
+            formatted.contains("\n{{include file_.md}}\n"),
+            "{}",
+            formatted
+        );
+    }
+
+    #[test]
+    fn list_items_keep_continuation_lines() {
+        assert_eq!(
+            format_markdown("* item\ncontinued line\n* next\n\n1. one\ntwo\n2. three\n"),
+            Ok("* item continued line\n* next\n\n1. one two\n2. three\n".to_string())
+        );
+    }
+
+    #[test]
+    fn blocks_after_paragraphs_and_headers_are_kept() {
+        assert_eq!(
+            format_markdown("text\n# Title\n* item\n"),
+            Ok("text\n\n# Title\n\n* item\n".to_string())
+        );
+    }
+
+    #[test]
+    fn front_matter_is_kept() {
+        let raw = "---\ntitle: My Book\nauthor:   Me\n---\n#  Title\n";
+
+        assert_eq!(
+            format_markdown(raw),
+            Ok("---\ntitle: My Book\nauthor:   Me\n---\n# Title\n".to_string())
+        );
+    }
+
+    #[test]
+    fn diff_is_the_shortest_edit_script() {
+        let cases = [
+            ("", ""),
+            ("a b c", ""),
+            ("", "a b c"),
+            ("a b c a b b a", "c b a b a c"),
+            ("x a y b", "a b z"),
+        ];
+
+        for (old, new) in cases {
+            let old: Vec<&str> = old.split_whitespace().collect();
+            let new: Vec<&str> = new.split_whitespace().collect();
+            let lines = diff_lines(&old, &new);
+
+            let (mut before, mut after, mut edits) = (vec![], vec![], 0);
+            for l in &lines {
+                match l {
+                    DiffLine::Same(s) => {
+                        before.push(*s);
+                        after.push(*s);
+                    }
+                    DiffLine::Removed(s) => {
+                        before.push(*s);
+                        edits += 1;
+                    }
+                    DiffLine::Added(s) => {
+                        after.push(*s);
+                        edits += 1;
+                    }
+                }
+            }
+
+            assert_eq!((before, after), (old.clone(), new.clone()));
+            assert!(edits <= 5, "{:?} {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn diff_has_hunks_for_changed_lines() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+
+        assert_eq!(
+            render_diff("x.md", old, new),
+            "--- x.md\n+++ x.md (formatted)\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -8,3 +8,4 @@\n h\n i\n j\n+k\n"
+        );
+        assert_eq!(
+            render_diff("x.md", old, old),
+            "--- x.md\n+++ x.md (formatted)\n"
+        );
+    }
+
+    #[test]
+    fn check_fails_on_unformatted_files_without_writing_them() {
+        let path = temp_file("check.md", UNFORMATTED);
+
+        assert_eq!(run(&["--check".to_string(), path.clone()]), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), UNFORMATTED);
+        assert!(
+            render_diff(&path, UNFORMATTED, &format_markdown(UNFORMATTED).unwrap())
+                .contains("\n+* one\n")
+        );
+
+        assert_eq!(run(std::slice::from_ref(&path)), 0);
+        assert_eq!(run(&["--check".to_string(), path.clone()]), 0);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert_eq!(run(&[]), 2);
+        assert_eq!(run(&["--fix".to_string(), "a.md".to_string()]), 2);
+    }
+}
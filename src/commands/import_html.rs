@@ -0,0 +1,31 @@
+use std::{fs, path::Path};
+
+use crate::{parsing::html, rendering::markdown};
+
+pub fn run(args: &[String]) -> i32 {
+    let (input, output) = match args {
+        [i, o] => (Path::new(i), Path::new(o)),
+        _ => {
+            eprintln!("Usage: rdom import-html <input.html> <output.md>");
+            return 2;
+        }
+    };
+
+    let raw = match fs::read_to_string(input) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("Could not read `{}`: {}", input.display(), e);
+            return 1;
+        }
+    };
+
+    let blocks = html::parse_html(&raw);
+
+    match fs::write(output, markdown::render_document(blocks)) {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("Could not write `{}`: {}", output.display(), e);
+            1
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod fmt;
+pub mod import_html;
+pub mod pdf;
@@ -0,0 +1,38 @@
+use std::{fs, path::Path};
+
+use crate::{parsing::transclusion, rendering::pdf};
+
+pub fn run(args: &[String]) -> i32 {
+    let (input, output) = match args {
+        [i, o] => (Path::new(i), Path::new(o)),
+        _ => {
+            eprintln!("Usage: rdom pdf <input.md> <output.pdf>");
+            return 2;
+        }
+    };
+
+    let document = match transclusion::load_document(input) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("Could not build `{}`: {}", input.display(), e);
+            return 1;
+        }
+    };
+
+    for d in &document.diagnostics {
+        eprintln!("Warning: {}", d);
+    }
+
+    let base_path = input.parent().unwrap_or_else(|| Path::new(""));
+
+    let result = pdf::write(document.blocks, base_path)
+        .and_then(|d| fs::write(output, d).map_err(|e| e.to_string()));
+
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("Could not write `{}`: {}", output.display(), e);
+            1
+        }
+    }
+}
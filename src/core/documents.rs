@@ -1,15 +1,23 @@
 use std::collections::HashMap;
 
-#[derive(Debug)]
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "type", rename_all = "snake_case"))]
 pub enum Block {
     Header(HeaderBlock),
     Paragraph(ParagraphBlock),
     Code(CodeBlock),
     List(ListBlock),
     Image(ImageBlock),
+    Custom(CustomBlock),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "snake_case"))]
 pub enum HeaderLevel {
     H1,
     H2,
@@ -19,7 +27,8 @@ pub enum HeaderLevel {
     H6,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct HeaderBlock {
     pub(crate) style: Style,
     pub(crate) level: HeaderLevel,
@@ -27,33 +36,56 @@ pub struct HeaderBlock {
     pub(crate) indexed: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct ParagraphBlock {
     pub(crate) style: Style,
     pub(crate) content: Vec<InlineContent>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct CodeBlock {
     pub(crate) style: Style,
     pub(crate) content: String,
     pub(crate) language: Option<String>,
+    pub(crate) title: Option<String>,
+    // Inclusive ranges of line numbers, counted from 1 at the start of the block.
+    pub(crate) highlighted_lines: Vec<(usize, usize)>,
+    // The number of the first line, when line numbers are shown.
+    pub(crate) line_numbers: Option<usize>,
+    // Set until the file is read by `parsing::includes::resolve_includes`, it is a parsing
+    // directive rather than document content so it is not exported.
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub(crate) include: Option<CodeInclude>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CodeInclude {
+    pub(crate) path: String,
+    // The `lines` attribute as written, it is parsed when the file is read so that an invalid
+    // range fails the build.
+    pub(crate) lines: Option<String>,
+    pub(crate) region: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct ListBlock {
     pub(crate) ordered: bool,
     pub(crate) style: Style,
     pub(crate) items: Vec<ListItem>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct ListItem {
     pub(crate) style: Style,
     pub(crate) content: Vec<InlineContent>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct ImageBlock {
     pub(crate) style: Style,
     pub(crate) source: String,
@@ -63,36 +95,93 @@ pub struct ImageBlock {
     pub(crate) width: Option<String>,
 }
 
-#[derive(Debug)]
+// A block produced by a parser extension. Backends without special handling for `kind`
+// render `content` in its place, and the Markdown writer writes `source` back unchanged.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct CustomBlock {
+    pub(crate) kind: String,
+    pub(crate) data: Vec<(String, String)>,
+    pub(crate) content: Vec<Block>,
+    pub(crate) source: String,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "json",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 pub enum Style {
     Ref(Vec<String>),
     Custom(HashMap<String, String>),
     Default,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "type", rename_all = "snake_case"))]
 pub enum InlineContent {
     Text(InlineText),
     Span(InlineSpan),
     Link(InlineLink),
+    Image(InlineImage),
+    Custom(InlineCustom),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct InlineText {
     pub(crate) content: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct InlineSpan {
-    pub(crate) content: String,
+    pub(crate) content: Vec<InlineContent>,
     pub(crate) style: Style,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct InlineLink {
-    pub(crate) content: String,
+    pub(crate) content: Vec<InlineContent>,
     pub(crate) url: String,
     pub(crate) style: Style,
+    pub(crate) title: String,
+    // The label of the definition the link was made from, for links written as references.
+    pub(crate) reference: Option<String>,
+}
+
+// An image inside text, images on a line of their own are an `ImageBlock`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct InlineImage {
+    pub(crate) style: Style,
+    pub(crate) source: String,
+    pub(crate) title: String,
+    pub(crate) alt_text: String,
+}
+
+// Inline content produced by an inline extension. Backends without special handling for
+// `kind` render `content` as a span with `style`, and the Markdown writer writes `source`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct InlineCustom {
+    pub(crate) kind: String,
+    pub(crate) data: Vec<(String, String)>,
+    pub(crate) content: String,
+    pub(crate) style: Style,
+    pub(crate) source: String,
+}
+
+// A piece of text with the combined style of the spans around it and the URL of the link
+// around it, see `InlineContent::runs`.
+#[derive(Debug, Clone)]
+pub struct InlineRun {
+    pub(crate) text: String,
+    pub(crate) style: Style,
+    pub(crate) link: Option<String>,
 }
 
 impl Block {
@@ -115,6 +204,10 @@ impl Block {
     pub fn image(block: ImageBlock) -> Block {
         Block::Image(block)
     }
+
+    pub fn custom(block: CustomBlock) -> Block {
+        Block::Custom(block)
+    }
 }
 
 impl HeaderBlock {
@@ -169,6 +262,46 @@ impl CodeBlock {
             style,
             content,
             language,
+            title: None,
+            highlighted_lines: vec![],
+            line_numbers: None,
+            include: None,
+        }
+    }
+
+    pub fn with_include(mut self, include: CodeInclude) -> CodeBlock {
+        self.include = Some(include);
+        self
+    }
+
+    pub fn with_title(mut self, title: String) -> CodeBlock {
+        self.title = Some(title);
+        self
+    }
+
+    pub fn with_highlighted_lines(mut self, ranges: Vec<(usize, usize)>) -> CodeBlock {
+        self.highlighted_lines = ranges;
+        self
+    }
+
+    pub fn with_line_numbers(mut self, start: usize) -> CodeBlock {
+        self.line_numbers = Some(start);
+        self
+    }
+
+    pub fn is_highlighted(&self, line: usize) -> bool {
+        self.highlighted_lines
+            .iter()
+            .any(|(start, end)| line >= *start && line <= *end)
+    }
+}
+
+impl CodeInclude {
+    pub fn new(path: String, lines: Option<String>, region: Option<String>) -> CodeInclude {
+        CodeInclude {
+            path,
+            lines,
+            region,
         }
     }
 }
@@ -217,6 +350,106 @@ impl ImageBlock {
     }
 }
 
+impl CustomBlock {
+    pub fn new(kind: String, content: Vec<Block>, source: String) -> CustomBlock {
+        CustomBlock {
+            kind,
+            data: vec![],
+            content,
+            source,
+        }
+    }
+
+    pub fn with_data(mut self, key: String, value: String) -> CustomBlock {
+        self.data.push((key, value));
+        self
+    }
+}
+
+impl InlineContent {
+    pub fn text(&self) -> String {
+        match self {
+            InlineContent::Text(t) => t.content.clone(),
+            InlineContent::Span(s) => InlineContent::plain_text(&s.content),
+            InlineContent::Link(l) => InlineContent::plain_text(&l.content),
+            InlineContent::Image(i) => i.alt_text.clone(),
+            InlineContent::Custom(c) => c.content.clone(),
+        }
+    }
+
+    pub fn plain_text(content: &[InlineContent]) -> String {
+        content.iter().map(|c| c.text()).collect()
+    }
+
+    fn push_runs(
+        content: Vec<InlineContent>,
+        style: &Style,
+        link: &Option<String>,
+        runs: &mut Vec<InlineRun>,
+    ) {
+        for c in content {
+            match c {
+                InlineContent::Text(t) => runs.push(InlineRun {
+                    text: t.content,
+                    style: style.clone(),
+                    link: link.clone(),
+                }),
+                InlineContent::Span(s) => InlineContent::push_runs(
+                    s.content,
+                    &style.clone().with_style(s.style),
+                    link,
+                    runs,
+                ),
+                InlineContent::Link(l) => InlineContent::push_runs(
+                    l.content,
+                    &style.clone().with_style(l.style),
+                    &Some(l.url),
+                    runs,
+                ),
+                // Backends without inline images show the alternative text.
+                InlineContent::Image(i) => runs.push(InlineRun {
+                    text: i.alt_text,
+                    style: style.clone().with_style(i.style),
+                    link: link.clone(),
+                }),
+                InlineContent::Custom(c) => runs.push(InlineRun {
+                    text: c.content,
+                    style: style.clone().with_style(c.style),
+                    link: link.clone(),
+                }),
+            }
+        }
+    }
+
+    // Flattens nested content to runs of text, for backends that can't nest inline markup.
+    pub fn runs(content: Vec<InlineContent>) -> Vec<InlineRun> {
+        let mut runs = Vec::<InlineRun>::new();
+        InlineContent::push_runs(content, &Style::Default, &None, &mut runs);
+        runs
+    }
+
+    pub fn with_class(self, class: &str) -> InlineContent {
+        match self {
+            InlineContent::Text(t) => InlineContent::Span(InlineSpan::from_text(
+                Style::create_ref(vec![class.to_string()]),
+                t.content,
+            )),
+            InlineContent::Span(s) => {
+                InlineContent::Span(InlineSpan::new(s.style.with_class(class), s.content))
+            }
+            InlineContent::Link(l) => InlineContent::Link(l),
+            InlineContent::Image(i) => InlineContent::Image(InlineImage {
+                style: i.style.with_class(class),
+                ..i
+            }),
+            InlineContent::Custom(c) => InlineContent::Custom(InlineCustom {
+                style: c.style.with_class(class),
+                ..c
+            }),
+        }
+    }
+}
+
 impl InlineText {
     pub fn new(content: String) -> InlineText {
         InlineText { content }
@@ -224,19 +457,88 @@ impl InlineText {
 }
 
 impl InlineSpan {
-    pub fn new(style: Style, content: String) -> InlineSpan {
+    pub fn new(style: Style, content: Vec<InlineContent>) -> InlineSpan {
         InlineSpan { content, style }
     }
+
+    pub fn from_text(style: Style, text: String) -> InlineSpan {
+        InlineSpan::new(style, vec![InlineContent::Text(InlineText::new(text))])
+    }
 }
 
 impl InlineLink {
-    pub fn new(style: Style, url: String, content: String) -> InlineLink {
+    pub fn new(style: Style, url: String, content: Vec<InlineContent>) -> InlineLink {
         InlineLink {
             content,
             url,
             style,
+            title: "".to_string(),
+            reference: None,
         }
     }
+
+    pub fn with_title(self, title: String) -> InlineLink {
+        InlineLink { title, ..self }
+    }
+
+    pub fn with_reference(self, label: String) -> InlineLink {
+        InlineLink {
+            reference: Some(label),
+            ..self
+        }
+    }
+
+    pub fn from_text(style: Style, url: String, text: String) -> InlineLink {
+        InlineLink::new(style, url, vec![InlineContent::Text(InlineText::new(text))])
+    }
+}
+
+impl InlineRun {
+    // Consecutive runs in the same link (or in no link) grouped together.
+    pub fn group_by_link(runs: Vec<InlineRun>) -> Vec<(Option<String>, Vec<InlineRun>)> {
+        let mut groups = Vec::<(Option<String>, Vec<InlineRun>)>::new();
+
+        for run in runs {
+            match groups.last_mut() {
+                Some((link, group)) if *link == run.link => group.push(run),
+                _ => groups.push((run.link.clone(), vec![run])),
+            }
+        }
+
+        groups
+    }
+}
+
+impl InlineImage {
+    pub fn new(style: Style, source: String, title: String, alt_text: String) -> InlineImage {
+        InlineImage {
+            style,
+            source,
+            title,
+            alt_text,
+        }
+    }
+}
+
+impl InlineCustom {
+    pub fn new(kind: String, content: String, source: String) -> InlineCustom {
+        InlineCustom {
+            kind,
+            data: vec![],
+            content,
+            style: Style::Default,
+            source,
+        }
+    }
+
+    pub fn with_data(mut self, key: String, value: String) -> InlineCustom {
+        self.data.push((key, value));
+        self
+    }
+
+    pub fn with_style(self, style: Style) -> InlineCustom {
+        InlineCustom { style, ..self }
+    }
 }
 
 impl Style {
@@ -251,4 +553,40 @@ impl Style {
     pub fn default() -> Style {
         Style::Default
     }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        match self {
+            Style::Ref(c) => c.iter().any(|v| v == class),
+            _ => false,
+        }
+    }
+
+    pub fn with_class(self, class: &str) -> Style {
+        match self {
+            Style::Ref(mut c) => {
+                if !c.iter().any(|v| v == class) {
+                    c.push(class.to_string());
+                }
+                Style::Ref(c)
+            }
+            Style::Default => Style::Ref(vec![class.to_string()]),
+            custom => custom,
+        }
+    }
+
+    // The style of content nested in content with this style, `inner` wins where they
+    // can't be combined.
+    pub fn with_style(self, inner: Style) -> Style {
+        match (self, inner) {
+            (outer, Style::Default) => outer,
+            (Style::Ref(outer), Style::Ref(classes)) => classes
+                .iter()
+                .fold(Style::Ref(outer), |s, c| s.with_class(c)),
+            (Style::Custom(mut outer), Style::Custom(values)) => {
+                outer.extend(values);
+                Style::Custom(outer)
+            }
+            (_, inner) => inner,
+        }
+    }
 }
@@ -34,17 +34,22 @@ impl Formatters {
             formatters: vec![
                 Formatter::Trim,
                 Formatter::RegexReplace(RegexReplaceFormatter {
-                    pattern: "^(\\* )",
+                    pattern: "^(\\*(\\s+|$))",
                     replacement: "",
                 }),
                 Formatter::RegexReplace(RegexReplaceFormatter {
-                    pattern: "^([0-9]\\. )|^([0-9][0-9]\\. )",
+                    pattern: "^([0-9]{1,2}\\.(\\s+|$))",
                     replacement: "",
                 }),
             ],
         }
     }
 
+    pub fn with_formatter(mut self, formatter: Formatter) -> Formatters {
+        self.formatters.push(formatter);
+        self
+    }
+
     pub fn run_preprocessors(&self, value: String) -> String {
         self.preprocessors
             .clone()
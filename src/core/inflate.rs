@@ -0,0 +1,335 @@
+// Decoder for zlib/deflate streams (RFC 1950 and 1951), used to read PNG image data.
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u32,
+}
+
+// Canonical Huffman table, codes are decoded a bit at a time as in zlib's `puff.c`.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl<'a> BitReader<'a> {
+    fn read_bit(&mut self) -> Result<u32, String> {
+        let byte = self
+            .data
+            .get(self.position)
+            .ok_or_else(|| "Unexpected end of deflate stream".to_string())?;
+
+        let b = (*byte as u32 >> self.bit) & 1;
+
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.position += 1;
+        }
+
+        Ok(b)
+    }
+
+    fn read_bits(&mut self, count: u8) -> Result<u32, String> {
+        let mut value = 0;
+
+        for i in 0..count {
+            value |= self.read_bit()? << i;
+        }
+
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+
+    fn decode(&mut self, table: &Huffman) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+
+        for len in 1..16 {
+            code |= self.read_bit()? as i32;
+            let count = table.counts[len] as i32;
+
+            if code - count < first {
+                return Ok(table.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err("Invalid Huffman code in deflate stream".to_string())
+    }
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+
+        for l in lengths {
+            counts[*l as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, l) in lengths.iter().enumerate() {
+            if *l != 0 {
+                symbols[offsets[*l as usize] as usize] = symbol as u16;
+                offsets[*l as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn fixed() -> (Huffman, Huffman) {
+        let mut lengths = [0u8; 288];
+
+        for (i, l) in lengths.iter_mut().enumerate() {
+            *l = match i {
+                0..=143 => 8,
+                144..=255 => 9,
+                256..=279 => 7,
+                _ => 8,
+            };
+        }
+
+        (Huffman::new(&lengths), Huffman::new(&[5u8; 30]))
+    }
+}
+
+fn read_dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_count = reader.read_bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for i in CODE_LENGTH_ORDER.iter().take(code_count) {
+        code_lengths[*i] = reader.read_bits(3)? as u8;
+    }
+
+    let code_table = Huffman::new(&code_lengths);
+    let mut lengths = Vec::<u8>::new();
+
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match reader.decode(&code_table)? {
+            s if s < 16 => (s as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| "Repeat with no previous length".to_string())?;
+                (previous, 3 + reader.read_bits(2)?)
+            }
+            17 => (0, 3 + reader.read_bits(3)?),
+            _ => (0, 11 + reader.read_bits(7)?),
+        };
+
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+
+    if lengths.len() > literal_count + distance_count {
+        return Err("Too many code lengths in deflate stream".to_string());
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    loop {
+        match reader.decode(literals)? as usize {
+            s if s < 256 => output.push(s as u8),
+            256 => return Ok(()),
+            s if s < 286 => {
+                let i = s - 257;
+                let length = LENGTH_BASE[i] as usize + reader.read_bits(LENGTH_EXTRA[i])? as usize;
+
+                let d = reader.decode(distances)? as usize;
+                if d >= 30 {
+                    return Err("Invalid distance in deflate stream".to_string());
+                }
+                let distance =
+                    DISTANCE_BASE[d] as usize + reader.read_bits(DISTANCE_EXTRA[d])? as usize;
+
+                if distance > output.len() {
+                    return Err("Distance too far back in deflate stream".to_string());
+                }
+
+                let start = output.len() - distance;
+                for k in 0..length {
+                    output.push(output[start + k]);
+                }
+            }
+            _ => return Err("Invalid length in deflate stream".to_string()),
+        }
+    }
+}
+
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader {
+        data,
+        position: 0,
+        bit: 0,
+    };
+    let mut output = Vec::<u8>::new();
+
+    loop {
+        let last = reader.read_bits(1)? == 1;
+
+        match reader.read_bits(2)? {
+            0 => {
+                reader.align();
+                let header = data
+                    .get(reader.position..reader.position + 4)
+                    .ok_or_else(|| "Unexpected end of deflate stream".to_string())?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+
+                if u16::from_le_bytes([header[2], header[3]]) != !length {
+                    return Err("Invalid stored block length in deflate stream".to_string());
+                }
+
+                let length = length as usize;
+                let start = reader.position + 4;
+                let stored = data
+                    .get(start..start + length)
+                    .ok_or_else(|| "Unexpected end of deflate stream".to_string())?;
+
+                output.extend_from_slice(stored);
+                reader.position = start + length;
+            }
+            1 => {
+                let (literals, distances) = Huffman::fixed();
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err("Invalid deflate block type".to_string()),
+        }
+
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+pub fn inflate_zlib(data: &[u8]) -> Result<Vec<u8>, String> {
+    // Two byte header, the Adler-32 trailer is not checked.
+    match data.len() > 2 && data[0] & 0x0F == 8 {
+        true => inflate(&data[2..]),
+        false => Err("Not a zlib stream".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &[u8] = b"The quick brown fox jumps over the lazy dog. The quick brown fox jumps over the lazy dog. The quick brown fox jumps over the lazy dog. Pack my box with five dozen liquor jugs.";
+
+    #[test]
+    fn stored_blocks() {
+        assert_eq!(
+            inflate(&[1, 7, 0, 248, 255, b's', b't', b'o', b'r', b'e', b'd', b'!']),
+            Ok(b"stored!".to_vec())
+        );
+        assert_eq!(
+            inflate(&[1, 7, 0, 0, 0, b's', b't', b'o', b'r', b'e', b'd', b'!']),
+            Err("Invalid stored block length in deflate stream".to_string())
+        );
+        assert_eq!(
+            inflate(&[1, 7, 0, 248, 255, b's']),
+            Err("Unexpected end of deflate stream".to_string())
+        );
+    }
+
+    #[test]
+    fn fixed_blocks() {
+        assert_eq!(
+            inflate(&[75, 76, 74, 78, 132, 33, 133, 140, 212, 156, 156, 124, 100, 18, 0]),
+            Ok(b"abcabcabcabc hello hello hello".to_vec())
+        );
+    }
+
+    #[test]
+    fn dynamic_blocks() {
+        let compressed = [
+            181, 203, 199, 1, 128, 32, 16, 5, 209, 86, 126, 5, 212, 226, 193, 6, 64, 73, 6, 86,
+            178, 80, 189, 219, 132, 231, 121, 179, 58, 141, 88, 253, 118, 66, 37, 234, 1, 134, 94,
+            28, 245, 126, 50, 168, 233, 132, 194, 249, 146, 115, 96, 39, 43, 176, 254, 134, 23,
+            201, 238, 30, 80, 140, 186, 47, 14, 198, 55, 205, 105, 234, 128, 203, 199, 74, 137, 95,
+            155, 197, 7,
+        ];
+
+        assert_eq!(inflate(&compressed), Ok(TEXT.to_vec()));
+        assert!(inflate(&compressed[..40]).is_err());
+    }
+
+    #[test]
+    fn blocks_follow_each_other() {
+        // A stored block that isn't the last, then a fixed one.
+        let mut data = vec![0, 2, 0, 253, 255, b'h', b'i'];
+        data.extend_from_slice(&[
+            75, 76, 74, 78, 132, 33, 133, 140, 212, 156, 156, 124, 100, 18, 0,
+        ]);
+
+        assert_eq!(
+            inflate(&data),
+            Ok(b"hiabcabcabcabc hello hello hello".to_vec())
+        );
+    }
+
+    #[test]
+    fn zlib_streams() {
+        assert_eq!(
+            inflate_zlib(&[120, 218, 203, 200, 4, 0, 1, 59, 0, 210]),
+            Ok(b"hi".to_vec())
+        );
+        assert_eq!(
+            inflate_zlib(&[1, 2, 3]),
+            Err("Not a zlib stream".to_string())
+        );
+        assert_eq!(inflate(&[7]), Err("Invalid deflate block type".to_string()));
+    }
+}
@@ -1,2 +1,4 @@
 pub mod documents;
 pub mod formatting;
+pub mod inflate;
+pub mod zip;
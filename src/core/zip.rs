@@ -0,0 +1,226 @@
+// Minimal zip writer, entries are stored uncompressed which every reader (and the EPUB and
+// OOXML containers) accepts.
+
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+#[derive(Default)]
+pub struct ZipWriter {
+    buffer: Vec<u8>,
+    entries: Vec<ZipEntry>,
+}
+
+// 1980-01-01 00:00, fixed so archives are reproducible.
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = 0x0021;
+
+// General purpose flag bit 11, names are UTF-8.
+const UTF8_NAMES: u16 = 1 << 11;
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+
+    !crc
+}
+
+fn push_u16(buffer: &mut Vec<u8>, v: u16) {
+    buffer.extend_from_slice(&v.to_le_bytes());
+}
+
+fn push_u32(buffer: &mut Vec<u8>, v: u32) {
+    buffer.extend_from_slice(&v.to_le_bytes());
+}
+
+impl ZipWriter {
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        if self.buffer.len() + data.len() > u32::MAX as usize {
+            return Err("Archive is too large (zip64 is not supported)".to_string());
+        }
+
+        if self.entries.len() == u16::MAX as usize {
+            return Err("Archive has too many entries (zip64 is not supported)".to_string());
+        }
+
+        if name.len() > u16::MAX as usize {
+            return Err(format!("Entry name is too long: `{}`", name));
+        }
+
+        let entry = ZipEntry {
+            name: name.to_string(),
+            crc: crc32(data),
+            size: data.len() as u32,
+            offset: self.buffer.len() as u32,
+        };
+
+        // Local file header.
+        push_u32(&mut self.buffer, 0x0403_4b50);
+        push_u16(&mut self.buffer, 10);
+        push_u16(&mut self.buffer, UTF8_NAMES);
+        push_u16(&mut self.buffer, 0);
+        push_u16(&mut self.buffer, DOS_TIME);
+        push_u16(&mut self.buffer, DOS_DATE);
+        push_u32(&mut self.buffer, entry.crc);
+        push_u32(&mut self.buffer, entry.size);
+        push_u32(&mut self.buffer, entry.size);
+        push_u16(&mut self.buffer, entry.name.len() as u16);
+        push_u16(&mut self.buffer, 0);
+        self.buffer.extend_from_slice(entry.name.as_bytes());
+        self.buffer.extend_from_slice(data);
+
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let directory_offset = self.buffer.len() as u32;
+
+        for entry in &self.entries {
+            // Central directory file header.
+            push_u32(&mut self.buffer, 0x0201_4b50);
+            push_u16(&mut self.buffer, 20);
+            push_u16(&mut self.buffer, 10);
+            push_u16(&mut self.buffer, UTF8_NAMES);
+            push_u16(&mut self.buffer, 0);
+            push_u16(&mut self.buffer, DOS_TIME);
+            push_u16(&mut self.buffer, DOS_DATE);
+            push_u32(&mut self.buffer, entry.crc);
+            push_u32(&mut self.buffer, entry.size);
+            push_u32(&mut self.buffer, entry.size);
+            push_u16(&mut self.buffer, entry.name.len() as u16);
+            push_u16(&mut self.buffer, 0);
+            push_u16(&mut self.buffer, 0);
+            push_u16(&mut self.buffer, 0);
+            push_u16(&mut self.buffer, 0);
+            push_u32(&mut self.buffer, 0);
+            push_u32(&mut self.buffer, entry.offset);
+            self.buffer.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let directory_size = self.buffer.len() as u32 - directory_offset;
+
+        // End of central directory record.
+        push_u32(&mut self.buffer, 0x0605_4b50);
+        push_u16(&mut self.buffer, 0);
+        push_u16(&mut self.buffer, 0);
+        push_u16(&mut self.buffer, self.entries.len() as u16);
+        push_u16(&mut self.buffer, self.entries.len() as u16);
+        push_u32(&mut self.buffer, directory_size);
+        push_u32(&mut self.buffer, directory_offset);
+        push_u16(&mut self.buffer, 0);
+
+        self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(data: &[u8], i: usize) -> u16 {
+        u16::from_le_bytes([data[i], data[i + 1]])
+    }
+
+    fn u32_at(data: &[u8], i: usize) -> u32 {
+        u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]])
+    }
+
+    // Reads the entries back through the central directory, checking the local headers agree.
+    fn read_entries(data: &[u8]) -> Vec<(String, u16, Vec<u8>)> {
+        let end = data.len() - 22;
+        assert_eq!(u32_at(data, end), 0x0605_4b50);
+
+        let count = u16_at(data, end + 10) as usize;
+        let mut i = u32_at(data, end + 16) as usize;
+        let mut entries = vec![];
+
+        for _ in 0..count {
+            assert_eq!(u32_at(data, i), 0x0201_4b50);
+            let flags = u16_at(data, i + 8);
+            let crc = u32_at(data, i + 16);
+            let size = u32_at(data, i + 20) as usize;
+            let name_length = u16_at(data, i + 28) as usize;
+            let offset = u32_at(data, i + 42) as usize;
+            let name = String::from_utf8(data[i + 46..i + 46 + name_length].to_vec()).unwrap();
+
+            assert_eq!(u32_at(data, offset), 0x0403_4b50);
+            assert_eq!(u16_at(data, offset + 6), flags);
+            assert_eq!(u32_at(data, offset + 14), crc);
+            let start = offset + 30 + u16_at(data, offset + 26) as usize;
+            let content = data[start..start + size].to_vec();
+            assert_eq!(crc32(&content), crc);
+
+            entries.push((name, flags, content));
+            i += 46 + name_length;
+        }
+
+        entries
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn entries_are_read_back_in_order() {
+        let mut zip = ZipWriter::default();
+        zip.add_file("mimetype", b"application/epub+zip").unwrap();
+        zip.add_file("docs/résumé.txt", "ünïcödé".as_bytes())
+            .unwrap();
+        zip.add_file("empty", b"").unwrap();
+
+        let data = zip.finish();
+
+        // The first entry's data directly follows its header, as EPUB readers expect.
+        assert_eq!(&data[30..38], b"mimetype");
+        assert_eq!(&data[38..58], b"application/epub+zip");
+
+        assert_eq!(
+            read_entries(&data),
+            vec![
+                (
+                    "mimetype".to_string(),
+                    UTF8_NAMES,
+                    b"application/epub+zip".to_vec()
+                ),
+                (
+                    "docs/résumé.txt".to_string(),
+                    UTF8_NAMES,
+                    "ünïcödé".as_bytes().to_vec()
+                ),
+                ("empty".to_string(), UTF8_NAMES, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn entry_count_is_limited() {
+        let mut zip = ZipWriter::default();
+
+        for i in 0..u16::MAX {
+            zip.add_file(&i.to_string(), b"").unwrap();
+        }
+
+        assert_eq!(
+            zip.add_file("one-too-many", b""),
+            Err("Archive has too many entries (zip64 is not supported)".to_string())
+        );
+        assert_eq!(read_entries(&zip.finish()).len(), u16::MAX as usize);
+    }
+}
@@ -6,16 +6,28 @@ use crate::{
     parsing::{inline_parser, processing},
     templating::mustache::{MustacheData, MustacheValue},
 };
-use std::{collections::HashMap, fs};
+use std::{collections::HashMap, env, fs, process};
 
 use crate::parsing::block_parser::Input;
 
+pub mod commands;
 pub mod core;
 pub mod parsing;
 pub mod rendering;
 pub mod templating;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(|a| a.as_str()) {
+        Some("fmt") => process::exit(commands::fmt::run(&args[1..])),
+        Some("import-html") => process::exit(commands::import_html::run(&args[1..])),
+        Some("pdf") => process::exit(commands::pdf::run(&args[1..])),
+        _ => demo(),
+    }
+}
+
+fn demo() {
     let template =
         fs::read_to_string("/home/max/Projects/rdom/examples/test_template.mustache").unwrap();
 
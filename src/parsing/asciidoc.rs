@@ -0,0 +1,537 @@
+use crate::core::documents::{
+    Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, InlineContent, InlineLink, InlineSpan,
+    InlineText, ListBlock, ListItem, ParagraphBlock, Style,
+};
+
+const URL_SCHEMES: [&str; 3] = ["https://", "http://", "mailto:"];
+
+fn starts_with_at(chars: &[char], i: usize, s: &str) -> bool {
+    s.chars()
+        .enumerate()
+        .all(|(k, c)| chars.get(i + k) == Some(&c))
+}
+
+fn is_word_char(c: Option<&char>) -> bool {
+    c.map(|c| c.is_alphanumeric()).unwrap_or(false)
+}
+
+fn find_close(chars: &[char], from: usize, delimiter: &[char], constrained: bool) -> Option<usize> {
+    (from + 1..chars.len().saturating_sub(delimiter.len() - 1)).find(|j| {
+        chars[*j..*j + delimiter.len()] == *delimiter
+            && !chars[j - 1].is_whitespace()
+            && (!constrained || !is_word_char(chars.get(j + delimiter.len())))
+    })
+}
+
+fn read_until(chars: &[char], from: usize, end: char) -> Option<(String, usize)> {
+    (from..chars.len())
+        .find(|j| chars[*j] == end)
+        .map(|j| (chars[from..j].iter().collect(), j))
+}
+
+fn create_link(url: String, text: String) -> InlineContent {
+    let text = match text.trim().is_empty() {
+        true => url.clone(),
+        false => text.trim().to_string(),
+    };
+
+    InlineContent::Link(InlineLink::from_text(Style::Default, url, text))
+}
+
+fn parse_link(chars: &[char], i: usize) -> Option<(InlineContent, usize)> {
+    // Cross references, `<<id,text>>`.
+    if starts_with_at(chars, i, "<<") {
+        let end = (i + 2..chars.len()).find(|j| starts_with_at(chars, *j, ">>"))?;
+        let inner: String = chars[i + 2..end].iter().collect();
+        let (id, text) = inner.split_once(',').unwrap_or((&inner, ""));
+
+        return Some((
+            create_link(format!("#{}", id.trim()), text.to_string()),
+            end + 2,
+        ));
+    }
+
+    let (start, explicit) = match starts_with_at(chars, i, "link:") {
+        true => (i + 5, true),
+        false => match URL_SCHEMES.iter().any(|s| starts_with_at(chars, i, s)) {
+            true => (i, false),
+            false => return None,
+        },
+    };
+
+    let end = (start..chars.len())
+        .find(|j| chars[*j].is_whitespace() || chars[*j] == '[')
+        .unwrap_or(chars.len());
+
+    match chars.get(end) {
+        Some('[') => {
+            let (text, close) = read_until(chars, end + 1, ']')?;
+            let url = chars[start..end].iter().collect();
+
+            Some((create_link(url, text), close + 1))
+        }
+        _ if explicit => None,
+        _ => {
+            // Trailing punctuation belongs to the sentence rather than the url.
+            let mut end = end;
+            while end > start && ".,;:!?)".contains(chars[end - 1]) {
+                end -= 1;
+            }
+            let url: String = chars[start..end].iter().collect();
+
+            Some((create_link(url.clone(), url), end))
+        }
+    }
+}
+
+fn create_styled(delimiter: char, inner: &[char]) -> Vec<InlineContent> {
+    let class = match delimiter {
+        '*' => "b",
+        '_' => "i",
+        _ => {
+            return vec![InlineContent::Span(InlineSpan::from_text(
+                Style::create_ref(vec!["code".to_string()]),
+                inner.iter().collect(),
+            ))]
+        }
+    };
+
+    parse_chars(inner)
+        .into_iter()
+        .map(|c| c.with_class(class))
+        .collect()
+}
+
+fn flush_text(buffer: &mut String, content: &mut Vec<InlineContent>) {
+    if !buffer.is_empty() {
+        content.push(InlineContent::Text(InlineText::new(std::mem::take(buffer))));
+    }
+}
+
+fn parse_chars(chars: &[char]) -> Vec<InlineContent> {
+    let mut content = Vec::<InlineContent>::new();
+    let mut buffer = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\\' && i + 1 < chars.len() && "*_`\\<".contains(chars[i + 1]) {
+            buffer.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+
+        if "*_`".contains(c) {
+            // Unconstrained pairs (`**`) can be used mid-word, constrained (`*`) cannot.
+            let unconstrained = chars.get(i + 1) == Some(&c);
+            let found = match unconstrained {
+                true => find_close(chars, i + 2, &[c, c], false).map(|e| (i + 2, e, e + 2)),
+                false => None,
+            }
+            .or_else(|| {
+                match !is_word_char(i.checked_sub(1).and_then(|p| chars.get(p)))
+                    && chars
+                        .get(i + 1)
+                        .map(|n| !n.is_whitespace())
+                        .unwrap_or(false)
+                {
+                    true => find_close(chars, i + 1, &[c], true).map(|e| (i + 1, e, e + 1)),
+                    false => None,
+                }
+            });
+
+            if let Some((start, end, next)) = found {
+                flush_text(&mut buffer, &mut content);
+                content.extend(create_styled(c, &chars[start..end]));
+                i = next;
+                continue;
+            }
+        }
+
+        if !is_word_char(i.checked_sub(1).and_then(|p| chars.get(p))) {
+            if let Some((link, next)) = parse_link(chars, i) {
+                flush_text(&mut buffer, &mut content);
+                content.push(link);
+                i = next;
+                continue;
+            }
+        }
+
+        buffer.push(c);
+        i += 1;
+    }
+
+    flush_text(&mut buffer, &mut content);
+    content
+}
+
+pub fn parse_inline(text: &str) -> Vec<InlineContent> {
+    parse_chars(&text.chars().collect::<Vec<char>>())
+}
+
+fn header_level(line: &str) -> Option<(HeaderLevel, &str)> {
+    let count = line.chars().take_while(|c| *c == '=').count();
+    let text = line[count..].strip_prefix(' ')?;
+
+    let level = match count {
+        1 => HeaderLevel::H1,
+        2 => HeaderLevel::H2,
+        3 => HeaderLevel::H3,
+        4 => HeaderLevel::H4,
+        5 => HeaderLevel::H5,
+        6 => HeaderLevel::H6,
+        _ => return None,
+    };
+
+    Some((level, text.trim()))
+}
+
+fn is_delimiter(line: &str, c: char) -> bool {
+    line.len() >= 4 && line.chars().all(|l| l == c)
+}
+
+fn is_attribute_entry(line: &str) -> bool {
+    line.strip_prefix(':')
+        .and_then(|l| l.split_once(':'))
+        .map(|(name, _)| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '!')
+        })
+        .unwrap_or(false)
+}
+
+fn list_marker(line: &str) -> Option<(bool, &str)> {
+    let line = line.trim_start();
+    let first = line.chars().next()?;
+
+    let (ordered, count) = match first {
+        '*' => (false, line.chars().take_while(|c| *c == '*').count()),
+        '-' => (false, 1),
+        '.' => (true, line.chars().take_while(|c| *c == '.').count()),
+        c if c.is_ascii_digit() => {
+            let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+            match line[digits..].starts_with('.') {
+                true => (true, digits + 1),
+                false => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    line[count..]
+        .strip_prefix(' ')
+        .map(|text| (ordered, text.trim()))
+}
+
+fn is_block_start(line: &str) -> bool {
+    header_level(line).is_some()
+        || is_delimiter(line, '-')
+        || is_delimiter(line, '.')
+        || line.starts_with("image::")
+        || (line.starts_with('[') && line.ends_with(']'))
+}
+
+fn source_language(attributes: &Option<String>) -> Option<String> {
+    let attributes = attributes.as_ref()?;
+    let mut parts = attributes.split(',').map(|p| p.trim());
+
+    match parts.next() {
+        Some("source") | Some("listing") | Some("") => parts
+            .next()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string()),
+        _ => None,
+    }
+}
+
+fn create_image_block(line: &str, title: Option<String>) -> Option<ImageBlock> {
+    let rest = line.strip_prefix("image::")?;
+    let (source, attributes) = rest.split_once('[')?;
+    let attributes = attributes.strip_suffix(']')?;
+
+    let (mut alt, mut width, mut height, mut named_title) = (None, None, None, None);
+
+    for (i, attribute) in attributes
+        .split(',')
+        .map(|a| a.trim())
+        .filter(|a| !a.is_empty())
+        .enumerate()
+    {
+        let value = |v: &str| Some(v.trim().trim_matches('"').to_string());
+
+        match attribute.split_once('=') {
+            Some(("alt", v)) => alt = value(v),
+            Some(("width", v)) => width = value(v),
+            Some(("height", v)) => height = value(v),
+            Some(("title", v)) => named_title = value(v),
+            Some(_) => {}
+            None => match i {
+                0 => alt = value(attribute),
+                1 => width = value(attribute),
+                2 => height = value(attribute),
+                _ => {}
+            },
+        }
+    }
+
+    Some(ImageBlock::new(
+        Style::Default,
+        source.trim().to_string(),
+        named_title.or(title).unwrap_or_default(),
+        alt.unwrap_or_default(),
+        height,
+        width,
+    ))
+}
+
+fn parse_list(lines: &[&str], start: usize) -> (ListBlock, usize) {
+    let ordered = list_marker(lines[start]).map(|(o, _)| o).unwrap_or(false);
+    let mut items = Vec::<String>::new();
+    let mut i = start;
+
+    while i < lines.len() {
+        let line = lines[i].trim_end();
+
+        if line.is_empty() {
+            // Blank lines between items of the same list do not end it.
+            let next = (i..lines.len()).find(|j| !lines[*j].trim().is_empty());
+            match next.and_then(|j| list_marker(lines[j])) {
+                Some((o, _)) if o == ordered => {
+                    i = next.unwrap();
+                    continue;
+                }
+                _ => break,
+            }
+        }
+
+        match list_marker(line) {
+            // Nested items are flattened, the document model has no nested lists. An item of
+            // the other kind starts a new list, as after a blank line.
+            Some((o, text)) if o == ordered => items.push(text.to_string()),
+            Some(_) => break,
+            None if is_block_start(line) => break,
+            // `+` joins a following block to the item, only the text is kept.
+            None if line == "+" => {}
+            None => {
+                if let Some(last) = items.last_mut() {
+                    last.push(' ');
+                    last.push_str(line.trim());
+                }
+            }
+        }
+
+        i += 1;
+    }
+
+    let items = items
+        .into_iter()
+        .map(|t| ListItem::new(Style::Default, parse_inline(&t)))
+        .collect();
+
+    (ListBlock::new(Style::Default, ordered, items), i)
+}
+
+fn parse_delimited(lines: &[&str], start: usize) -> (String, usize) {
+    let delimiter = lines[start].trim_end();
+    let end = (start + 1..lines.len())
+        .find(|j| lines[*j].trim_end() == delimiter)
+        .unwrap_or(lines.len());
+
+    (lines[start + 1..end].join("\n"), end + 1)
+}
+
+fn flush_paragraph(paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>) {
+    if !paragraph.is_empty() {
+        let text = paragraph.join(" ");
+        blocks.push(Block::paragraph(ParagraphBlock::new(
+            Style::Default,
+            parse_inline(&text),
+        )));
+        paragraph.clear();
+    }
+}
+
+pub fn parse_asciidoc(input: &str) -> Vec<Block> {
+    let lines: Vec<&str> = input.lines().collect();
+    let mut blocks = Vec::<Block>::new();
+    let mut paragraph = Vec::<&str>::new();
+    let mut attributes: Option<String> = None;
+    let mut title: Option<String> = None;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i].trim_end();
+
+        if line.is_empty() {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            i += 1;
+            continue;
+        }
+
+        if is_delimiter(line, '/') {
+            i = parse_delimited(&lines, i).1;
+            continue;
+        }
+
+        if line.starts_with("//") || (paragraph.is_empty() && is_attribute_entry(line)) {
+            i += 1;
+            continue;
+        }
+
+        if let Some((level, text)) = header_level(line) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            let indexed = matches!(level, HeaderLevel::H1 | HeaderLevel::H2);
+            blocks.push(Block::header(HeaderBlock::new(
+                Style::Default,
+                level,
+                parse_inline(text),
+                indexed,
+            )));
+            i += 1;
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            attributes = Some(line[1..line.len() - 1].to_string());
+            i += 1;
+            continue;
+        }
+
+        // Block titles, `.Title`, are only kept for images.
+        if paragraph.is_empty()
+            && line.starts_with('.')
+            && line[1..]
+                .chars()
+                .next()
+                .map(|c| c != '.' && !c.is_whitespace())
+                .unwrap_or(false)
+        {
+            title = Some(line[1..].to_string());
+            i += 1;
+            continue;
+        }
+
+        if is_delimiter(line, '-') || is_delimiter(line, '.') {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            let (content, next) = parse_delimited(&lines, i);
+            let language = source_language(&attributes);
+            blocks.push(Block::code(CodeBlock::new(
+                Style::Default,
+                content,
+                language,
+            )));
+            attributes = None;
+            title = None;
+            i = next;
+            continue;
+        }
+
+        if let Some(image) = create_image_block(line, title.clone()) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            blocks.push(Block::image(image));
+            attributes = None;
+            title = None;
+            i += 1;
+            continue;
+        }
+
+        if paragraph.is_empty() && list_marker(line).is_some() {
+            let (list, next) = parse_list(&lines, i);
+            blocks.push(Block::list(list));
+            i = next;
+            continue;
+        }
+
+        paragraph.push(line.trim_start());
+        attributes = None;
+        i += 1;
+    }
+
+    flush_paragraph(&mut paragraph, &mut blocks);
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::markdown;
+
+    // Fixtures are compared as Markdown, which shows every part of the document model used here.
+    fn convert(input: &str) -> String {
+        markdown::render_document(parse_asciidoc(input))
+    }
+
+    #[test]
+    fn headers() {
+        assert_eq!(
+            convert("= Title\n\n== Section\n\n=== Sub\n\n====== Six\n\n======= Seven"),
+            "# Title\n\n## Section\n\n### Sub\n\n###### Six\n\n======= Seven\n"
+        );
+    }
+
+    #[test]
+    fn paragraphs_comments_and_attributes() {
+        assert_eq!(
+            convert(":toc: left\n\nOne\n  two\n// comment\n\n////\nhidden\n////\nThree"),
+            "One two\n\nThree\n"
+        );
+    }
+
+    #[test]
+    fn inline_markup() {
+        assert_eq!(
+            convert("*bold*, _italic_, `mono`, **un**con and a*b* \\*escaped*"),
+            "**bold**, *italic*, `mono`, **un**con and a\\*b\\* \\*escaped\\*\n"
+        );
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            convert("See https://a.b/c. or https://a.b[A B], link:x.html[X] and <<setup,Set up>>."),
+            "See [https://a.b/c](https://a.b/c). or [A B](https://a.b), [X](x.html) and [Set up](#setup).\n"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            convert("* one\n* two\n** nested\n\n* after blank\n- dash"),
+            "* one\n* two\n* nested\n* after blank\n* dash\n"
+        );
+        assert_eq!(
+            convert(". first\n. second\n+\ncontinued\n3. third"),
+            "1. first\n2. second continued\n3. third\n"
+        );
+    }
+
+    #[test]
+    fn lists_end_when_the_kind_changes() {
+        assert_eq!(
+            convert("* one\n. first\n* two"),
+            "* one\n\n1. first\n\n* two\n"
+        );
+        assert_eq!(convert("* one\n\n. first"), "* one\n\n1. first\n");
+    }
+
+    #[test]
+    fn code_blocks() {
+        assert_eq!(
+            convert("[source,rust]\n----\nfn main() {}\n----\n\n....\nliteral *text*\n...."),
+            "```rust\nfn main() {}\n```\n\n```\nliteral *text*\n```\n"
+        );
+        assert_eq!(convert("----\nunclosed"), "```\nunclosed\n```\n");
+    }
+
+    #[test]
+    fn images() {
+        assert_eq!(
+            convert(".A caption\nimage::a.png[Alt,200,100]\n\nimage::b.png[alt=\"B\",width=50%,title=\"Named\"]"),
+            "![Alt](a.png \"A caption\"){height:100, width:200}\n\n![B](b.png \"Named\"){width:50%}\n"
+        );
+    }
+}
@@ -1,4 +1,9 @@
-use crate::core::formatting::Formatters;
+use std::rc::Rc;
+
+use crate::core::{documents::Block, formatting::Formatters};
+
+use super::extensions::{self, BlockExtension, BuiltinParser, Parser, Priority};
+use super::{inline_parser, references};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LineType {
@@ -8,6 +13,8 @@ pub enum LineType {
     UnorderedListItem,
     CodeBlockDelimited,
     Image,
+    Include,
+    LinkDefinition,
     Empty,
 }
 
@@ -26,6 +33,11 @@ pub enum BlockToken {
     UnorderedListItem(String),
     CodeBlock(Option<String>, String),
     Image(String),
+    Include(String),
+    // A link reference definition, resolved by `processing` and not part of the output.
+    LinkDefinition(String),
+    // A block made by an extension.
+    Block(Block),
     Empty,
     Unknown(String),
 }
@@ -33,22 +45,69 @@ pub enum BlockToken {
 #[derive(Debug)]
 pub struct Input {
     lines: Vec<Line>,
+    extensions: Vec<(Priority, Rc<dyn BlockExtension>)>,
+}
+
+// The width of the leading whitespace in columns (with tab stops every four columns) and the
+// rest of the line.
+fn split_indentation(line: &str) -> (usize, &str) {
+    let rest = line.trim_start_matches([' ', '\t']);
+
+    let width = line[..line.len() - rest.len()]
+        .chars()
+        .fold(0, |w, c| match c {
+            '\t' => w + 4 - w % 4,
+            _ => w + 1,
+        });
+
+    (width, rest)
+}
+
+// A marker followed by whitespace or the end of the line.
+fn starts_with_marker(s: &str, marker: &str) -> bool {
+    match s.strip_prefix(marker) {
+        Some(rest) => rest.is_empty() || rest.starts_with([' ', '\t']),
+        None => false,
+    }
+}
+
+fn starts_with_ordered_marker(s: &str) -> bool {
+    let digits = s.chars().take_while(|c| c.is_ascii_digit()).count();
+
+    (1..=2).contains(&digits) && starts_with_marker(&s[digits..], ".")
+}
+
+// An image with nothing but its attributes after it, images anywhere else are inline.
+fn is_image_line(s: &str) -> bool {
+    match inline_parser::read_image(s) {
+        Some((_, next)) => {
+            let rest = s[next..].trim();
+            rest.is_empty() || (rest.starts_with('{') && rest.ends_with('}'))
+        }
+        None => false,
+    }
 }
 
 impl LineType {
     pub fn new(line: &str) -> LineType {
-        match line {
-            s if s.is_empty() => LineType::Empty,
-            s if s.len() < 3 => LineType::Text,
-            s if s.chars().nth(0) == Some('#') => LineType::Header,
-            s if &s[0..3] == "```" => LineType::CodeBlockDelimited,
-            s if &s[0..2] == "* " => LineType::UnorderedListItem,
-            s if s.chars().nth(0).unwrap_or('\n').is_digit(10)
-                && (s.chars().nth(1) == Some('.') || s.chars().nth(2) == Some('.')) =>
-            {
-                LineType::OrderedListItem
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let (indent, s) = split_indentation(line);
+
+        match s {
+            s if s.trim().is_empty() => LineType::Empty,
+            // Lines indented by four columns or more are never the start of a block.
+            _ if indent >= 4 => LineType::Text,
+            s if s.starts_with("{{include ") && s.trim_end().ends_with("}}") => LineType::Include,
+            s if s.starts_with('#') => LineType::Header,
+            // As in CommonMark the info string can't contain backticks, so a line starting with
+            // a code span isn't a fence.
+            s if s.starts_with("```") && !s.trim_start_matches('`').contains('`') => {
+                LineType::CodeBlockDelimited
             }
-            s if s.chars().nth(0) == Some('!') => LineType::Image,
+            s if starts_with_marker(s, "*") => LineType::UnorderedListItem,
+            s if starts_with_ordered_marker(s) => LineType::OrderedListItem,
+            s if is_image_line(s) => LineType::Image,
+            s if references::parse_definition(s).is_some() => LineType::LinkDefinition,
             _ => LineType::Text,
         }
     }
@@ -61,12 +120,39 @@ impl Input {
             .enumerate()
             .map(|(i, l)| Line {
                 number: i,
-                text: l.to_string(),
+                // CRLF line endings are read as LF.
+                text: l.strip_suffix('\r').unwrap_or(l).to_string(),
                 line_type: LineType::new(l),
             })
             .collect();
 
-        Input { lines: ls }
+        Input {
+            lines: ls,
+            extensions: vec![],
+        }
+    }
+
+    pub fn with_extension(self, extension: Box<dyn BlockExtension>, priority: Priority) -> Input {
+        self.with_shared_extension(Rc::from(extension), priority)
+    }
+
+    // As `with_extension`, for an extension that's also registered on other inputs.
+    pub fn with_shared_extension(
+        mut self,
+        extension: Rc<dyn BlockExtension>,
+        priority: Priority,
+    ) -> Input {
+        self.extensions.push((priority, extension));
+        self
+    }
+
+    // Lines claimed by extensions tried after the paragraph parser are paragraph text anyway.
+    fn is_claimed(&self, i: usize) -> bool {
+        self.in_bounds(i)
+            && self.extensions.iter().any(|(p, e)| {
+                extensions::runs_before(*p, BuiltinParser::Paragraph)
+                    && e.claims(&self.lines[i].text)
+            })
     }
 
     pub fn try_get_line(&self, index: usize) -> Option<Line> {
@@ -133,6 +219,15 @@ impl Input {
         (lines, i)
     }
 
+    // The index of the last line of a block ending at `last`, taking one empty line after it
+    // along with it.
+    fn with_trailing_empty(&self, last: usize) -> usize {
+        match (self.in_bounds(last + 1), self.get_line_type(last + 1)) {
+            (true, LineType::Empty) => last + 1,
+            _ => last,
+        }
+    }
+
     fn format_block_text(lines: Vec<Line>, formatters: &Formatters) -> String {
         let mut s = String::new();
 
@@ -156,11 +251,21 @@ impl Input {
     ) -> Option<(BlockToken, usize)> {
         match self.get_line_type(curr) {
             LineType::Text => {
-                let (lines, next) = self.try_get_until_end_or_not_type(curr, LineType::Text);
+                let (mut lines, end) = self.try_get_until_end_or_not_type(curr, LineType::Text);
+
+                // A paragraph ends before a line claimed by an extension, so the
+                // extension's block can follow it without an empty line in between.
+                let last = match (1..lines.len()).find(|k| self.is_claimed(curr + k)) {
+                    Some(k) => {
+                        lines.truncate(k);
+                        curr + k - 1
+                    }
+                    None => self.with_trailing_empty(end - 1),
+                };
 
                 Some((
                     BlockToken::Paragraph(Input::format_block_text(lines, formatters)),
-                    next,
+                    last,
                 ))
             }
             _ => None,
@@ -177,7 +282,7 @@ impl Input {
                 let lines = vec![self.lines[curr].clone()];
                 Some((
                     BlockToken::Header(Input::format_block_text(lines, formatters)),
-                    curr + 1,
+                    self.with_trailing_empty(curr),
                 ))
             }
             _ => None,
@@ -212,25 +317,18 @@ impl Input {
         formatters: &Formatters,
     ) -> Option<(BlockToken, usize)> {
         match self.get_line_type(curr) {
-            LineType::OrderedListItem => match self.get_line_type(curr + 1) {
-                LineType::Text => {
-                    let (lines, next) =
-                        self.try_get_until_end_or_not_type(curr + 1, LineType::Text);
-
-                    Some((
-                        BlockToken::OrderedListItem(Input::format_block_text(lines, formatters)),
-                        next,
-                    ))
-                }
-                _ => {
-                    let lines = vec![self.lines[curr].clone()];
-
-                    Some((
-                        BlockToken::OrderedListItem(Input::format_block_text(lines, formatters)),
-                        curr,
-                    ))
-                }
-            },
+            // Text lines straight after the item continue it.
+            LineType::OrderedListItem => {
+                let (rest, end) = self.try_get_until_end_or_not_type(curr + 1, LineType::Text);
+                let lines = std::iter::once(self.lines[curr].clone())
+                    .chain(rest)
+                    .collect();
+
+                Some((
+                    BlockToken::OrderedListItem(Input::format_block_text(lines, formatters)),
+                    end - 1,
+                ))
+            }
             _ => None,
         }
     }
@@ -241,25 +339,18 @@ impl Input {
         formatters: &Formatters,
     ) -> Option<(BlockToken, usize)> {
         match self.get_line_type(curr) {
-            LineType::UnorderedListItem => match self.get_line_type(curr + 1) {
-                LineType::Text => {
-                    let (lines, next) =
-                        self.try_get_until_end_or_not_type(curr + 1, LineType::Text);
-
-                    Some((
-                        BlockToken::UnorderedListItem(Input::format_block_text(lines, formatters)),
-                        next,
-                    ))
-                }
-                _ => {
-                    let lines = vec![self.lines[curr].clone()];
-
-                    Some((
-                        BlockToken::UnorderedListItem(Input::format_block_text(lines, formatters)),
-                        curr,
-                    ))
-                }
-            },
+            // Text lines straight after the item continue it.
+            LineType::UnorderedListItem => {
+                let (rest, end) = self.try_get_until_end_or_not_type(curr + 1, LineType::Text);
+                let lines = std::iter::once(self.lines[curr].clone())
+                    .chain(rest)
+                    .collect();
+
+                Some((
+                    BlockToken::UnorderedListItem(Input::format_block_text(lines, formatters)),
+                    end - 1,
+                ))
+            }
             _ => None,
         }
     }
@@ -270,7 +361,27 @@ impl Input {
         formatters: &Formatters,
     ) -> Option<(BlockToken, usize)> {
         match self.get_line_type(curr) {
-            LineType::Image => Some((BlockToken::Image(self.lines[curr].text.clone()), curr)),
+            LineType::Image => Some((
+                BlockToken::Image(self.lines[curr].text.trim_start().to_string()),
+                curr,
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn try_parse_include(&self, curr: usize) -> Option<(BlockToken, usize)> {
+        match self.get_line_type(curr) {
+            LineType::Include => Some((BlockToken::Include(self.lines[curr].text.clone()), curr)),
+            _ => None,
+        }
+    }
+
+    pub fn try_parse_link_definition(&self, curr: usize) -> Option<(BlockToken, usize)> {
+        match self.get_line_type(curr) {
+            LineType::LinkDefinition => Some((
+                BlockToken::LinkDefinition(self.lines[curr].text.trim_start().to_string()),
+                curr,
+            )),
             _ => None,
         }
     }
@@ -282,21 +393,56 @@ impl Input {
         }
     }
 
+    pub fn try_parse_extension(&self, curr: usize, index: usize) -> Option<(BlockToken, usize)> {
+        let (_, extension) = &self.extensions[index];
+
+        if !extension.claims(&self.lines[curr].text) {
+            return None;
+        }
+
+        let lines: Vec<&str> = self.lines[curr..].iter().map(|l| l.text.as_str()).collect();
+
+        extension
+            .parse(&lines)
+            .map(|(block, count)| (BlockToken::Block(block), curr + count.max(1) - 1))
+    }
+
+    fn try_parse_with(
+        &self,
+        parser: Parser,
+        curr: usize,
+        formatters: &Formatters,
+    ) -> Option<(BlockToken, usize)> {
+        match parser {
+            Parser::Builtin(BuiltinParser::OrderedList) => {
+                self.try_parse_ordered_list_item(curr, formatters)
+            }
+            Parser::Builtin(BuiltinParser::UnorderedList) => {
+                self.try_parse_unordered_list_item(curr, formatters)
+            }
+            Parser::Builtin(BuiltinParser::Header) => self.try_parse_header(curr, formatters),
+            Parser::Builtin(BuiltinParser::Code) => self.try_parse_code_block(curr, formatters),
+            Parser::Builtin(BuiltinParser::Image) => self.try_parse_image(curr, formatters),
+            Parser::Builtin(BuiltinParser::Include) => self.try_parse_include(curr),
+            Parser::Builtin(BuiltinParser::LinkDefinition) => self.try_parse_link_definition(curr),
+            Parser::Builtin(BuiltinParser::Paragraph) => self.try_parse_paragraph(curr, formatters),
+            Parser::Builtin(BuiltinParser::Empty) => self.try_parse_empty(curr),
+            Parser::Extension(i) => self.try_parse_extension(curr, i),
+        }
+    }
+
     pub fn try_parse_block(
         &self,
         curr: usize,
         formatters: &Formatters,
     ) -> Option<(BlockToken, usize)> {
         if self.in_bounds(curr) {
-            self.try_parse_ordered_list_item(curr, &formatters)
-                .or_else(|| self.try_parse_unordered_list_item(curr, &formatters))
-                .or_else(|| self.try_parse_header(curr, &formatters))
-                .or_else(|| self.try_parse_code_block(curr, &formatters))
-                .or_else(|| self.try_parse_image(curr, &formatters))
-                .or_else(|| self.try_parse_paragraph(curr, &formatters))
-                .or_else(|| self.try_parse_empty(curr))
-                .map(|(bt, n)| Some((bt, n + 1)))
-                .unwrap_or(None)
+            let priorities: Vec<Priority> = self.extensions.iter().map(|(p, _)| *p).collect();
+
+            extensions::parser_order(&priorities)
+                .into_iter()
+                .find_map(|p| self.try_parse_with(p, curr, formatters))
+                .map(|(bt, n)| (bt, n + 1))
         } else {
             // Not strictly needed.
             None
@@ -304,7 +450,19 @@ impl Input {
     }
 
     pub fn parse_blocks(&self, formatters: &Formatters) -> Vec<BlockToken> {
-        let mut tokens = Vec::<BlockToken>::new();
+        self.parse_blocks_with_spans(formatters)
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect()
+    }
+
+    // As `parse_blocks`, with the first and last line of each token (counted from 1). Trailing
+    // empty lines consumed with a token are not part of its span.
+    pub fn parse_blocks_with_spans(
+        &self,
+        formatters: &Formatters,
+    ) -> Vec<(BlockToken, (usize, usize))> {
+        let mut tokens = Vec::<(BlockToken, (usize, usize))>::new();
         let mut i: usize = 0;
 
         while self.in_bounds(i) {
@@ -312,10 +470,234 @@ impl Input {
                 .try_parse_block(i, formatters)
                 .unwrap_or((BlockToken::Unknown(self.lines[i].text.clone()), i + 1));
 
-            tokens.push(token);
+            let last = (i..next.min(self.lines.len()))
+                .rev()
+                .find(|l| self.lines[*l].line_type != LineType::Empty)
+                .unwrap_or(i);
+
+            tokens.push((token, (i + 1, last + 1)));
             i = next;
         }
 
         tokens
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::CustomBlock;
+
+    #[test]
+    fn multibyte_line_starts() {
+        assert_eq!(LineType::new("é"), LineType::Text);
+        assert_eq!(LineType::new("日本語"), LineType::Text);
+        assert_eq!(LineType::new("🦀 crab"), LineType::Text);
+        assert_eq!(LineType::new("![ölçü](a.png)"), LineType::Image);
+    }
+
+    #[test]
+    fn short_lines() {
+        assert_eq!(LineType::new("#"), LineType::Header);
+        assert_eq!(LineType::new("*"), LineType::UnorderedListItem);
+        assert_eq!(LineType::new("1."), LineType::OrderedListItem);
+        assert_eq!(LineType::new("a"), LineType::Text);
+        assert_eq!(LineType::new("!"), LineType::Text);
+    }
+
+    #[test]
+    fn image_lines() {
+        assert_eq!(
+            LineType::new("![a](b.png \"T\"){width:50%}"),
+            LineType::Image
+        );
+        assert_eq!(LineType::new("![a](b.png) and text"), LineType::Text);
+        assert_eq!(LineType::new("![not an image"), LineType::Text);
+        assert_eq!(LineType::new("!important"), LineType::Text);
+    }
+
+    #[test]
+    fn markers_need_whitespace() {
+        assert_eq!(LineType::new("*emphasis*"), LineType::Text);
+        assert_eq!(LineType::new("1.5 apples"), LineType::Text);
+        assert_eq!(LineType::new("123. items"), LineType::Text);
+        assert_eq!(LineType::new("12.\tTwelve"), LineType::OrderedListItem);
+        assert_eq!(LineType::new("*\titem"), LineType::UnorderedListItem);
+    }
+
+    #[test]
+    fn link_definition_lines() {
+        assert_eq!(
+            LineType::new("[Docs]: https://example.com \"The docs\""),
+            LineType::LinkDefinition
+        );
+        assert_eq!(LineType::new("   [a b]: /url"), LineType::LinkDefinition);
+        assert_eq!(LineType::new("[a]: "), LineType::Text);
+        assert_eq!(LineType::new("[a] and [b]: c"), LineType::Text);
+        assert_eq!(LineType::new("[a](b)"), LineType::Text);
+    }
+
+    #[test]
+    fn fences_have_no_backticks_after_them() {
+        assert_eq!(LineType::new("```rust {1}"), LineType::CodeBlockDelimited);
+        assert_eq!(LineType::new("````"), LineType::CodeBlockDelimited);
+        assert_eq!(LineType::new("``` a``b ``` code"), LineType::Text);
+    }
+
+    #[test]
+    fn indentation_and_tabs() {
+        assert_eq!(LineType::new("   # Header"), LineType::Header);
+        assert_eq!(LineType::new("    # Header"), LineType::Text);
+        assert_eq!(LineType::new("\t```"), LineType::Text);
+        assert_eq!(LineType::new("  \t* item"), LineType::Text);
+        assert_eq!(LineType::new(" \t "), LineType::Empty);
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        assert_eq!(LineType::new("\r"), LineType::Empty);
+        assert_eq!(LineType::new("*\r"), LineType::UnorderedListItem);
+        assert_eq!(LineType::new("```rust\r"), LineType::CodeBlockDelimited);
+        assert_eq!(LineType::new("{{include a.md}}\r"), LineType::Include);
+
+        let input = Input::new(vec!["```\r", "code\r", "```\r"]);
+        let tokens = input.parse_blocks(&Formatters::default());
+
+        assert!(matches!(&tokens[0], BlockToken::CodeBlock(_, code) if code == "code"));
+    }
+
+    // Claims lines starting with the prefix and makes a block of them up to an empty line,
+    // unless the claimed line is just the prefix.
+    struct Tagged {
+        kind: &'static str,
+        prefix: &'static str,
+    }
+
+    impl BlockExtension for Tagged {
+        fn name(&self) -> &str {
+            self.kind
+        }
+
+        fn prefix(&self) -> Option<&str> {
+            Some(self.prefix)
+        }
+
+        fn parse(&self, lines: &[&str]) -> Option<(Block, usize)> {
+            if lines[0].trim() == self.prefix {
+                return None;
+            }
+
+            let count = lines
+                .iter()
+                .position(|l| l.trim().is_empty())
+                .unwrap_or(lines.len());
+
+            Some((
+                Block::custom(CustomBlock::new(
+                    self.kind.to_string(),
+                    vec![],
+                    lines[..count].join("\n"),
+                )),
+                count,
+            ))
+        }
+    }
+
+    fn tagged(kind: &'static str, prefix: &'static str) -> Box<dyn BlockExtension> {
+        Box::new(Tagged { kind, prefix })
+    }
+
+    // The tokens as `kind:source` for extension blocks and the token name otherwise.
+    fn describe(input: Input, markdown: &str) -> Vec<String> {
+        Input {
+            lines: Input::new(markdown.split('\n').collect()).lines,
+            ..input
+        }
+        .parse_blocks(&Formatters::default())
+        .into_iter()
+        .map(|t| match t {
+            BlockToken::Block(Block::Custom(c)) => format!("{}:{}", c.kind, c.source),
+            t => format!("{:?}", t)
+                .split('(')
+                .next()
+                .unwrap_or("")
+                .to_string(),
+        })
+        .collect()
+    }
+
+    #[test]
+    fn extensions_run_at_their_priority() {
+        let markdown = "* item\n\n# Title";
+
+        let before = Input::new(vec![])
+            .with_extension(
+                tagged("star", "* "),
+                Priority::Before(BuiltinParser::UnorderedList),
+            )
+            .with_extension(tagged("hash", "# "), Priority::After(BuiltinParser::Header));
+
+        assert_eq!(
+            describe(before, markdown),
+            vec!["star:* item", "Empty", "Header"]
+        );
+
+        let after = Input::new(vec![])
+            .with_extension(
+                tagged("star", "* "),
+                Priority::After(BuiltinParser::UnorderedList),
+            )
+            .with_extension(tagged("hash", "# "), Priority::First);
+
+        assert_eq!(
+            describe(after, markdown),
+            vec!["UnorderedListItem", "Empty", "hash:# Title"]
+        );
+    }
+
+    #[test]
+    fn extensions_with_the_same_priority_run_in_registration_order() {
+        let input = Input::new(vec![])
+            .with_extension(tagged("first", "!!"), Priority::Last)
+            .with_extension(tagged("second", "!!"), Priority::Last)
+            .with_extension(tagged("third", "!"), Priority::First);
+
+        assert_eq!(describe(input, "!! a"), vec!["third:!! a"]);
+
+        let input = Input::new(vec![])
+            .with_extension(tagged("first", "!!"), Priority::First)
+            .with_extension(tagged("second", "!!"), Priority::First);
+
+        assert_eq!(describe(input, "!! a"), vec!["first:!! a"]);
+    }
+
+    #[test]
+    fn extensions_can_decline_claimed_lines() {
+        let input = Input::new(vec![]).with_extension(tagged("note", ":::"), Priority::First);
+
+        assert_eq!(
+            describe(input, ":::\n\n::: one\ntwo\n\nthree"),
+            vec!["Paragraph", "note:::: one\ntwo", "Empty", "Paragraph"]
+        );
+    }
+
+    #[test]
+    fn paragraphs_end_before_claimed_lines() {
+        let markdown = "text\nmore text\n::: note\nbody";
+
+        let before = Input::new(vec![]).with_extension(
+            tagged("note", ":::"),
+            Priority::Before(BuiltinParser::Paragraph),
+        );
+
+        assert_eq!(
+            describe(before, markdown),
+            vec!["Paragraph", "note:::: note\nbody"]
+        );
+
+        // The paragraph parser would take the claimed line anyway.
+        let after = Input::new(vec![]).with_extension(tagged("note", ":::"), Priority::Last);
+
+        assert_eq!(describe(after, markdown), vec!["Paragraph"]);
+    }
+}
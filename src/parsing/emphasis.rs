@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use crate::core::documents::{InlineContent, InlineSpan, InlineText, Style};
+
+// Emphasis is matched as in CommonMark: the inline parser reads the input into content and
+// runs of delimiter characters, and `resolve` pairs the runs up into (nested) spans. Runs
+// left without a partner are text.
+
+#[derive(Debug)]
+pub struct DelimiterRun {
+    marker: char,
+    // Characters left in the run, and the length of the run as it was read.
+    count: usize,
+    length: usize,
+    can_open: bool,
+    can_close: bool,
+}
+
+#[derive(Debug)]
+pub enum Node {
+    Content(InlineContent),
+    Delimiter(DelimiterRun),
+}
+
+fn is_space(c: Option<char>) -> bool {
+    c.map(|c| c.is_whitespace()).unwrap_or(true)
+}
+
+fn is_punctuation(c: Option<char>) -> bool {
+    c.map(|c| !c.is_alphanumeric() && !c.is_whitespace())
+        .unwrap_or(false)
+}
+
+impl DelimiterRun {
+    // `before` and `after` are the characters around the run, `None` at the start or end of
+    // the input.
+    pub fn new(
+        marker: char,
+        length: usize,
+        before: Option<char>,
+        after: Option<char>,
+    ) -> DelimiterRun {
+        let left_flanking = !is_space(after)
+            && (!is_punctuation(after) || is_space(before) || is_punctuation(before));
+        let right_flanking = !is_space(before)
+            && (!is_punctuation(before) || is_space(after) || is_punctuation(after));
+
+        let (can_open, can_close) = match marker {
+            // Underscores inside words (as in `snake_case_names`) are never emphasis.
+            '_' => (
+                left_flanking && (!right_flanking || is_punctuation(before)),
+                right_flanking && (!left_flanking || is_punctuation(after)),
+            ),
+            // Strikethrough is always two tildes, a single one is text (as in `~5 minutes`).
+            '~' if length != 2 => (false, false),
+            _ => (left_flanking, right_flanking),
+        };
+
+        DelimiterRun {
+            marker,
+            count: length,
+            length,
+            can_open,
+            can_close,
+        }
+    }
+
+    fn can_match(&self, closer: &DelimiterRun) -> bool {
+        // A run that can both open and close only pairs with one making a length that isn't
+        // a multiple of three, so `*a**b*` is `a**b` in italics.
+        let multiple_of_three = (self.can_close || closer.can_open)
+            && (self.length + closer.length).is_multiple_of(3)
+            && !(self.length.is_multiple_of(3) && closer.length.is_multiple_of(3));
+
+        self.marker == closer.marker && self.can_open && !multiple_of_three
+    }
+
+    fn text(&self) -> String {
+        self.marker.to_string().repeat(self.count)
+    }
+}
+
+fn span_style(marker: char, used: usize) -> Style {
+    match (marker, used) {
+        ('~', _) => Style::Ref(vec!["s".to_string()]),
+        (_, 1) => Style::Ref(vec!["i".to_string()]),
+        (_, _) => Style::Ref(vec!["b".to_string()]),
+    }
+}
+
+// Delimiters are text from here on, and neighbouring text is joined.
+fn into_content(nodes: Vec<Node>) -> Vec<InlineContent> {
+    let mut content = Vec::<InlineContent>::new();
+
+    for node in nodes {
+        let c = match node {
+            Node::Content(c) => c,
+            Node::Delimiter(d) => InlineContent::Text(InlineText::new(d.text())),
+        };
+
+        match (content.last_mut(), c) {
+            (Some(InlineContent::Text(last)), InlineContent::Text(t)) => {
+                last.content.push_str(&t.content)
+            }
+            (_, c) => content.push(c),
+        }
+    }
+
+    content.retain(|c| !matches!(c, InlineContent::Text(t) if t.content.is_empty()));
+    content
+}
+
+// The nodes as a linked list, with a second list through the delimiter runs that may still
+// open or close a span. Spans replace the nodes between their delimiters, so every node is
+// only moved once for each span around it.
+struct NodeList {
+    nodes: Vec<Option<Node>>,
+    next: Vec<Option<usize>>,
+    prev: Vec<Option<usize>>,
+    next_run: Vec<Option<usize>>,
+    prev_run: Vec<Option<usize>>,
+    head: Option<usize>,
+}
+
+impl NodeList {
+    fn new(nodes: Vec<Node>) -> NodeList {
+        let count = nodes.len();
+        let runs: Vec<usize> = (0..count)
+            .filter(|i| matches!(nodes[*i], Node::Delimiter(_)))
+            .collect();
+
+        let mut list = NodeList {
+            nodes: nodes.into_iter().map(Some).collect(),
+            next: (0..count)
+                .map(|i| Some(i + 1).filter(|n| *n < count))
+                .collect(),
+            prev: (0..count).map(|i| i.checked_sub(1)).collect(),
+            next_run: vec![None; count],
+            prev_run: vec![None; count],
+            head: Some(0).filter(|_| count > 0),
+        };
+
+        for pair in runs.windows(2) {
+            list.next_run[pair[0]] = Some(pair[1]);
+            list.prev_run[pair[1]] = Some(pair[0]);
+        }
+
+        list
+    }
+
+    fn first_run(&self) -> Option<usize> {
+        (0..self.nodes.len()).find(|i| matches!(self.nodes[*i], Some(Node::Delimiter(_))))
+    }
+
+    fn run(&self, i: usize) -> &DelimiterRun {
+        match &self.nodes[i] {
+            Some(Node::Delimiter(d)) => d,
+            _ => unreachable!("node {} is not a delimiter run", i),
+        }
+    }
+
+    fn run_mut(&mut self, i: usize) -> &mut DelimiterRun {
+        match &mut self.nodes[i] {
+            Some(Node::Delimiter(d)) => d,
+            _ => unreachable!("node {} is not a delimiter run", i),
+        }
+    }
+
+    fn remove_run(&mut self, i: usize) {
+        if let Some(p) = self.prev_run[i] {
+            self.next_run[p] = self.next_run[i];
+        }
+        if let Some(n) = self.next_run[i] {
+            self.prev_run[n] = self.prev_run[i];
+        }
+    }
+
+    fn remove_node(&mut self, i: usize) {
+        match self.prev[i] {
+            Some(p) => self.next[p] = self.next[i],
+            None => self.head = self.next[i],
+        }
+        if let Some(n) = self.next[i] {
+            self.prev[n] = self.prev[i];
+        }
+        self.remove_run(i);
+        self.nodes[i] = None;
+    }
+
+    // Wraps the nodes between the opener and closer in a span, and returns the closer to
+    // try next: the same one if it has delimiters left.
+    fn emphasise(&mut self, opener: usize, closer: usize) -> Option<usize> {
+        let used = match (self.run(opener).count, self.run(closer).count) {
+            (o, c) if o >= 2 && c >= 2 => 2,
+            _ => 1,
+        };
+
+        self.run_mut(opener).count -= used;
+        self.run_mut(closer).count -= used;
+
+        // Runs between the two can't be closed any more.
+        self.next_run[opener] = Some(closer);
+        self.prev_run[closer] = Some(opener);
+
+        let mut children = Vec::<Node>::new();
+        let mut i = self.next[opener];
+
+        while let Some(n) = i.filter(|n| *n != closer) {
+            children.extend(self.nodes[n].take());
+            i = self.next[n];
+        }
+
+        let span = self.nodes.len();
+        self.nodes
+            .push(Some(Node::Content(InlineContent::Span(InlineSpan::new(
+                span_style(self.run(opener).marker, used),
+                into_content(children),
+            )))));
+        self.next.push(Some(closer));
+        self.prev.push(Some(opener));
+        self.next_run.push(None);
+        self.prev_run.push(None);
+        self.next[opener] = Some(span);
+        self.prev[closer] = Some(span);
+
+        if self.run(opener).count == 0 {
+            self.remove_node(opener);
+        }
+
+        match self.run(closer).count {
+            0 => {
+                let next = self.next_run[closer];
+                self.remove_node(closer);
+                next
+            }
+            _ => Some(closer),
+        }
+    }
+
+    fn into_content(mut self) -> Vec<InlineContent> {
+        let mut nodes = Vec::<Node>::new();
+        let mut i = self.head;
+
+        while let Some(n) = i {
+            nodes.extend(self.nodes[n].take());
+            i = self.next[n];
+        }
+
+        into_content(nodes)
+    }
+}
+
+pub fn resolve(nodes: Vec<Node>) -> Vec<InlineContent> {
+    let mut list = NodeList::new(nodes);
+
+    // The closest node below which no opener was found for a kind of closer, so each run is
+    // only looked at a bounded number of times.
+    let mut bottoms = HashMap::<(char, bool, usize), Option<usize>>::new();
+    let mut closer = list.first_run();
+
+    while let Some(c) = closer {
+        let run = list.run(c);
+
+        if !run.can_close {
+            closer = list.next_run[c];
+            continue;
+        }
+
+        let key = (run.marker, run.can_open, run.length % 3);
+        let bottom = bottoms.get(&key).copied().flatten();
+
+        let mut opener = None;
+        let mut o = list.prev_run[c];
+
+        while let Some(i) = o.filter(|i| Some(*i) != bottom) {
+            if list.run(i).can_match(list.run(c)) {
+                opener = Some(i);
+                break;
+            }
+            o = list.prev_run[i];
+        }
+
+        closer = match opener {
+            Some(o) => list.emphasise(o, c),
+            None => {
+                bottoms.insert(key, list.prev_run[c]);
+                let next = list.next_run[c];
+
+                if !list.run(c).can_open {
+                    list.remove_run(c);
+                }
+
+                next
+            }
+        };
+    }
+
+    list.into_content()
+}
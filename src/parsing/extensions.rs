@@ -0,0 +1,295 @@
+use std::fmt;
+
+use crate::core::documents::{Block, InlineContent};
+
+// A parser for block syntax that isn't built in. Extensions are registered on the block
+// parser with `Input::with_extension`, and are tried at their `Priority` relative to the
+// built in parsers.
+pub trait BlockExtension {
+    fn name(&self) -> &str;
+
+    // Lines starting with the prefix are claimed, if `claims` isn't overridden.
+    fn prefix(&self) -> Option<&str> {
+        None
+    }
+
+    fn claims(&self, line: &str) -> bool {
+        match self.prefix() {
+            Some(p) => line.starts_with(p),
+            None => false,
+        }
+    }
+
+    // Called with the claimed line and every line after it, returns the block and the
+    // number of lines it was made from (at least one). `None` leaves the lines to the
+    // parsers after this one.
+    fn parse(&self, lines: &[&str]) -> Option<(Block, usize)>;
+}
+
+impl fmt::Debug for dyn BlockExtension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BlockExtension({})", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuiltinParser {
+    OrderedList,
+    UnorderedList,
+    Header,
+    Code,
+    Image,
+    Include,
+    LinkDefinition,
+    Paragraph,
+    Empty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+    First,
+    Before(BuiltinParser),
+    After(BuiltinParser),
+    Last,
+}
+
+// The order the built in parsers are tried in.
+pub const BUILTIN_ORDER: [BuiltinParser; 9] = [
+    BuiltinParser::OrderedList,
+    BuiltinParser::UnorderedList,
+    BuiltinParser::Header,
+    BuiltinParser::Code,
+    BuiltinParser::Image,
+    BuiltinParser::Include,
+    BuiltinParser::LinkDefinition,
+    BuiltinParser::Paragraph,
+    BuiltinParser::Empty,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parser {
+    Builtin(BuiltinParser),
+    // An index into the registered extensions.
+    Extension(usize),
+}
+
+// The built in parsers with the extensions placed by priority, extensions with the same
+// priority are tried in the order they were registered.
+pub fn parser_order(priorities: &[Priority]) -> Vec<Parser> {
+    let extensions_at = |priority: Priority| {
+        priorities
+            .iter()
+            .enumerate()
+            .filter(move |(_, p)| **p == priority)
+            .map(|(i, _)| Parser::Extension(i))
+    };
+
+    let mut order: Vec<Parser> = extensions_at(Priority::First).collect();
+
+    for builtin in BUILTIN_ORDER {
+        order.extend(extensions_at(Priority::Before(builtin)));
+        order.push(Parser::Builtin(builtin));
+        order.extend(extensions_at(Priority::After(builtin)));
+    }
+
+    order.extend(extensions_at(Priority::Last));
+    order
+}
+
+// Whether an extension with the priority is tried before the built in parser.
+pub fn runs_before(priority: Priority, builtin: BuiltinParser) -> bool {
+    let index = |b: BuiltinParser| BUILTIN_ORDER.iter().position(|o| *o == b);
+
+    match priority {
+        Priority::First => true,
+        Priority::Before(b) => index(b) <= index(builtin),
+        Priority::After(b) => index(b) < index(builtin),
+        Priority::Last => false,
+    }
+}
+
+// A parser for inline syntax that isn't built in, tried whenever one of its trigger
+// characters is reached, before the built in syntax for that character.
+pub trait InlineExtension {
+    fn name(&self) -> &str;
+
+    fn triggers(&self) -> Vec<char>;
+
+    // Called with the text from the trigger character to the end of the input, and the
+    // character before the trigger (to check for word boundaries). Returns the content and
+    // the number of bytes it was made from, `None` leaves the trigger to the built in parser
+    // or as text.
+    fn parse(&self, input: &str, previous: Option<char>) -> Option<(InlineContent, usize)>;
+}
+
+impl fmt::Debug for dyn InlineExtension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InlineExtension({})", self.name())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InlineExtensions {
+    extensions: Vec<Box<dyn InlineExtension>>,
+    triggers: Vec<char>,
+}
+
+impl InlineExtensions {
+    pub fn new() -> InlineExtensions {
+        InlineExtensions::default()
+    }
+
+    // Extensions are tried in the order they were registered.
+    pub fn with_extension(mut self, extension: Box<dyn InlineExtension>) -> InlineExtensions {
+        for c in extension.triggers() {
+            if !self.triggers.contains(&c) {
+                self.triggers.push(c);
+            }
+        }
+
+        self.extensions.push(extension);
+        self
+    }
+
+    pub fn is_trigger(&self, c: char) -> bool {
+        self.triggers.contains(&c)
+    }
+
+    pub fn try_parse(
+        &self,
+        c: char,
+        input: &str,
+        previous: Option<char>,
+    ) -> Option<(InlineContent, usize)> {
+        self.extensions
+            .iter()
+            .filter(|e| e.triggers().contains(&c))
+            .find_map(|e| e.parse(input, previous))
+            .filter(|(_, n)| *n > 0 && input.is_char_boundary(*n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Prefixed;
+
+    impl BlockExtension for Prefixed {
+        fn name(&self) -> &str {
+            "prefixed"
+        }
+
+        fn prefix(&self) -> Option<&str> {
+            Some(":::")
+        }
+
+        fn parse(&self, _: &[&str]) -> Option<(Block, usize)> {
+            None
+        }
+    }
+
+    struct Unclaimed;
+
+    impl BlockExtension for Unclaimed {
+        fn name(&self) -> &str {
+            "unclaimed"
+        }
+
+        fn parse(&self, _: &[&str]) -> Option<(Block, usize)> {
+            None
+        }
+    }
+
+    #[test]
+    fn claims_default_to_the_prefix() {
+        assert!(Prefixed.claims("::: note"));
+        assert!(Prefixed.claims(":::"));
+        assert!(!Prefixed.claims(" ::: note"));
+        assert!(!Prefixed.claims(":: note"));
+
+        assert_eq!(Unclaimed.prefix(), None);
+        assert!(!Unclaimed.claims(""));
+        assert!(!Unclaimed.claims("anything"));
+    }
+
+    #[test]
+    fn builtin_order_without_extensions() {
+        assert_eq!(
+            parser_order(&[]),
+            BUILTIN_ORDER.map(Parser::Builtin).to_vec()
+        );
+    }
+
+    #[test]
+    fn extensions_are_placed_by_priority() {
+        let order = parser_order(&[
+            Priority::Last,
+            Priority::Before(BuiltinParser::Header),
+            Priority::After(BuiltinParser::Code),
+            Priority::First,
+            Priority::After(BuiltinParser::Empty),
+        ]);
+
+        let position = |parser: Parser| order.iter().position(|p| *p == parser).unwrap();
+        let header = position(Parser::Builtin(BuiltinParser::Header));
+        let code = position(Parser::Builtin(BuiltinParser::Code));
+
+        assert_eq!(order.len(), BUILTIN_ORDER.len() + 5);
+        assert_eq!(order[0], Parser::Extension(3));
+        assert_eq!(order[header - 1], Parser::Extension(1));
+        assert_eq!(order[code + 1], Parser::Extension(2));
+        assert_eq!(
+            order[order.len() - 3..],
+            [
+                Parser::Builtin(BuiltinParser::Empty),
+                Parser::Extension(4),
+                Parser::Extension(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_priority_keeps_registration_order() {
+        let order = parser_order(&[
+            Priority::Before(BuiltinParser::Paragraph),
+            Priority::First,
+            Priority::Before(BuiltinParser::Paragraph),
+            Priority::First,
+            Priority::Before(BuiltinParser::Paragraph),
+        ]);
+
+        let paragraph = order
+            .iter()
+            .position(|p| *p == Parser::Builtin(BuiltinParser::Paragraph))
+            .unwrap();
+
+        assert_eq!(order[..2], [Parser::Extension(1), Parser::Extension(3)]);
+        assert_eq!(
+            order[paragraph - 3..paragraph],
+            [
+                Parser::Extension(0),
+                Parser::Extension(2),
+                Parser::Extension(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn runs_before_builtin_parsers() {
+        let paragraph = BuiltinParser::Paragraph;
+
+        assert!(runs_before(Priority::First, paragraph));
+        assert!(runs_before(Priority::Before(paragraph), paragraph));
+        assert!(runs_before(
+            Priority::After(BuiltinParser::Header),
+            paragraph
+        ));
+        assert!(!runs_before(Priority::After(paragraph), paragraph));
+        assert!(!runs_before(
+            Priority::Before(BuiltinParser::Empty),
+            paragraph
+        ));
+        assert!(!runs_before(Priority::Last, paragraph));
+    }
+}
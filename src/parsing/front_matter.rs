@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+// Front matter is a block of `key: value` lines between `---` delimiters at the very start
+// of a document. Only flat string values are supported.
+pub fn split_front_matter(raw: &str) -> (HashMap<String, String>, String) {
+    let mut lines = raw.split('\n');
+
+    match lines.next().map(|l| l.trim_end()) {
+        Some("---") => {}
+        _ => return (HashMap::new(), raw.to_string()),
+    }
+
+    let mut values = HashMap::<String, String>::new();
+    let mut consumed = 1;
+
+    for line in lines.by_ref() {
+        consumed += 1;
+
+        if line.trim_end() == "---" {
+            let rest = raw.split('\n').skip(consumed).collect::<Vec<&str>>();
+            return (values, rest.join("\n"));
+        }
+
+        if let Some((key, value)) = line.split_once(':') {
+            let value = value.trim();
+            let unquoted = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+
+            values.insert(key.trim().to_string(), unquoted.to_string());
+        }
+    }
+
+    // No closing delimiter, so this was not front matter.
+    (HashMap::new(), raw.to_string())
+}
@@ -0,0 +1,832 @@
+use std::collections::HashMap;
+
+use crate::core::documents::{
+    Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, InlineContent, InlineImage, InlineLink,
+    InlineSpan, InlineText, ListBlock, ListItem, ParagraphBlock, Style,
+};
+
+#[derive(Debug)]
+enum HtmlToken {
+    StartTag(String, HashMap<String, String>, bool),
+    EndTag(String),
+    Text(String),
+}
+
+#[derive(Debug)]
+enum HtmlNode {
+    Element(HtmlElement),
+    Text(String),
+}
+
+#[derive(Debug)]
+struct HtmlElement {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<HtmlNode>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct InlineState {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    code: bool,
+}
+
+const VOID_ELEMENTS: [&str; 9] = [
+    "area", "br", "col", "embed", "hr", "img", "input", "link", "meta",
+];
+
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+const SKIPPED_ELEMENTS: [&str; 5] = ["head", "script", "style", "template", "noscript"];
+
+const BLOCK_ELEMENTS: [&str; 35] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+fn is_block_element(name: &str) -> bool {
+    BLOCK_ELEMENTS.contains(&name)
+}
+
+pub fn decode_entities(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let decoded = rest.find(';').filter(|e| *e <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                e if e.starts_with("#x") || e.starts_with("#X") => u32::from_str_radix(&e[2..], 16)
+                    .ok()
+                    .and_then(char::from_u32),
+                e if e.starts_with('#') => e[1..].parse::<u32>().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            c.map(|c| (c, end + 1))
+        });
+
+        match decoded {
+            Some((c, length)) => {
+                out.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn parse_attributes(s: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let chars: Vec<char> = s.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        while i < chars.len() && (chars[i].is_whitespace() || chars[i] == '/') {
+            i += 1;
+        }
+
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '=' && chars[i] != '/' {
+            i += 1;
+        }
+        let name: String = chars[start..i].iter().collect::<String>().to_lowercase();
+
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+
+        let value = match chars.get(i) {
+            Some('=') => {
+                i += 1;
+                while i < chars.len() && chars[i].is_whitespace() {
+                    i += 1;
+                }
+
+                match chars.get(i) {
+                    Some(q) if *q == '"' || *q == '\'' => {
+                        let quote = *q;
+                        let start = i + 1;
+                        i = start;
+                        while i < chars.len() && chars[i] != quote {
+                            i += 1;
+                        }
+                        let v: String = chars[start..i.min(chars.len())].iter().collect();
+                        i += 1;
+                        v
+                    }
+                    _ => {
+                        let start = i;
+                        while i < chars.len() && !chars[i].is_whitespace() {
+                            i += 1;
+                        }
+                        chars[start..i].iter().collect()
+                    }
+                }
+            }
+            _ => "".to_string(),
+        };
+
+        if !name.is_empty() {
+            attributes.insert(name, decode_entities(&value));
+        }
+    }
+
+    attributes
+}
+
+fn tokenize(input: &str) -> Vec<HtmlToken> {
+    let mut tokens = Vec::<HtmlToken>::new();
+    let mut rest = input;
+
+    while !rest.is_empty() {
+        let lt = match rest.find('<') {
+            Some(i) => i,
+            None => {
+                tokens.push(HtmlToken::Text(decode_entities(rest)));
+                break;
+            }
+        };
+
+        if lt > 0 {
+            tokens.push(HtmlToken::Text(decode_entities(&rest[..lt])));
+            rest = &rest[lt..];
+            continue;
+        }
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map(|e| &comment[e + 3..]).unwrap_or("");
+            continue;
+        }
+
+        let end = match rest.find('>') {
+            Some(e) => e,
+            None => {
+                tokens.push(HtmlToken::Text(decode_entities(rest)));
+                break;
+            }
+        };
+
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if tag.starts_with('!') || tag.starts_with('?') {
+            // Doctype and processing instructions.
+            continue;
+        }
+
+        if let Some(name) = tag.strip_prefix('/') {
+            tokens.push(HtmlToken::EndTag(name.trim().to_lowercase()));
+            continue;
+        }
+
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_lowercase();
+
+        if name.is_empty() {
+            tokens.push(HtmlToken::Text(format!("<{}>", tag)));
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/') || VOID_ELEMENTS.contains(&name.as_str());
+        let attributes = parse_attributes(&tag[name_end..]);
+
+        // Script and style content is not markup, skip to the matching end tag.
+        if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+            let close = format!("</{}", name);
+            let end = rest.to_lowercase().find(&close).unwrap_or(rest.len());
+            tokens.push(HtmlToken::StartTag(name.clone(), attributes, false));
+            tokens.push(HtmlToken::Text(rest[..end].to_string()));
+            rest = &rest[end..];
+            continue;
+        }
+
+        tokens.push(HtmlToken::StartTag(name, attributes, self_closing));
+    }
+
+    tokens
+}
+
+// Builds a tree from the tokens, closing elements leniently as browsers do for the common
+// cases (unclosed `p` and `li`, stray end tags).
+fn build_tree(tokens: Vec<HtmlToken>) -> Vec<HtmlNode> {
+    let mut stack: Vec<HtmlElement> = vec![HtmlElement {
+        name: "#root".to_string(),
+        attributes: HashMap::new(),
+        children: vec![],
+    }];
+
+    fn close_top(stack: &mut Vec<HtmlElement>) {
+        if stack.len() > 1 {
+            let e = stack.pop().unwrap();
+            stack
+                .last_mut()
+                .unwrap()
+                .children
+                .push(HtmlNode::Element(e));
+        }
+    }
+
+    for token in tokens {
+        match token {
+            HtmlToken::Text(t) => stack.last_mut().unwrap().children.push(HtmlNode::Text(t)),
+            HtmlToken::StartTag(name, attributes, self_closing) => {
+                let closes_p = is_block_element(&name);
+                let top = stack.last().unwrap().name.clone();
+
+                if (closes_p && top == "p") || (name == "li" && top == "li") {
+                    close_top(&mut stack);
+                }
+
+                let element = HtmlElement {
+                    name,
+                    attributes,
+                    children: vec![],
+                };
+
+                match self_closing {
+                    true => stack
+                        .last_mut()
+                        .unwrap()
+                        .children
+                        .push(HtmlNode::Element(element)),
+                    false => stack.push(element),
+                }
+            }
+            HtmlToken::EndTag(name) => {
+                if stack.iter().skip(1).any(|e| e.name == name) {
+                    while stack.last().map(|e| e.name != name).unwrap_or(false) {
+                        close_top(&mut stack);
+                    }
+                    close_top(&mut stack);
+                }
+            }
+        }
+    }
+
+    while stack.len() > 1 {
+        close_top(&mut stack);
+    }
+
+    stack.pop().unwrap().children
+}
+
+fn text_content(nodes: &[HtmlNode]) -> String {
+    nodes
+        .iter()
+        .map(|n| match n {
+            HtmlNode::Text(t) => t.clone(),
+            HtmlNode::Element(e) if e.name == "br" => "\n".to_string(),
+            HtmlNode::Element(e) => text_content(&e.children),
+        })
+        .collect()
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    let mut out = String::new();
+    let mut space = false;
+
+    for c in s.chars() {
+        match c.is_whitespace() && c != '\u{a0}' {
+            true => space = true,
+            false => {
+                if space {
+                    out.push(' ');
+                    space = false;
+                }
+                out.push(c);
+            }
+        }
+    }
+
+    if space {
+        out.push(' ');
+    }
+
+    out
+}
+
+// Text here has its whitespace collapsed already, so only the join can double a space.
+fn push_text(content: &mut Vec<InlineContent>, text: String) {
+    match content.last_mut() {
+        Some(InlineContent::Text(t)) if t.content.ends_with(' ') => {
+            t.content.push_str(text.strip_prefix(' ').unwrap_or(&text))
+        }
+        Some(InlineContent::Text(t)) => t.content.push_str(&text),
+        _ => content.push(InlineContent::Text(InlineText::new(text))),
+    }
+}
+
+fn push_styled(content: &mut Vec<InlineContent>, text: String, state: InlineState) {
+    let classes: Vec<String> = [
+        (state.bold, "b"),
+        (state.italic, "i"),
+        (state.strike, "s"),
+        (state.code, "code"),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, c)| c.to_string())
+    .collect();
+
+    if classes.is_empty() {
+        push_text(content, text);
+        return;
+    }
+
+    // Surrounding whitespace stays outside of the span.
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        push_text(content, text);
+        return;
+    }
+
+    if text.starts_with(char::is_whitespace) {
+        push_text(content, " ".to_string());
+    }
+
+    content.push(InlineContent::Span(InlineSpan::from_text(
+        Style::create_ref(classes),
+        trimmed.to_string(),
+    )));
+
+    if text.ends_with(char::is_whitespace) {
+        push_text(content, " ".to_string());
+    }
+}
+
+fn collect_inline(nodes: Vec<HtmlNode>, state: InlineState, content: &mut Vec<InlineContent>) {
+    for node in nodes {
+        match node {
+            HtmlNode::Text(t) => match state.code {
+                true => push_styled(content, t, state),
+                false => push_styled(content, collapse_whitespace(&t), state),
+            },
+            HtmlNode::Element(e) => match e.name.as_str() {
+                "strong" | "b" => collect_inline(
+                    e.children,
+                    InlineState {
+                        bold: true,
+                        ..state
+                    },
+                    content,
+                ),
+                "em" | "i" => collect_inline(
+                    e.children,
+                    InlineState {
+                        italic: true,
+                        ..state
+                    },
+                    content,
+                ),
+                "del" | "s" | "strike" => collect_inline(
+                    e.children,
+                    InlineState {
+                        strike: true,
+                        ..state
+                    },
+                    content,
+                ),
+                "code" | "kbd" | "samp" | "tt" => collect_inline(
+                    e.children,
+                    InlineState {
+                        code: true,
+                        ..state
+                    },
+                    content,
+                ),
+                "a" => match e.attributes.get("href").cloned() {
+                    Some(href) => push_link(content, href, e, state),
+                    None => collect_inline(e.children, state, content),
+                },
+                "img" => content.push(InlineContent::Image(create_inline_image(&e))),
+                "br" => push_text(content, " ".to_string()),
+                n if SKIPPED_ELEMENTS.contains(&n) => {}
+                _ => collect_inline(e.children, state, content),
+            },
+        }
+    }
+}
+
+// Link text keeps its markup, surrounding whitespace stays outside of the link as for spans.
+fn push_link(content: &mut Vec<InlineContent>, href: String, e: HtmlElement, state: InlineState) {
+    let text = collapse_whitespace(&text_content(&e.children));
+    let title = e.attributes.get("title").cloned().unwrap_or_default();
+
+    let mut children = Vec::<InlineContent>::new();
+    collect_inline(e.children, state, &mut children);
+    let children = normalise_inline(children);
+
+    if children.is_empty() {
+        return;
+    }
+
+    if text.starts_with(' ') {
+        push_text(content, " ".to_string());
+    }
+
+    content.push(InlineContent::Link(
+        InlineLink::new(Style::Default, href, children).with_title(title),
+    ));
+
+    if text.ends_with(' ') && !text.trim().is_empty() {
+        push_text(content, " ".to_string());
+    }
+}
+
+fn normalise_inline(content: Vec<InlineContent>) -> Vec<InlineContent> {
+    let mut out = Vec::<InlineContent>::new();
+
+    // Remove doubled spaces between items and trim the ends.
+    for c in content {
+        let previous_space = out
+            .last()
+            .map(|p: &InlineContent| p.text().ends_with(' '))
+            .unwrap_or(true);
+
+        match c {
+            InlineContent::Text(mut t) => {
+                if previous_space {
+                    t.content = t.content.trim_start().to_string();
+                }
+                if !t.content.is_empty() {
+                    push_text(&mut out, t.content);
+                }
+            }
+            other => out.push(other),
+        }
+    }
+
+    if let Some(InlineContent::Text(t)) = out.last_mut() {
+        t.content = t.content.trim_end().to_string();
+        if t.content.is_empty() {
+            out.pop();
+        }
+    }
+
+    out
+}
+
+fn inline_content(nodes: Vec<HtmlNode>) -> Vec<InlineContent> {
+    let mut content = Vec::<InlineContent>::new();
+
+    collect_inline(nodes, InlineState::default(), &mut content);
+
+    normalise_inline(content)
+}
+
+fn create_inline_image(e: &HtmlElement) -> InlineImage {
+    let get = |k: &str| e.attributes.get(k).cloned().unwrap_or_default();
+
+    InlineImage::new(Style::Default, get("src"), get("title"), get("alt"))
+}
+
+// An image with nothing else around it is a block image, as `![alt](src)` on its own line.
+fn lone_image(nodes: &[HtmlNode]) -> Option<&HtmlElement> {
+    let mut content = nodes.iter().filter(|n| match n {
+        HtmlNode::Text(t) => !t.trim().is_empty(),
+        HtmlNode::Element(e) => e.name != "br",
+    });
+
+    match (content.next(), content.next()) {
+        (Some(HtmlNode::Element(e)), None) if e.name == "img" => Some(e),
+        _ => None,
+    }
+}
+
+fn create_image_block(e: &HtmlElement) -> ImageBlock {
+    let get = |k: &str| e.attributes.get(k).cloned();
+
+    ImageBlock::new(
+        Style::Default,
+        get("src").unwrap_or_default(),
+        get("title").unwrap_or_default(),
+        get("alt").unwrap_or_default(),
+        get("height"),
+        get("width"),
+    )
+}
+
+fn code_language(e: &HtmlElement) -> Option<String> {
+    e.attributes.get("class").and_then(|c| {
+        c.split_whitespace().find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+                .map(|l| l.to_string())
+        })
+    })
+}
+
+fn create_code_block(e: HtmlElement) -> CodeBlock {
+    // The language is usually on the inner `code` element, but sometimes on the `pre`.
+    let inner_language = e.children.iter().find_map(|c| match c {
+        HtmlNode::Element(ce) if ce.name == "code" => code_language(ce),
+        _ => None,
+    });
+
+    let language = inner_language.or_else(|| code_language(&e));
+    let text = text_content(&e.children);
+    let text = text.strip_prefix('\n').unwrap_or(&text);
+
+    CodeBlock::new(
+        Style::Default,
+        text.strip_suffix('\n').unwrap_or(text).to_string(),
+        language,
+    )
+}
+
+fn flush_paragraph(pending: &mut Vec<HtmlNode>, blocks: &mut Vec<Block>) {
+    if let Some(e) = lone_image(pending) {
+        blocks.push(Block::image(create_image_block(e)));
+        pending.clear();
+        return;
+    }
+
+    let content = inline_content(std::mem::take(pending));
+
+    if !content.is_empty() {
+        blocks.push(Block::paragraph(ParagraphBlock::new(
+            Style::Default,
+            content,
+        )));
+    }
+}
+
+fn list_items(e: HtmlElement, items: &mut Vec<ListItem>) {
+    for child in e.children {
+        let li = match child {
+            HtmlNode::Element(li) if li.name == "li" => li,
+            _ => continue,
+        };
+
+        // The document model has no nested lists, so nested items are flattened in order.
+        let (nested, inline): (Vec<HtmlNode>, Vec<HtmlNode>) =
+            li.children.into_iter().partition(|n| match n {
+                HtmlNode::Element(ne) => ne.name == "ul" || ne.name == "ol",
+                _ => false,
+            });
+
+        items.push(ListItem::new(Style::Default, inline_content(inline)));
+
+        for n in nested {
+            if let HtmlNode::Element(ne) = n {
+                list_items(ne, items);
+            }
+        }
+    }
+}
+
+fn convert_nodes(nodes: Vec<HtmlNode>, blocks: &mut Vec<Block>) {
+    let mut pending = Vec::<HtmlNode>::new();
+
+    for node in nodes {
+        let e = match node {
+            HtmlNode::Element(e) if is_block_element(&e.name) || e.name == "img" => e,
+            HtmlNode::Element(e) if SKIPPED_ELEMENTS.contains(&e.name.as_str()) => continue,
+            other => {
+                pending.push(other);
+                continue;
+            }
+        };
+
+        flush_paragraph(&mut pending, blocks);
+
+        let level = match e.name.as_str() {
+            "h1" => Some(HeaderLevel::H1),
+            "h2" => Some(HeaderLevel::H2),
+            "h3" => Some(HeaderLevel::H3),
+            "h4" => Some(HeaderLevel::H4),
+            "h5" => Some(HeaderLevel::H5),
+            "h6" => Some(HeaderLevel::H6),
+            _ => None,
+        };
+
+        if let Some(level) = level {
+            // Same as `processing::create_header_block`, only h1 and h2 are indexed.
+            let indexed = matches!(level, HeaderLevel::H1 | HeaderLevel::H2);
+            let content = inline_content(e.children);
+            blocks.push(Block::header(HeaderBlock::new(
+                Style::Default,
+                level,
+                content,
+                indexed,
+            )));
+            continue;
+        }
+
+        match e.name.as_str() {
+            "p" => {
+                pending = e.children;
+                flush_paragraph(&mut pending, blocks);
+            }
+            "pre" => blocks.push(Block::code(create_code_block(e))),
+            "img" => blocks.push(Block::image(create_image_block(&e))),
+            "ul" | "ol" => {
+                let ordered = e.name == "ol";
+                let mut items = Vec::<ListItem>::new();
+                list_items(e, &mut items);
+                blocks.push(Block::list(ListBlock::new(Style::Default, ordered, items)));
+            }
+            "hr" => {}
+            _ => convert_nodes(e.children, blocks),
+        }
+    }
+
+    flush_paragraph(&mut pending, blocks);
+}
+
+pub fn parse_html(input: &str) -> Vec<Block> {
+    let mut blocks = Vec::<Block>::new();
+
+    convert_nodes(build_tree(tokenize(input)), &mut blocks);
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debug(html: &str) -> String {
+        format!("{:?}", parse_html(html))
+    }
+
+    fn paragraph(content: Vec<InlineContent>) -> String {
+        format!(
+            "{:?}",
+            vec![Block::paragraph(ParagraphBlock::new(
+                Style::Default,
+                content
+            ))]
+        )
+    }
+
+    fn text(s: &str) -> InlineContent {
+        InlineContent::Text(InlineText::new(s.to_string()))
+    }
+
+    fn span(classes: &[&str], s: &str) -> InlineContent {
+        InlineContent::Span(InlineSpan::from_text(
+            Style::create_ref(classes.iter().map(|c| c.to_string()).collect()),
+            s.to_string(),
+        ))
+    }
+
+    #[test]
+    fn links_keep_their_markup() {
+        assert_eq!(
+            debug("<p>See <a href='u' title='T'> the <em>new</em> <strong><code>api</code></strong> </a>now</p>"),
+            paragraph(vec![
+                text("See "),
+                InlineContent::Link(
+                    InlineLink::new(
+                        Style::Default,
+                        "u".to_string(),
+                        vec![text("the "), span(&["i"], "new"), text(" "), span(&["b", "code"], "api")],
+                    )
+                    .with_title("T".to_string())
+                ),
+                text(" now"),
+            ])
+        );
+    }
+
+    #[test]
+    fn links_without_a_target_are_text() {
+        assert_eq!(
+            debug("<p><a name='x'>plain <b>bold</b></a><a href='u'> </a></p>"),
+            paragraph(vec![text("plain "), span(&["b"], "bold")])
+        );
+    }
+
+    #[test]
+    fn images_in_text_stay_inline() {
+        let image = |source: &str, alt: &str| {
+            InlineContent::Image(InlineImage::new(
+                Style::Default,
+                source.to_string(),
+                "".to_string(),
+                alt.to_string(),
+            ))
+        };
+
+        assert_eq!(
+            debug("<p>An <img src='a.png' alt='A'> icon</p>"),
+            paragraph(vec![text("An "), image("a.png", "A"), text(" icon")])
+        );
+        assert_eq!(
+            debug("<p><a href='u'><img src='b.png' alt='B'></a></p>"),
+            paragraph(vec![InlineContent::Link(InlineLink::new(
+                Style::Default,
+                "u".to_string(),
+                vec![image("b.png", "B")],
+            ))])
+        );
+        assert_eq!(
+            format!("{:?}", parse_html("<h2>Logo <img src='l.png'></h2>")),
+            format!(
+                "{:?}",
+                vec![Block::header(HeaderBlock::new(
+                    Style::Default,
+                    HeaderLevel::H2,
+                    vec![text("Logo "), image("l.png", "")],
+                    true,
+                ))]
+            )
+        );
+    }
+
+    #[test]
+    fn images_on_their_own_are_blocks() {
+        let block = |width: Option<&str>| {
+            format!(
+                "{:?}",
+                vec![Block::image(ImageBlock::new(
+                    Style::Default,
+                    "a.png".to_string(),
+                    "T".to_string(),
+                    "A".to_string(),
+                    None,
+                    width.map(|w| w.to_string()),
+                ))]
+            )
+        };
+
+        assert_eq!(
+            debug("<p>\n  <img src='a.png' alt='A' title='T' width='50%'>\n</p>"),
+            block(Some("50%"))
+        );
+        assert_eq!(debug("<img src='a.png' alt='A' title='T'>"), block(None));
+    }
+
+    #[test]
+    fn joined_text_has_single_spaces() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                parse_html("<p>one <br> two <span> three</span></p>")
+            ),
+            format!(
+                "{:?}",
+                vec![Block::paragraph(ParagraphBlock::new(
+                    Style::Default,
+                    vec![InlineContent::Text(InlineText::new(
+                        "one two three".to_string()
+                    ))],
+                ))]
+            )
+        );
+    }
+}
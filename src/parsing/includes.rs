@@ -0,0 +1,330 @@
+use std::{fs, path::Path};
+
+use crate::core::documents::{Block, CodeBlock, CodeInclude, CustomBlock};
+
+use super::info_string;
+
+// Language names for included files, extensions not listed here are used as they are.
+const LANGUAGES: [(&str, &str); 16] = [
+    ("rs", "rust"),
+    ("py", "python"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("ts", "typescript"),
+    ("sh", "shell"),
+    ("bash", "shell"),
+    ("htm", "html"),
+    ("md", "markdown"),
+    ("yml", "yaml"),
+    ("h", "c"),
+    ("hpp", "cpp"),
+    ("cc", "cpp"),
+    ("rb", "ruby"),
+    ("kt", "kotlin"),
+    ("cs", "csharp"),
+];
+
+pub fn infer_language(path: &str) -> Option<String> {
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+
+    let language = LANGUAGES
+        .iter()
+        .find(|(e, _)| *e == extension)
+        .map(|(_, l)| l.to_string())
+        .unwrap_or(extension);
+
+    Some(language)
+}
+
+// The word following `marker` on the line, ignoring a trailing comment close.
+fn marker_name<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let rest = &line[line.find(marker)? + marker.len()..];
+
+    Some(
+        rest.split_whitespace()
+            .next()
+            .unwrap_or("")
+            .trim_end_matches("*/")
+            .trim_end_matches("-->"),
+    )
+}
+
+// Regions are marked as in mdBook, `ANCHOR: name` to `ANCHOR_END: name`, or with
+// `#region name` to `#endregion`, in a comment of the included file's language.
+fn is_region_start(line: &str, name: &str) -> bool {
+    marker_name(line, "ANCHOR:") == Some(name) || marker_name(line, "#region") == Some(name)
+}
+
+// The line ending the region started at `start`. A `#endregion` without a name ends the
+// innermost open `#region`, so it only ends this region if it was started with `#region`
+// and every region nested in it has been closed.
+fn region_end(lines: &[&str], start: usize, name: &str) -> Option<usize> {
+    let bare_end = marker_name(lines[start], "#region") == Some(name);
+    let mut depth: usize = 0;
+
+    for (i, line) in lines.iter().enumerate().skip(start + 1) {
+        if marker_name(line, "ANCHOR_END:") == Some(name) {
+            return Some(i);
+        }
+
+        match (
+            marker_name(line, "#region"),
+            marker_name(line, "#endregion"),
+        ) {
+            (Some(_), _) => depth += 1,
+            (None, Some(n)) if n == name || (n.is_empty() && bare_end && depth == 0) => {
+                return Some(i)
+            }
+            (None, Some(_)) => depth = depth.saturating_sub(1),
+            (None, None) => {}
+        }
+    }
+
+    None
+}
+
+fn is_marker(line: &str) -> bool {
+    ["ANCHOR:", "ANCHOR_END:", "#region", "#endregion"]
+        .iter()
+        .any(|m| line.contains(m))
+}
+
+fn dedent(lines: Vec<&str>) -> Vec<String> {
+    let margin = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    lines
+        .into_iter()
+        .map(|l| l.get(margin..).unwrap_or("").trim_end().to_string())
+        .collect()
+}
+
+fn extract_region(text: &str, name: &str, path: &str) -> Result<String, String> {
+    let lines: Vec<&str> = text.lines().collect();
+
+    let start = lines
+        .iter()
+        .position(|l| is_region_start(l, name))
+        .ok_or_else(|| format!("Region `{}` not found in `{}`", name, path))?;
+
+    let end = region_end(&lines, start, name)
+        .ok_or_else(|| format!("Region `{}` is not closed in `{}`", name, path))?;
+
+    // Markers of other regions nested in this one are left out.
+    let content = lines[start + 1..end]
+        .iter()
+        .filter(|l| !is_marker(l))
+        .copied()
+        .collect();
+
+    Ok(dedent(content).join("\n"))
+}
+
+fn extract_lines(text: &str, ranges: &[(usize, usize)], path: &str) -> Result<String, String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut selected = Vec::<&str>::new();
+
+    for (start, end) in ranges {
+        if *start == 0 || *end > lines.len() {
+            return Err(format!(
+                "Lines {}-{} are out of range for `{}` ({} lines)",
+                start,
+                end,
+                path,
+                lines.len()
+            ));
+        }
+
+        selected.extend(&lines[start - 1..*end]);
+    }
+
+    Ok(selected.join("\n"))
+}
+
+fn read_include(include: &CodeInclude, base_path: &Path) -> Result<String, String> {
+    let path = base_path.join(&include.path);
+
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("Could not read included file `{}`: {}", path.display(), e))?;
+
+    match (&include.region, &include.lines) {
+        (Some(region), _) => extract_region(&text, region, &include.path),
+        (None, Some(lines)) => {
+            let ranges = info_string::parse_line_ranges(lines)
+                .map_err(|e| format!("{} in the include of `{}`", e, include.path))?;
+            extract_lines(&text, &ranges, &include.path)
+        }
+        (None, None) => Ok(text.trim_end_matches('\n').to_string()),
+    }
+}
+
+fn resolve_code_block(block: CodeBlock, base_path: &Path) -> Result<CodeBlock, String> {
+    let include = match &block.include {
+        Some(i) => i.clone(),
+        None => return Ok(block),
+    };
+
+    let content = read_include(&include, base_path)?;
+
+    let language = match block.language.as_deref() {
+        None | Some("") => infer_language(&include.path).or(block.language),
+        Some(_) => block.language,
+    };
+
+    Ok(CodeBlock {
+        content,
+        language,
+        include: None,
+        ..block
+    })
+}
+
+// Reads the files included into code blocks, relative to `base_path` (usually the
+// directory of the document), including code blocks inside extension blocks. A missing
+// file or region is an error.
+pub fn resolve_includes(blocks: Vec<Block>, base_path: &Path) -> Result<Vec<Block>, String> {
+    blocks
+        .into_iter()
+        .map(|b| match b {
+            Block::Code(c) => resolve_code_block(c, base_path).map(Block::code),
+            Block::Custom(c) => Ok(Block::custom(CustomBlock {
+                content: resolve_includes(c.content, base_path)?,
+                ..c
+            })),
+            other => Ok(other),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::Style;
+
+    const SOURCE: &str = "use std::fs;
+
+// ANCHOR: main
+fn main() {
+    // #region body
+    run();
+    // #endregion
+}
+// ANCHOR_END: main
+";
+
+    fn base_path() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("rdom-includes-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.rs"), SOURCE).unwrap();
+        dir
+    }
+
+    fn include(lines: Option<&str>, region: Option<&str>) -> Block {
+        Block::code(
+            CodeBlock::new(Style::Default, "".to_string(), None).with_include(CodeInclude::new(
+                "main.rs".to_string(),
+                lines.map(|l| l.to_string()),
+                region.map(|r| r.to_string()),
+            )),
+        )
+    }
+
+    fn resolve(block: Block) -> Result<CodeBlock, String> {
+        match resolve_includes(vec![block], &base_path())?.pop() {
+            Some(Block::Code(c)) => Ok(c),
+            other => panic!("Expected a code block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn whole_files() {
+        let code = resolve(include(None, None)).unwrap();
+
+        assert_eq!(code.content, SOURCE.trim_end());
+        assert_eq!(code.language.as_deref(), Some("rust"));
+        assert!(code.include.is_none());
+    }
+
+    #[test]
+    fn regions() {
+        assert_eq!(
+            resolve(include(None, Some("main"))).unwrap().content,
+            "fn main() {\n    run();\n}"
+        );
+        assert_eq!(
+            resolve(include(None, Some("body"))).unwrap().content,
+            "run();"
+        );
+        assert_eq!(
+            resolve(include(None, Some("other"))).unwrap_err(),
+            "Region `other` not found in `main.rs`"
+        );
+        assert_eq!(
+            extract_region(
+                "# #region a\n# #region b\nx\n# #endregion\ny\n# #endregion\nz",
+                "a",
+                "a.py"
+            )
+            .unwrap(),
+            "x\ny"
+        );
+        assert_eq!(
+            extract_region("// ANCHOR: open\nx", "open", "a.rs").unwrap_err(),
+            "Region `open` is not closed in `a.rs`"
+        );
+    }
+
+    #[test]
+    fn line_ranges() {
+        assert_eq!(
+            resolve(include(Some("1,4"), None)).unwrap().content,
+            "use std::fs;\nfn main() {"
+        );
+        assert_eq!(
+            resolve(include(Some("8-10"), None)).unwrap_err(),
+            "Lines 8-10 are out of range for `main.rs` (9 lines)"
+        );
+        assert!(resolve(include(Some("0-1"), None)).is_err());
+        assert_eq!(
+            resolve(include(Some("40-10"), None)).unwrap_err(),
+            "Invalid line range `40-10` in the include of `main.rs`"
+        );
+        assert_eq!(
+            resolve(include(Some("1,abc"), None)).unwrap_err(),
+            "Invalid line range `abc` in the include of `main.rs`"
+        );
+    }
+
+    #[test]
+    fn missing_files_are_errors() {
+        let block = Block::code(
+            CodeBlock::new(Style::Default, "".to_string(), Some("text".to_string()))
+                .with_include(CodeInclude::new("missing.rs".to_string(), None, None)),
+        );
+
+        assert!(resolve(block)
+            .unwrap_err()
+            .starts_with("Could not read included file"));
+    }
+
+    #[test]
+    fn includes_in_extension_blocks() {
+        let custom = Block::custom(CustomBlock::new(
+            "tabs".to_string(),
+            vec![include(None, Some("body"))],
+            "".to_string(),
+        ));
+
+        match resolve_includes(vec![custom], &base_path()).unwrap().pop() {
+            Some(Block::Custom(c)) => match &c.content[..] {
+                [Block::Code(code)] => assert_eq!(code.content, "run();"),
+                other => panic!("Expected a code block, got {:?}", other),
+            },
+            other => panic!("Expected an extension block, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// Code fence info strings, e.g. ```` ```rust title="main.rs" {3,5-7} linenos ````.
+
+#[derive(Debug, Default)]
+pub struct InfoString {
+    pub language: Option<String>,
+    pub attributes: Vec<(String, Option<String>)>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl InfoString {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_deref().unwrap_or(""))
+    }
+
+    // The first line number, from `linenos` (counting from 1 without a value) or `start`.
+    pub fn line_numbers(&self) -> Option<Result<usize, String>> {
+        self.get("linenos")
+            .or_else(|| self.get("start"))
+            .map(|v| match v {
+                "" => Ok(1),
+                v => v
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid first line number `{}`", v)),
+            })
+    }
+}
+
+// Splits on whitespace outside of quotes and braces, a backslash in quotes escapes the next
+// character.
+fn split_words(info: &str) -> Vec<String> {
+    let mut words = Vec::<String>::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut braces = false;
+    let mut chars = info.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote, braces) {
+            ('\\', Some(_), _) => current.extend(chars.next()),
+            (c, None, false) if c.is_whitespace() => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            ('"', None, false) | ('\'', None, false) => quote = Some(c),
+            (c, Some(q), _) if c == q => quote = None,
+            ('{', None, _) => {
+                braces = true;
+                current.push(c);
+            }
+            ('}', None, _) => {
+                braces = false;
+                current.push(c);
+            }
+            (c, _, _) => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        words.push(current);
+    }
+
+    words
+}
+
+fn parse_range(part: &str) -> Option<(usize, usize)> {
+    let part = part.trim();
+    match part.split_once('-') {
+        Some((start, end)) => match (start.trim().parse(), end.trim().parse()) {
+            (Ok(start), Ok(end)) if start <= end => Some((start, end)),
+            _ => None,
+        },
+        None => part.parse().ok().map(|n| (n, n)),
+    }
+}
+
+// Parses comma separated line numbers and ranges, `3,5-7`. Invalid parts are ignored, which
+// is only right for highlights.
+pub fn parse_ranges(s: &str) -> Vec<(usize, usize)> {
+    s.split(',').filter_map(parse_range).collect()
+}
+
+// Like `parse_ranges`, but any part that is not a line number or range is an error.
+pub fn parse_line_ranges(s: &str) -> Result<Vec<(usize, usize)>, String> {
+    s.split(',')
+        .map(|part| {
+            parse_range(part).ok_or_else(|| format!("Invalid line range `{}`", part.trim()))
+        })
+        .collect()
+}
+
+pub fn parse(info: &str) -> InfoString {
+    let mut result = InfoString::default();
+
+    for (i, word) in split_words(info).into_iter().enumerate() {
+        match (
+            word.strip_prefix('{').and_then(|w| w.strip_suffix('}')),
+            word.split_once('='),
+        ) {
+            (Some(ranges), _) => result.ranges.extend(parse_ranges(ranges)),
+            (None, Some((name, value))) => result
+                .attributes
+                .push((name.to_string(), Some(value.to_string()))),
+            // The language is the first word, anything after it is a flag.
+            (None, None) if i == 0 && !word.is_empty() => result.language = Some(word),
+            (None, None) => result.attributes.push((word, None)),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes() {
+        let info = parse("rust title=\"say \\\"hi\\\".rs\" {3,5-7} linenos");
+
+        assert_eq!(info.language.as_deref(), Some("rust"));
+        assert_eq!(info.get("title"), Some("say \"hi\".rs"));
+        assert_eq!(info.get("linenos"), Some(""));
+        assert_eq!(info.get("region"), None);
+        assert_eq!(info.ranges, vec![(3, 3), (5, 7)]);
+        assert_eq!(info.line_numbers(), Some(Ok(1)));
+    }
+
+    #[test]
+    fn bare_fences_have_no_language() {
+        assert_eq!(parse("").language, None);
+        assert_eq!(parse("   ").language, None);
+        assert_eq!(parse("{1}").language, None);
+        assert_eq!(parse("{1}").ranges, vec![(1, 1)]);
+    }
+
+    #[test]
+    fn line_numbers() {
+        assert_eq!(parse("rust").line_numbers(), None);
+        assert_eq!(parse("rust linenos=5").line_numbers(), Some(Ok(5)));
+        assert_eq!(parse("rust start=0").line_numbers(), Some(Ok(0)));
+        assert_eq!(
+            parse("rust linenos=abc").line_numbers(),
+            Some(Err("Invalid first line number `abc`".to_string()))
+        );
+        assert_eq!(
+            parse("rust start=-2").line_numbers(),
+            Some(Err("Invalid first line number `-2`".to_string()))
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(
+            parse_ranges("3, 5-7,x,9-8,10"),
+            vec![(3, 3), (5, 7), (10, 10)]
+        );
+        assert_eq!(parse_ranges(""), vec![]);
+    }
+
+    #[test]
+    fn strict_ranges() {
+        assert_eq!(parse_line_ranges("3, 5-7"), Ok(vec![(3, 3), (5, 7)]));
+        assert_eq!(
+            parse_line_ranges("1,40-10"),
+            Err("Invalid line range `40-10`".to_string())
+        );
+        assert_eq!(
+            parse_line_ranges("abc"),
+            Err("Invalid line range `abc`".to_string())
+        );
+        assert_eq!(
+            parse_line_ranges(""),
+            Err("Invalid line range ``".to_string())
+        );
+    }
+}
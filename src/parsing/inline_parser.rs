@@ -1,181 +1,832 @@
-use crate::core::documents::{InlineContent, InlineLink, InlineSpan, InlineText, Style};
+use std::collections::{HashMap, HashSet};
 
-fn in_bounds(s: &String, i: usize) -> bool {
-    i < s.len()
+use crate::core::documents::{
+    InlineContent, InlineImage, InlineLink, InlineSpan, InlineText, Style,
+};
+
+use super::emphasis::{self, DelimiterRun, Node};
+use super::extensions::InlineExtensions;
+use super::references::LinkReferences;
+
+// Positions in the input are byte offsets, and are only ever moved by whole characters so
+// slicing at them can't split a character.
+
+fn look_ahead(s: &str, i: usize) -> Option<char> {
+    s.get(i..)?.chars().next()
 }
 
-fn get_char(s: &String, i: usize) -> Option<char> {
-    s.chars().nth(i)
+fn look_back(s: &str, i: usize) -> Option<char> {
+    s.get(..i)?.chars().next_back()
 }
 
-fn look_ahead(s: &String, i: usize) -> Option<char> {
-    get_char(s, i)
+// The offset just past the character at `i`.
+fn next_char(s: &str, i: usize) -> usize {
+    i + look_ahead(s, i).map(|c| c.len_utf8()).unwrap_or(1)
 }
 
-fn look_back(s: &String, i: usize) -> Option<char> {
-    get_char(s, i - 1)
+// The first character boundary at or after `i`.
+fn char_boundary(s: &str, i: usize) -> usize {
+    (i..s.len())
+        .find(|i| s.is_char_boundary(*i))
+        .unwrap_or(s.len())
 }
 
 fn is_control_char(c: char) -> bool {
-    c == '_' || c == '*' || c == '`' || c == '['
+    c == '_' || c == '*' || c == '~' || c == '`' || c == '[' || c == '!' || c == '\\'
 }
 
-fn compare_look_ahead(s: &String, pattern: &str, i: usize) -> bool {
-    if in_bounds(s, i + pattern.len() - 1) {
-        let slice = &s[i..(i + pattern.len())];
+// Any ASCII punctuation can be escaped with a backslash, a backslash before anything else is
+// text.
+fn is_escapable(c: char) -> bool {
+    c.is_ascii_punctuation()
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
 
-        slice == pattern
-    } else {
-        false
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(n)) if is_escapable(*n) => out.extend(chars.next()),
+            _ => out.push(c),
+        }
     }
+
+    out
+}
+
+fn backtick_run(s: &str, i: usize) -> usize {
+    s[i..].chars().take_while(|c| *c == '`').count()
 }
 
-pub fn read_until_char(s: &String, c: char, inclusive: bool, from: usize) -> (String, usize) {
+// The offset of the next run of exactly `length` backticks from `from`.
+fn find_backtick_run(s: &str, length: usize, from: usize) -> Option<usize> {
     let mut i = from;
 
-    loop {
-        match s.chars().nth(i) {
-            Some(fc) if fc == c => {
-                break;
-            }
-            Some(_) => {
-                i = i + 1;
-            }
-            None => {
-                i = s.len() - 1;
-                break;
-            }
+    while let Some(n) = s[i..].find('`') {
+        let run = backtick_run(s, i + n);
+
+        match run == length {
+            true => return Some(i + n),
+            false => i += n + run,
         }
     }
 
-    (s[from..i].to_string(), if inclusive { i + 1 } else { i })
+    None
 }
 
-fn read_until_control_char(s: &String, from: usize) -> (String, usize) {
-    let mut i = from;
+// The offsets of matching `[` and `]` pairs, in one pass so unmatched brackets don't make
+// parsing quadratic. Escaped brackets and those in code spans are skipped.
+fn match_brackets(s: &str) -> HashMap<usize, usize> {
+    let mut pairs = HashMap::<usize, usize>::new();
+    let mut open = Vec::<usize>::new();
+    let mut unclosed_code = HashSet::<usize>::new();
+    let mut i = 0;
 
-    loop {
-        match s.chars().nth(i) {
-            Some(fc) if is_control_char(fc) => {
-                break;
+    while let Some(c) = look_ahead(s, i) {
+        match c {
+            '\\' => i = next_char(s, next_char(s, i)).min(s.len()),
+            '`' => {
+                let run = backtick_run(s, i);
+
+                let end = match unclosed_code.contains(&run) {
+                    true => None,
+                    false => find_backtick_run(s, run, i + run),
+                };
+
+                i = match end {
+                    Some(end) => end + run,
+                    None => {
+                        unclosed_code.insert(run);
+                        i + run
+                    }
+                };
             }
-            Some(_) => {
-                i = i + 1;
+            '[' => {
+                open.push(i);
+                i += 1;
             }
-            None => {
-                i = s.len();
-                break;
+            ']' => {
+                if let Some(o) = open.pop() {
+                    pairs.insert(o, i);
+                }
+                i += 1;
             }
+            _ => i = next_char(s, i),
         }
     }
 
-    (s[from..(i)].to_string(), i)
+    pairs
 }
 
-fn read_until_string(s: &String, pattern: &str, inclusive: bool, from: usize) -> (String, usize) {
-    let mut i = from;
+// The offset of the first `c` that isn't escaped.
+fn find_unescaped(s: &str, c: char) -> Option<usize> {
+    let mut escaped = false;
+
+    for (i, d) in s.char_indices() {
+        match (escaped, d) {
+            (false, d) if d == c => return Some(i),
+            (false, '\\') => escaped = true,
+            _ => escaped = false,
+        }
+    }
 
-    //let mut chars
+    None
+}
 
-    loop {
-        match (in_bounds(s, i), compare_look_ahead(&s, pattern, i)) {
-            (true, true) => {
-                break;
+// The URL and title of a destination, the title is optional and in double quotes. URLs with
+// spaces are written in angle brackets, as in `<my file.png> "Title"`.
+pub fn split_title(s: &str) -> (String, String) {
+    let angled = s
+        .strip_prefix('<')
+        .and_then(|r| find_unescaped(r, '>').map(|i| (&r[..i], r[i + 1..].trim())));
+
+    let (url, rest) = match angled {
+        Some(parts) => parts,
+        None => s
+            .split_once(char::is_whitespace)
+            .map(|(url, rest)| (url, rest.trim()))
+            .unwrap_or((s, "")),
+    };
+
+    match rest {
+        "" => (unescape(url), "".to_string()),
+        t if t.len() >= 2 && t.starts_with('"') && t.ends_with('"') => {
+            (unescape(url), unescape(&t[1..t.len() - 1]))
+        }
+        _ => (unescape(s), "".to_string()),
+    }
+}
+
+// The URL and title of a link in `(url "title")` at `from`, and the offset after it.
+// Parentheses inside are balanced and escapes are removed.
+fn read_destination(s: &str, from: usize) -> Option<(String, String, usize)> {
+    if look_ahead(s, from) != Some('(') {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut i = from + 1;
+
+    while let Some(c) = look_ahead(s, i) {
+        match c {
+            '\\' => i = next_char(s, next_char(s, i)).min(s.len()),
+            '(' => {
+                depth += 1;
+                i += 1;
             }
-            (true, false) => {
-                i = i + 1;
+            ')' if depth == 0 => {
+                let (url, title) = split_title(s[from + 1..i].trim());
+                return Some((url, title, i + 1));
             }
-            (false, _) => {
-                i = s.len();
-                break;
+            ')' => {
+                depth -= 1;
+                i += 1;
             }
+            _ => i = next_char(s, i),
+        }
+    }
+
+    None
+}
+
+// An image in `![alt](src "title")` at `i`, the alternative text is the plain text of the
+// content in the brackets.
+fn read_image_at(
+    s: &str,
+    i: usize,
+    brackets: &HashMap<usize, usize>,
+) -> Option<(InlineImage, usize)> {
+    let close = *brackets.get(&(i + 1))?;
+    let (source, title, next) = read_destination(s, close + 1)?;
+    let alt_text = InlineContent::plain_text(&parse_inline_content(s[i + 2..close].to_string()));
+
+    Some((
+        InlineImage::new(Style::Default, source, title, alt_text),
+        next,
+    ))
+}
+
+// An image at the start of `s`, and the offset after it.
+pub fn read_image(s: &str) -> Option<(InlineImage, usize)> {
+    match s.starts_with("![") {
+        true => read_image_at(s, 0, &match_brackets(s)),
+        false => None,
+    }
+}
+
+// A link from the `[` at `i`, either inline as `[text](url "title")` or a reference as
+// `[text][label]`, `[label][]` or `[label]`. Returns the offset of the `]` closing the text,
+// the link without its content and the offset after the link.
+fn read_link(
+    s: &str,
+    i: usize,
+    brackets: &HashMap<usize, usize>,
+    references: &mut LinkReferences,
+) -> Option<(usize, InlineLink, usize)> {
+    let close = *brackets.get(&i)?;
+
+    if let Some((url, title, next)) = read_destination(s, close + 1) {
+        let link = InlineLink::new(Style::Default, url, vec![]).with_title(title);
+        return Some((close, link, next));
+    }
+
+    let (label, next, explicit) = match brackets.get(&(close + 1)) {
+        Some(end) if *end == close + 2 => (&s[i + 1..close], end + 1, true),
+        Some(end) => (&s[close + 2..*end], end + 1, true),
+        None => (&s[i + 1..close], close + 1, false),
+    };
+
+    match (references.get(label).cloned(), explicit) {
+        (Some(d), _) => {
+            let link = InlineLink::new(Style::Default, d.url, vec![])
+                .with_title(d.title)
+                .with_reference(label.to_string());
+            Some((close, link, next))
+        }
+        // Text in brackets without a definition is most likely not meant as a link, so only
+        // the explicit forms are reported.
+        (None, true) if !label.trim().is_empty() => {
+            references.add_undefined(label);
+            None
         }
+        (None, _) => None,
     }
+}
+
+// A single space on both sides of code is padding, so code can start or end with a backtick.
+fn code_content(s: &str) -> String {
+    match s.len() > 2 && s.starts_with(' ') && s.ends_with(' ') && !s.trim().is_empty() {
+        true => s[1..s.len() - 1].to_string(),
+        false => s.to_string(),
+    }
+}
+
+// Without a match the text runs up to (and the offset is that of) the last character, the
+// image syntax in `processing` relies on this to drop the closing bracket.
+pub fn read_until_char(s: &str, c: char, inclusive: bool, from: usize) -> (String, usize) {
+    let from = char_boundary(s, from);
+
+    let i = match s[from..].find(c) {
+        Some(n) => from + n,
+        None => s[from..]
+            .char_indices()
+            .last()
+            .map(|(n, _)| from + n)
+            .unwrap_or(from),
+    };
 
     (
         s[from..i].to_string(),
-        if inclusive { i + pattern.len() } else { i },
+        if inclusive { next_char(s, i) } else { i },
     )
 }
 
+// Text always takes the character at `from`, which may be a control character or trigger
+// that didn't start any syntax.
+fn read_until_control_char(s: &str, from: usize, extensions: &InlineExtensions) -> (String, usize) {
+    let start = next_char(s, from).min(s.len());
+
+    let i = s[start..]
+        .find(|c| is_control_char(c) || extensions.is_trigger(c))
+        .map(|n| start + n)
+        .unwrap_or(s.len());
+
+    (s[from..i].to_string(), i)
+}
+
+fn try_parse_extension(
+    s: &str,
+    i: usize,
+    extensions: &InlineExtensions,
+) -> Option<(InlineContent, usize)> {
+    let c = look_ahead(s, i).filter(|c| extensions.is_trigger(*c))?;
+
+    extensions
+        .try_parse(c, &s[i..], look_back(s, i))
+        .map(|(content, n)| (content, i + n))
+}
+
 pub fn parse_inline_content(input: String) -> Vec<InlineContent> {
-    let mut content = Vec::<InlineContent>::new();
+    parse_inline_content_with(input, &InlineExtensions::new())
+}
+
+pub fn parse_inline_content_with(
+    input: String,
+    extensions: &InlineExtensions,
+) -> Vec<InlineContent> {
+    parse_inline_content_with_references(input, extensions, &mut LinkReferences::new())
+}
+
+// As `parse_inline_content_with`, resolving reference links with the given definitions.
+pub fn parse_inline_content_with_references(
+    input: String,
+    extensions: &InlineExtensions,
+    references: &mut LinkReferences,
+) -> Vec<InlineContent> {
+    let mut nodes = Vec::<Node>::new();
     let mut i: usize = 0;
+    // Lengths of backtick runs known to have no closing run further on.
+    let mut unclosed_code = HashSet::<usize>::new();
+    let brackets = match_brackets(&input);
 
     loop {
-        match input.chars().nth(i) {
-            Some(c) if c == '*' => match (look_ahead(&input, i + 1), look_ahead(&input, i + 2)) {
-                (None, _) => {
-                    // TODO need to handle this!
-                    break;
-                }
-                (Some(_), None) => {
-                    // TODO need to handle this!
-                    break;
-                }
-                (Some(c1), Some(c2)) if c1 == '*' && c2 == '*' => {
-                    let (v, next) = read_until_string(&input, "***", true, i + 3);
+        if let Some((c, next)) = try_parse_extension(&input, i, extensions) {
+            nodes.push(Node::Content(c));
+            i = next;
+            continue;
+        }
 
-                    content.push(InlineContent::Span(InlineSpan {
-                        content: v,
-                        style: Style::Ref(vec!["b".to_string(), "i".to_string()]),
-                    }));
-                    i = next;
-                }
-                (Some(c1), Some(c2)) if c1 == '*' && c2 != '*' => {
-                    let (v, next) = read_until_string(&input, "**", true, i + 2);
+        match look_ahead(&input, i) {
+            Some(c) if c == '*' || c == '_' || c == '~' => {
+                let length = input[i..].chars().take_while(|d| *d == c).count();
+
+                nodes.push(Node::Delimiter(DelimiterRun::new(
+                    c,
+                    length,
+                    look_back(&input, i),
+                    look_ahead(&input, i + length),
+                )));
 
-                    content.push(InlineContent::Span(InlineSpan {
-                        content: v,
-                        style: Style::Ref(vec!["b".to_string()]),
-                    }));
+                i += length;
+            }
+            Some('\\') => {
+                let escaped = look_ahead(&input, i + 1).filter(|c| is_escapable(*c));
+
+                let (v, next) = match escaped {
+                    Some(c) => (c.to_string(), i + 2),
+                    None => ("\\".to_string(), i + 1),
+                };
+
+                nodes.push(Node::Content(InlineContent::Text(InlineText::new(v))));
+                i = next;
+            }
+            Some('`') => {
+                let length = backtick_run(&input, i);
+
+                // Without a closing run of the same length the backticks are text.
+                let end = match unclosed_code.contains(&length) {
+                    true => None,
+                    false => find_backtick_run(&input, length, i + length),
+                };
+
+                match end {
+                    Some(end) => {
+                        nodes.push(Node::Content(InlineContent::Span(InlineSpan::from_text(
+                            Style::Ref(vec!["code".to_string()]),
+                            code_content(&input[i + length..end]),
+                        ))));
+                        i = end + length;
+                    }
+                    None => {
+                        unclosed_code.insert(length);
+                        nodes.push(Node::Content(InlineContent::Text(InlineText::new(
+                            "`".repeat(length),
+                        ))));
+                        i += length;
+                    }
+                }
+            }
+            Some('!') => match read_image_at(&input, i, &brackets) {
+                Some((image, next)) => {
+                    nodes.push(Node::Content(InlineContent::Image(image)));
                     i = next;
                 }
-                (Some(_), Some(_)) => {
-                    let (v, next) = read_until_char(&input, '*', true, i + 1);
+                None => {
+                    nodes.push(Node::Content(InlineContent::Text(InlineText::new(
+                        "!".to_string(),
+                    ))));
+                    i += 1;
+                }
+            },
+            Some('[') => match read_link(&input, i, &brackets, references) {
+                Some((close, link, next)) => {
+                    let content = parse_inline_content_with_references(
+                        input[i + 1..close].to_string(),
+                        extensions,
+                        references,
+                    );
 
-                    content.push(InlineContent::Span(InlineSpan {
-                        content: v,
-                        style: Style::Ref(vec!["i".to_string()]),
-                    }));
+                    nodes.push(Node::Content(InlineContent::Link(InlineLink {
+                        content,
+                        ..link
+                    })));
                     i = next;
                 }
+                // The bracket is text, and whatever follows it is parsed as usual.
+                None => {
+                    nodes.push(Node::Content(InlineContent::Text(InlineText::new(
+                        "[".to_string(),
+                    ))));
+                    i += 1;
+                }
             },
-            Some(c) if c == '`' => {
-                let (v, next) = read_until_char(&input, '`', true, i + 1);
+            Some(_) => {
+                let (v, next) = read_until_control_char(&input, i, extensions);
 
-                content.push(InlineContent::Span(InlineSpan {
+                nodes.push(Node::Content(InlineContent::Text(InlineText {
                     content: v,
-                    style: Style::Ref(vec!["code".to_string()]),
-                }));
+                })));
 
                 i = next;
             }
-            Some(c) if c == '[' => {
-                let (text, next1) = read_until_char(&input, ']', true, i + 1);
+            None => {
+                break;
+            }
+        }
+    }
 
-                let (url, next2) = read_until_char(&input, ')', true, next1 + 1);
+    emphasis::resolve(nodes)
+}
 
-                content.push(InlineContent::Link(InlineLink::new(
-                    Style::Default,
-                    url,
-                    text,
-                )));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::InlineCustom;
+    use crate::parsing::extensions::InlineExtension;
+    use crate::parsing::references::LinkDefinition;
 
-                i = next2;
-            }
-            Some(_) => {
-                let (v, next) = read_until_control_char(&input, i);
+    // A compact form of the content: spans as `{classes:content}`, links as `[content](url)`
+    // and custom content as `<kind:content>`.
+    fn describe(content: &[InlineContent]) -> String {
+        content
+            .iter()
+            .map(|c| match c {
+                InlineContent::Text(t) => t.content.clone(),
+                InlineContent::Span(s) => match &s.style {
+                    Style::Ref(classes) => {
+                        format!("{{{}:{}}}", classes.join(" "), describe(&s.content))
+                    }
+                    _ => format!("{{:{}}}", describe(&s.content)),
+                },
+                InlineContent::Link(l) => format!("[{}]({})", describe(&l.content), l.url),
+                InlineContent::Image(i) => match i.title.is_empty() {
+                    true => format!("![{}]({})", i.alt_text, i.source),
+                    false => format!("![{}]({} \"{}\")", i.alt_text, i.source, i.title),
+                },
+                InlineContent::Custom(c) => format!("<{}:{}>", c.kind, c.content),
+            })
+            .collect()
+    }
+
+    fn parse(s: &str) -> String {
+        describe(&parse_inline_content(s.to_string()))
+    }
 
-                content.push(InlineContent::Text(InlineText { content: v }));
+    #[test]
+    fn ascii_spans() {
+        assert_eq!(
+            parse("a *b* **c** ***d*** `e` [f](g)"),
+            "a {i:b} {b:c} {i:{b:d}} {code:e} [f](g)"
+        );
+    }
 
-                i = next;
+    #[test]
+    fn nested_emphasis() {
+        assert_eq!(
+            parse("**bold with *italic* and `code`**"),
+            "{b:bold with {i:italic} and {code:code}}"
+        );
+        assert_eq!(parse("*a **b** c*"), "{i:a {b:b} c}");
+        assert_eq!(parse("***a** b*"), "{i:{b:a} b}");
+        assert_eq!(parse("***a* b**"), "{b:{i:a} b}");
+        assert_eq!(parse("_a __b__ c_"), "{i:a {b:b} c}");
+        assert_eq!(parse("*a _b_ c*"), "{i:a {i:b} c}");
+    }
+
+    #[test]
+    fn styled_link_text() {
+        assert_eq!(
+            parse("see [the **new** `api`](https://example.com) *now*"),
+            "see [the {b:new} {code:api}](https://example.com) {i:now}"
+        );
+        assert_eq!(parse("**[a](b)**"), "{b:[a](b)}");
+    }
+
+    #[test]
+    fn delimiter_run_rules() {
+        // Multiple of three rule.
+        assert_eq!(parse("*a**b*"), "{i:a**b}");
+        // Runs are only paired with runs of the same character.
+        assert_eq!(parse("*a_"), "*a_");
+        // Left over delimiters stay on the outside.
+        assert_eq!(parse("**a*"), "*{i:a}");
+        assert_eq!(parse("*a**"), "{i:a}*");
+        // Spaces after an opener or before a closer don't count as emphasis.
+        assert_eq!(parse("a * b * c"), "a * b * c");
+        assert_eq!(parse("2*3*4"), "2{i:3}4");
+    }
+
+    #[test]
+    fn intraword_underscores() {
+        assert_eq!(parse("snake_case_names"), "snake_case_names");
+        assert_eq!(parse("_snake_case_"), "{i:snake_case}");
+        assert_eq!(parse("__init__ and __main__"), "{b:init} and {b:main}");
+        assert_eq!(parse("a__b__c"), "a__b__c");
+    }
+
+    #[test]
+    fn underscore_emphasis() {
+        assert_eq!(parse("_italic_ and __bold__"), "{i:italic} and {b:bold}");
+        assert_eq!(parse("___both___"), "{i:{b:both}}");
+        assert_eq!(parse("a_b_ and _c_d"), "a_b_ and _c_d");
+        assert_eq!(parse("(_quoted_)"), "({i:quoted})");
+    }
+
+    #[test]
+    fn strikethrough() {
+        assert_eq!(parse("~~gone~~ now"), "{s:gone} now");
+        assert_eq!(parse("~~**both**~~"), "{s:{b:both}}");
+        assert_eq!(
+            parse("~5 minutes or ~~~three~~~"),
+            "~5 minutes or ~~~three~~~"
+        );
+        assert_eq!(parse("~~open"), "~~open");
+        assert_eq!(parse("~~ spaced ~~"), "~~ spaced ~~");
+    }
+
+    #[test]
+    fn accents_before_delimiters() {
+        assert_eq!(
+            parse("Déjà vu, *très* bien — `naïve`"),
+            "Déjà vu, {i:très} bien — {code:naïve}"
+        );
+    }
+
+    #[test]
+    fn german_and_russian() {
+        assert_eq!(
+            parse("Größe **Übergröße** и *жирный _курсив_* текст"),
+            "Größe {b:Übergröße} и {i:жирный {i:курсив}} текст"
+        );
+    }
+
+    #[test]
+    fn cjk() {
+        assert_eq!(
+            parse("日本語の*強調*と`コード`、中文**粗体**。"),
+            "日本語の{i:強調}と{code:コード}、中文{b:粗体}。"
+        );
+    }
+
+    #[test]
+    fn arabic_link() {
+        assert_eq!(
+            parse("انظر [الوثائق](https://example.com/ar/وثائق) هنا"),
+            "انظر [الوثائق](https://example.com/ar/وثائق) هنا"
+        );
+    }
+
+    #[test]
+    fn emoji() {
+        assert_eq!(
+            parse("🎉 *party* 👩‍👩‍👧 `🦀` **🚀**"),
+            "🎉 {i:party} 👩‍👩‍👧 {code:🦀} {b:🚀}"
+        );
+    }
+
+    #[test]
+    fn unmatched_emphasis_is_text() {
+        assert_eq!(parse("café *crème brûlée"), "café *crème brûlée");
+        assert_eq!(parse("ñ *"), "ñ *");
+        assert_eq!(parse("**ü"), "**ü");
+    }
+
+    #[test]
+    fn unclosed_code_and_links_are_text() {
+        assert_eq!(parse("日本 `コード"), "日本 `コード");
+        assert_eq!(parse("``a` *b*"), "``a` {i:b}");
+        assert_eq!(parse("[lien]é"), "[lien]é");
+        assert_eq!(parse("[a *b*](c"), "[a {i:b}](c");
+        assert_eq!(parse("[[a](b)"), "[[a](b)");
+    }
+
+    #[test]
+    fn backslash_escapes() {
+        assert_eq!(
+            parse(r"\*not\* \_em\_ \~~x\~~ \`c\` \[a\](b) \\"),
+            r"*not* _em_ ~~x~~ `c` [a](b) \"
+        );
+        assert_eq!(parse(r"*a \* b*"), "{i:a * b}");
+        assert_eq!(parse(r"a\b \é \"), r"a\b \é \");
+        assert_eq!(parse(r"[a \] b](c\)d)"), "[a ] b](c)d)");
+    }
+
+    #[test]
+    fn code_spans() {
+        assert_eq!(
+            parse(r"`a\*b` `` a`b `` ` `` `"),
+            r"{code:a\*b} {code:a`b} {code:``}"
+        );
+        assert_eq!(parse("[a `]` b](c)"), "[a {code:]} b](c)");
+        assert_eq!(parse("[a](b (c))"), "[a](b (c))");
+    }
+
+    #[test]
+    fn inline_images() {
+        assert_eq!(
+            parse(r#"a ![*badge*](b.svg "Build \"ok\"") in [![icon](i.png)](c)"#),
+            r#"a ![badge](b.svg "Build "ok"") in [![icon](i.png)](c)"#
+        );
+        assert_eq!(
+            parse("Hi! ![no image] !![a](b)"),
+            "Hi! ![no image] !![a](b)"
+        );
+        assert_eq!(parse(r#"\![a](b) [a](b "t")"#), "![a](b) [a](b)");
+    }
+
+    fn parse_with_references(s: &str) -> (String, Vec<String>) {
+        let mut references = LinkReferences::new()
+            .with_definition(
+                "Docs",
+                LinkDefinition {
+                    url: "https://example.com/docs".to_string(),
+                    title: "The docs".to_string(),
+                },
+            )
+            .with_definition(
+                "ÜBER  uns",
+                LinkDefinition {
+                    url: "/about".to_string(),
+                    title: "".to_string(),
+                },
+            );
+
+        let content = parse_inline_content_with_references(
+            s.to_string(),
+            &InlineExtensions::new(),
+            &mut references,
+        );
+
+        (describe(&content), references.take_undefined())
+    }
+
+    #[test]
+    fn reference_links() {
+        let (content, undefined) = parse_with_references(
+            "[the *docs*][docs], [Docs][], [docs] and [über\nUNS] but [x] and [y][]",
+        );
+
+        assert_eq!(
+            content,
+            "[the {i:docs}](https://example.com/docs), [Docs](https://example.com/docs), \
+             [docs](https://example.com/docs) and [über\nUNS](/about) but [x] and [y][]"
+        );
+        assert_eq!(undefined, vec!["y".to_string()]);
+    }
+
+    #[test]
+    fn undefined_references_are_text() {
+        let (content, undefined) = parse_with_references("see [this][nowhere] or [that][]");
+
+        assert_eq!(content, "see [this][nowhere] or [that][]");
+        assert_eq!(undefined, vec!["nowhere".to_string(), "that".to_string()]);
+    }
+
+    #[test]
+    fn read_until_char_offsets_are_bytes() {
+        assert_eq!(
+            read_until_char("![ölçü](a.png)", ']', false, 2),
+            ("ölçü".to_string(), 9)
+        );
+        assert_eq!(
+            read_until_char("(bild.png)", ' ', false, 1),
+            ("bild.png".to_string(), 9)
+        );
+        assert_eq!(read_until_char("ab", 'x', false, 5), ("".to_string(), 2));
+    }
+
+    struct Mention;
+
+    impl InlineExtension for Mention {
+        fn name(&self) -> &str {
+            "mention"
+        }
+
+        fn triggers(&self) -> Vec<char> {
+            vec!['@']
+        }
+
+        fn parse(&self, input: &str, previous: Option<char>) -> Option<(InlineContent, usize)> {
+            if previous.map(|c| c.is_alphanumeric()).unwrap_or(false) {
+                return None;
             }
-            None => {
-                break;
+
+            let end = input[1..]
+                .find(|c: char| !c.is_alphanumeric())
+                .map(|n| n + 1)
+                .unwrap_or(input.len());
+
+            Some((
+                InlineContent::Custom(InlineCustom::new(
+                    "mention".to_string(),
+                    input[..end].to_string(),
+                    input[..end].to_string(),
+                )),
+                end,
+            ))
+        }
+    }
+
+    #[test]
+    fn extensions_see_multibyte_neighbours() {
+        let extensions = InlineExtensions::new().with_extension(Box::new(Mention));
+        let content = parse_inline_content_with("ç@x à *@Zoë*!".to_string(), &extensions);
+
+        assert_eq!(describe(&content), "ç@x à {i:<mention:@Zoë>}!");
+    }
+
+    // `==text==`, which can span brackets.
+    struct Highlight;
+
+    impl InlineExtension for Highlight {
+        fn name(&self) -> &str {
+            "highlight"
+        }
+
+        fn triggers(&self) -> Vec<char> {
+            vec!['=']
+        }
+
+        fn parse(&self, input: &str, previous: Option<char>) -> Option<(InlineContent, usize)> {
+            if previous == Some('=') || !input.starts_with("==") {
+                return None;
             }
+
+            let end = input[2..].find("==")? + 2;
+
+            Some((
+                InlineContent::Custom(InlineCustom::new(
+                    "highlight".to_string(),
+                    input[2..end].to_string(),
+                    input[..end + 2].to_string(),
+                )),
+                end + 2,
+            ))
         }
     }
 
-    content
+    fn parse_with(s: &str) -> String {
+        let extensions = InlineExtensions::new()
+            .with_extension(Box::new(Mention))
+            .with_extension(Box::new(Highlight));
+
+        describe(&parse_inline_content_with(s.to_string(), &extensions))
+    }
+
+    #[test]
+    fn extensions_can_reject_mid_word_triggers() {
+        assert_eq!(
+            parse_with("mail a@b.c or @team, (@x) @"),
+            "mail a@b.c or <mention:@team>, (<mention:@x>) <mention:@>"
+        );
+        assert_eq!(
+            parse_with("a==b== x ===c=="),
+            "a<highlight:b> x <highlight:=c>"
+        );
+        assert_eq!(parse_with("no == close"), "no == close");
+    }
+
+    #[test]
+    fn extensions_inside_links() {
+        assert_eq!(
+            parse_with("[see ==this== @max](u) ==[a](b)=="),
+            "[see <highlight:this> <mention:@max>](u) <highlight:[a](b)>"
+        );
+        // The highlight can't close outside the link text.
+        assert_eq!(parse_with("[a ==b](u) c=="), "[a ==b](u) c==");
+    }
+
+    #[test]
+    fn extension_matches_spanning_brackets() {
+        // The first `]` closes the `[` for the bracket matching, but it's inside the
+        // highlight, so there is no link and nothing is lost.
+        assert_eq!(parse_with("[a ==b] c==](u) d"), "[a <highlight:b] c>](u) d");
+        assert_eq!(parse_with("[==a]==](u)"), "[<highlight:a]>](u)");
+        assert_eq!(
+            parse_with("![i ==a]==](p.png)"),
+            "![i <highlight:a]>](p.png)"
+        );
+        assert_eq!(parse_with("==[a==](u) [b](c)"), "<highlight:[a>](u) [b](c)");
+    }
+
+    #[test]
+    fn long_input_is_linear() {
+        let input = "é *ü* 漢字 **ß _ø_** ".repeat(20_000);
+        let content = parse_inline_content(input.clone());
+
+        assert_eq!(content.len(), 80_001);
+        assert_eq!(
+            InlineContent::plain_text(&content),
+            input.replace(['*', '_'], "")
+        );
+    }
+
+    #[test]
+    fn long_unmatched_input_is_linear() {
+        let input = "*a _b [c ".repeat(20_000);
+        let content = parse_inline_content(input.clone());
+
+        assert_eq!(InlineContent::plain_text(&content), input);
+    }
 }
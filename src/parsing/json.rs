@@ -0,0 +1,31 @@
+use serde::Deserialize;
+
+use crate::{core::documents::Block, rendering::json::JSON_VERSION};
+
+#[derive(Deserialize)]
+struct JsonDocument {
+    blocks: Vec<Block>,
+}
+
+#[derive(Deserialize)]
+struct JsonVersion {
+    version: u32,
+}
+
+pub fn load(input: &str) -> Result<Vec<Block>, String> {
+    // Check the version first so another shape is reported as such, not as a field error.
+    let version = serde_json::from_str::<JsonVersion>(input)
+        .map_err(|e| format!("Invalid rdom JSON document: {}", e))?
+        .version;
+
+    if version != JSON_VERSION {
+        return Err(format!(
+            "Unsupported rdom JSON version {} (expected {})",
+            version, JSON_VERSION
+        ));
+    }
+
+    serde_json::from_str::<JsonDocument>(input)
+        .map(|d| d.blocks)
+        .map_err(|e| format!("Invalid rdom JSON document: {}", e))
+}
@@ -1,3 +1,15 @@
+pub mod asciidoc;
 pub mod block_parser;
+pub mod emphasis;
+pub mod extensions;
+pub mod front_matter;
+pub mod html;
+pub mod includes;
+pub mod info_string;
 pub mod inline_parser;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod processing;
+pub mod references;
+pub mod rst;
+pub mod transclusion;
@@ -1,12 +1,29 @@
 use crate::core::documents::{
-    Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, ListBlock, ListItem, ParagraphBlock,
-    Style,
+    Block, CodeBlock, CodeInclude, HeaderBlock, HeaderLevel, ImageBlock, InlineContent,
+    InlineImage, InlineText, ListBlock, ListItem, ParagraphBlock, Style,
 };
 
 use super::block_parser::BlockToken;
+use super::extensions::InlineExtensions;
+use super::info_string;
 use super::inline_parser;
+use super::references::{self, LinkReferences};
 
-fn create_header_block(s: String, style: Style) -> HeaderBlock {
+type SpannedBlocks = Vec<(Block, (usize, usize))>;
+
+// A problem in the source that doesn't stop it being processed, `line` is counted from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub message: String,
+}
+
+fn create_header_block(
+    s: String,
+    style: Style,
+    extensions: &InlineExtensions,
+    references: &mut LinkReferences,
+) -> HeaderBlock {
     let mut i: usize = 0;
 
     loop {
@@ -33,27 +50,74 @@ fn create_header_block(s: String, style: Style) -> HeaderBlock {
         _ => (HeaderLevel::H6, false),
     };
 
-    let content = inline_parser::parse_inline_content(s[i..s.len()].trim().to_string());
+    let content = inline_parser::parse_inline_content_with_references(
+        s[i..s.len()].trim().to_string(),
+        extensions,
+        references,
+    );
 
     HeaderBlock::new(style, level, content, indexed)
 }
 
-fn create_paragraph_block(s: String, style: Style) -> ParagraphBlock {
-    ParagraphBlock::new(style, inline_parser::parse_inline_content(s))
+fn create_paragraph_block(
+    s: String,
+    style: Style,
+    extensions: &InlineExtensions,
+    references: &mut LinkReferences,
+) -> ParagraphBlock {
+    ParagraphBlock::new(
+        style,
+        inline_parser::parse_inline_content_with_references(s, extensions, references),
+    )
 }
 
-fn create_code_block(s: String, language: Option<String>, style: Style) -> CodeBlock {
-    CodeBlock::new(style, s, language)
-}
+// Returns the block and any problem with its info string.
+fn create_code_block(s: String, info: Option<String>, style: Style) -> (CodeBlock, Option<String>) {
+    let info = info_string::parse(&info.unwrap_or_default());
 
-fn create_image_block(s: String, style: Style) -> ImageBlock {
-    let (alt_text, next1) = inline_parser::read_until_char(&s, ']', false, 2);
+    let mut block =
+        CodeBlock::new(style, s, info.language.clone()).with_highlighted_lines(info.ranges.clone());
 
-    let (source, next2) = inline_parser::read_until_char(&s, ' ', false, next1 + 2);
+    if let Some(title) = info.get("title") {
+        block = block.with_title(title.to_string());
+    }
 
-    let (title, next3) = inline_parser::read_until_char(&s, '"', false, next2 + 2);
+    // Lines are numbered from 1 if the first line number is invalid.
+    let (start, problem) = match info.line_numbers() {
+        Some(Ok(start)) => (Some(start), None),
+        Some(Err(e)) => (Some(1), Some(e)),
+        None => (None, None),
+    };
 
-    let (hw, _) = inline_parser::read_until_char(&s, '}', false, next3 + 3);
+    if let Some(start) = start {
+        block = block.with_line_numbers(start);
+    }
+
+    if let Some(path) = info.get("include") {
+        block = block.with_include(CodeInclude::new(
+            path.to_string(),
+            info.get("lines").map(|l| l.to_string()),
+            info.get("region").map(|r| r.to_string()),
+        ));
+    }
+
+    (block, problem)
+}
+
+fn create_image_block(s: String, style: Style) -> ImageBlock {
+    // Image tokens are always whole images, see `LineType::new`.
+    let (image, next) = match inline_parser::read_image(&s) {
+        Some(i) => i,
+        None => (
+            InlineImage::new(style.clone(), s.clone(), "".to_string(), "".to_string()),
+            s.len(),
+        ),
+    };
+
+    let hw = s[next..]
+        .trim()
+        .trim_start_matches('{')
+        .trim_end_matches('}');
 
     let (height, width) = hw.trim().split(',').fold((None, None), |(h, w), x| {
         let mut ss = x.trim().split(':');
@@ -66,24 +130,90 @@ fn create_image_block(s: String, style: Style) -> ImageBlock {
         }
     });
 
-    ImageBlock::new(style, source, title, alt_text, height, width)
+    ImageBlock::new(
+        style,
+        image.source,
+        image.title,
+        image.alt_text,
+        height,
+        width,
+    )
 }
 
-fn create_list_item(s: String, style: Style) -> ListItem {
-    ListItem::new(style, inline_parser::parse_inline_content(s))
+fn create_list_item(
+    s: String,
+    style: Style,
+    extensions: &InlineExtensions,
+    references: &mut LinkReferences,
+) -> ListItem {
+    ListItem::new(
+        style,
+        inline_parser::parse_inline_content_with_references(s, extensions, references),
+    )
 }
 
 pub fn process_tokens(tokens: Vec<BlockToken>) -> Vec<Block> {
+    process_tokens_with_spans(tokens.into_iter().map(|t| (t, (0, 0))).collect())
+        .into_iter()
+        .map(|(b, _)| b)
+        .collect()
+}
+
+// As `process_tokens`, keeping the line span of the tokens each block was created from.
+pub fn process_tokens_with_spans(
+    tokens: Vec<(BlockToken, (usize, usize))>,
+) -> Vec<(Block, (usize, usize))> {
+    process_tokens_with_extensions(tokens, &InlineExtensions::new())
+}
+
+// As `process_tokens_with_spans`, parsing inline content with the given extensions.
+pub fn process_tokens_with_extensions(
+    tokens: Vec<(BlockToken, (usize, usize))>,
+    extensions: &InlineExtensions,
+) -> Vec<(Block, (usize, usize))> {
+    let mut references = collect_references(&tokens);
+
+    process_tokens_with_references(tokens, extensions, &mut references).0
+}
+
+// The link reference definitions in the tokens, links can refer to a definition anywhere in
+// the document.
+pub fn collect_references(tokens: &[(BlockToken, (usize, usize))]) -> LinkReferences {
+    tokens
+        .iter()
+        .filter_map(|(t, _)| match t {
+            BlockToken::LinkDefinition(s) => references::parse_definition(s),
+            _ => None,
+        })
+        .fold(LinkReferences::new(), |r, (label, definition)| {
+            r.with_definition(&label, definition)
+        })
+}
+
+// As `process_tokens_with_extensions`, resolving reference links with the given definitions
+// and reporting the links to undefined ones.
+pub fn process_tokens_with_references(
+    tokens: Vec<(BlockToken, (usize, usize))>,
+    extensions: &InlineExtensions,
+    references: &mut LinkReferences,
+) -> (SpannedBlocks, Vec<Diagnostic>) {
     let mut blocks = Vec::<Block>::new();
+    let mut spans = Vec::<(usize, usize)>::new();
+    let mut diagnostics = Vec::<Diagnostic>::new();
 
     let mut i: usize = 0;
 
     loop {
-        match tokens.get(i) {
+        let start = i;
+        let count = blocks.len();
+
+        match tokens.get(i).map(|(t, _)| t) {
             Some(BlockToken::Header(s)) => {
                 blocks.push(Block::header(create_header_block(
                     s.to_owned(),
                     Style::Default,
+                    extensions,
+                    references,
                 )));
                 i = i + 1;
             }
@@ -91,15 +221,23 @@ pub fn process_tokens(tokens: Vec<BlockToken>) -> Vec<Block> {
                 blocks.push(Block::paragraph(create_paragraph_block(
                     s.to_owned(),
                     Style::Default,
+                    extensions,
+                    references,
                 )));
                 i = i + 1;
             }
             Some(BlockToken::CodeBlock(l, s)) => {
-                blocks.push(Block::code(create_code_block(
-                    s.to_owned(),
-                    l.to_owned(),
-                    Style::Default,
-                )));
+                let (block, problem) =
+                    create_code_block(s.to_owned(), l.to_owned(), Style::Default);
+
+                if let Some(message) = problem {
+                    diagnostics.push(Diagnostic {
+                        line: tokens[i].1 .0,
+                        message,
+                    });
+                }
+
+                blocks.push(Block::code(block));
                 i = i + 1;
             }
             Some(BlockToken::Image(s)) => {
@@ -115,7 +253,7 @@ pub fn process_tokens(tokens: Vec<BlockToken>) -> Vec<Block> {
                 i = i + 1;
 
                 loop {
-                    match tokens.get(i) {
+                    match tokens.get(i).map(|(t, _)| t) {
                         Some(BlockToken::OrderedListItem(s)) => {
                             items.push(s.to_owned());
                             i = i + 1;
@@ -130,7 +268,7 @@ pub fn process_tokens(tokens: Vec<BlockToken>) -> Vec<Block> {
                     Style::Default,
                     items
                         .into_iter()
-                        .map(|s| create_list_item(s, Style::Default))
+                        .map(|s| create_list_item(s, Style::Default, extensions, references))
                         .collect(),
                 )));
             }
@@ -140,7 +278,7 @@ pub fn process_tokens(tokens: Vec<BlockToken>) -> Vec<Block> {
                 i = i + 1;
 
                 loop {
-                    match tokens.get(i) {
+                    match tokens.get(i).map(|(t, _)| t) {
                         Some(BlockToken::UnorderedListItem(s)) => {
                             items.push(s.to_owned());
                             i = i + 1;
@@ -155,11 +293,25 @@ pub fn process_tokens(tokens: Vec<BlockToken>) -> Vec<Block> {
                     Style::Default,
                     items
                         .into_iter()
-                        .map(|s| create_list_item(s, Style::Default))
+                        .map(|s| create_list_item(s, Style::Default, extensions, references))
                         .collect(),
                 )));
             }
-            Some(BlockToken::Empty) => {
+            // Includes are resolved by `transclusion::load_document`, otherwise the directive
+            // is kept as plain text, marked so it can be written back as a directive.
+            Some(BlockToken::Include(s)) => {
+                blocks.push(Block::paragraph(ParagraphBlock::new(
+                    Style::Default.with_class("include"),
+                    vec![InlineContent::Text(InlineText::new(s.trim().to_string()))],
+                )));
+                i = i + 1;
+            }
+            Some(BlockToken::Block(b)) => {
+                blocks.push(b.clone());
+                i = i + 1;
+            }
+            // Definitions were collected before the blocks were processed.
+            Some(BlockToken::LinkDefinition(_)) | Some(BlockToken::Empty) => {
                 i = i + 1;
             }
             Some(BlockToken::Unknown(_)) => {
@@ -169,7 +321,69 @@ pub fn process_tokens(tokens: Vec<BlockToken>) -> Vec<Block> {
                 break;
             }
         }
+
+        if blocks.len() > count {
+            spans.push((tokens[start].1 .0, tokens[i - 1].1 .1));
+        }
+
+        for label in references.take_undefined() {
+            diagnostics.push(Diagnostic {
+                line: tokens[start].1 .0,
+                message: format!("Undefined link reference `{}`", label),
+            });
+        }
+    }
+
+    (blocks.into_iter().zip(spans).collect(), diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::formatting::Formatters;
+    use crate::parsing::block_parser::Input;
+
+    fn process(markdown: &str) -> (SpannedBlocks, Vec<Diagnostic>) {
+        let tokens = Input::new(markdown.split('\n').collect())
+            .parse_blocks_with_spans(&Formatters::default());
+        let mut references = collect_references(&tokens);
+
+        process_tokens_with_references(tokens, &InlineExtensions::new(), &mut references)
+    }
+
+    #[test]
+    fn code_block_info_strings() {
+        let (blocks, diagnostics) = process("```\na\n```\n\n```rust linenos=7 {2}\nb\n```");
+
+        match &blocks[..] {
+            [(Block::Code(bare), _), (Block::Code(rust), _)] => {
+                assert_eq!(bare.language, None);
+                assert_eq!(bare.line_numbers, None);
+                assert_eq!(rust.language.as_deref(), Some("rust"));
+                assert_eq!(rust.line_numbers, Some(7));
+                assert_eq!(rust.highlighted_lines, vec![(2, 2)]);
+            }
+            other => panic!("Expected two code blocks, got {:?}", other),
+        }
+
+        assert!(diagnostics.is_empty());
     }
 
-    blocks
+    #[test]
+    fn invalid_line_numbers_are_reported() {
+        let (blocks, diagnostics) = process("Text\n\n```rust linenos=abc\nb\n```");
+
+        match &blocks[..] {
+            [_, (Block::Code(code), _)] => assert_eq!(code.line_numbers, Some(1)),
+            other => panic!("Expected a code block, got {:?}", other),
+        }
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                line: 3,
+                message: "Invalid first line number `abc`".to_string(),
+            }]
+        );
+    }
 }
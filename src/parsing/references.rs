@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use super::inline_parser;
+
+// Link reference definitions, `[label]: url "title"` on a line of their own. Links refer to
+// them with `[text][label]`, `[label][]` or `[label]`, wherever in the document they are.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkDefinition {
+    pub url: String,
+    pub title: String,
+}
+
+#[derive(Debug, Default)]
+pub struct LinkReferences {
+    definitions: HashMap<String, LinkDefinition>,
+    // Labels used in links without a definition, in the order they were found.
+    undefined: Vec<String>,
+}
+
+// Labels match case-insensitively and with any run of whitespace as a single space.
+pub fn normalise_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+// The label and definition on a definition line, the line is already without indentation.
+pub fn parse_definition(line: &str) -> Option<(String, LinkDefinition)> {
+    let rest = line.strip_prefix('[')?;
+
+    // Labels can't contain unescaped brackets.
+    let mut close = None;
+    let mut escaped = false;
+
+    for (i, c) in rest.char_indices() {
+        match (escaped, c) {
+            (false, ']') => {
+                close = Some(i);
+                break;
+            }
+            (false, '[') => return None,
+            _ => {}
+        }
+        escaped = !escaped && c == '\\';
+    }
+
+    let close = close?;
+    let label = &rest[..close];
+    let destination = rest[close + 1..].strip_prefix(':')?.trim();
+
+    match label.trim().is_empty() || destination.is_empty() {
+        true => None,
+        false => {
+            let (url, title) = inline_parser::split_title(destination);
+            Some((label.to_string(), LinkDefinition { url, title }))
+        }
+    }
+}
+
+impl LinkReferences {
+    pub fn new() -> LinkReferences {
+        LinkReferences::default()
+    }
+
+    // The first definition of a label is used, as in CommonMark.
+    pub fn with_definition(mut self, label: &str, definition: LinkDefinition) -> LinkReferences {
+        self.definitions
+            .entry(normalise_label(label))
+            .or_insert(definition);
+        self
+    }
+
+    pub fn get(&self, label: &str) -> Option<&LinkDefinition> {
+        self.definitions.get(&normalise_label(label))
+    }
+
+    pub fn add_undefined(&mut self, label: &str) {
+        self.undefined.push(label.to_string());
+    }
+
+    // The undefined labels found since the last call.
+    pub fn take_undefined(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.undefined)
+    }
+}
@@ -0,0 +1,618 @@
+use std::collections::HashMap;
+
+use crate::core::documents::{
+    Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, InlineContent, InlineLink, InlineSpan,
+    InlineText, ListBlock, ListItem, ParagraphBlock, Style,
+};
+
+const ADORNMENT_CHARS: &str = "=-`:'\"~^_*+#<>.";
+
+const CODE_DIRECTIVES: [&str; 3] = ["code-block", "code", "sourcecode"];
+
+const URL_SCHEMES: [&str; 3] = ["https://", "http://", "mailto:"];
+
+// Link targets, `.. _name: url`, referenced from the inline content.
+type Targets = HashMap<String, String>;
+
+fn normalise_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn starts_with_at(chars: &[char], i: usize, s: &str) -> bool {
+    s.chars()
+        .enumerate()
+        .all(|(k, c)| chars.get(i + k) == Some(&c))
+}
+
+fn can_open(chars: &[char], i: usize, length: usize) -> bool {
+    let before = i
+        .checked_sub(1)
+        .map(|p| chars[p].is_whitespace() || "([{<'\"-/:".contains(chars[p]))
+        .unwrap_or(true);
+    let after = chars
+        .get(i + length)
+        .map(|c| !c.is_whitespace())
+        .unwrap_or(false);
+
+    before && after
+}
+
+fn find_close(chars: &[char], from: usize, delimiter: &str) -> Option<usize> {
+    let length = delimiter.chars().count();
+
+    (from + 1..chars.len()).find(|j| {
+        starts_with_at(chars, *j, delimiter)
+            && !chars[j - 1].is_whitespace()
+            && chars
+                .get(j + length)
+                .map(|c| !c.is_alphanumeric())
+                .unwrap_or(true)
+    })
+}
+
+fn create_link(url: String, text: String) -> InlineContent {
+    InlineContent::Link(InlineLink::from_text(Style::Default, url, text))
+}
+
+// Interpreted text followed by `_` or `__`, either `text <url>`_ or a named reference.
+fn create_reference(inner: &str, targets: &Targets) -> InlineContent {
+    if let Some(open) = inner.rfind('<').filter(|_| inner.ends_with('>')) {
+        let text = inner[..open].trim();
+        let url = inner[open + 1..inner.len() - 1].trim().to_string();
+
+        return match text.is_empty() {
+            true => create_link(url.clone(), url),
+            false => create_link(url, text.to_string()),
+        };
+    }
+
+    match targets.get(&normalise_name(inner)) {
+        Some(url) => create_link(url.clone(), inner.to_string()),
+        None => InlineContent::Text(InlineText::new(inner.to_string())),
+    }
+}
+
+fn flush_text(buffer: &mut String, content: &mut Vec<InlineContent>) {
+    if !buffer.is_empty() {
+        content.push(InlineContent::Text(InlineText::new(std::mem::take(buffer))));
+    }
+}
+
+fn parse_chars(chars: &[char], targets: &Targets) -> Vec<InlineContent> {
+    let mut content = Vec::<InlineContent>::new();
+    let mut buffer = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\\' && i + 1 < chars.len() {
+            buffer.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+
+        let delimiter = match c {
+            '`' if starts_with_at(chars, i, "``") => Some("``"),
+            '`' => Some("`"),
+            '*' if starts_with_at(chars, i, "**") => Some("**"),
+            '*' => Some("*"),
+            _ => None,
+        };
+
+        if let Some(delimiter) = delimiter.filter(|d| can_open(chars, i, d.len())) {
+            let start = i + delimiter.len();
+
+            // Interpreted text can be followed by the `_`/`__` of a reference.
+            let close = match delimiter {
+                "`" => (start + 1..chars.len())
+                    .find(|j| chars[*j] == '`' && !chars[j - 1].is_whitespace()),
+                _ => find_close(chars, start, delimiter),
+            };
+
+            if let Some(end) = close {
+                let inner: String = chars[start..end].iter().collect();
+                let mut next = end + delimiter.len();
+
+                let item = match delimiter {
+                    "``" => vec![InlineContent::Span(InlineSpan::from_text(
+                        Style::create_ref(vec!["code".to_string()]),
+                        inner,
+                    ))],
+                    "**" => vec![InlineContent::Span(InlineSpan::from_text(
+                        Style::create_ref(vec!["b".to_string()]),
+                        inner,
+                    ))],
+                    "*" => vec![InlineContent::Span(InlineSpan::from_text(
+                        Style::create_ref(vec!["i".to_string()]),
+                        inner,
+                    ))],
+                    _ => {
+                        let underscores = chars[next..].iter().take_while(|c| **c == '_').count();
+                        match underscores {
+                            1 | 2 => {
+                                next += underscores;
+                                vec![create_reference(&inner, targets)]
+                            }
+                            // Default interpreted text role is rendered as emphasis.
+                            _ => vec![InlineContent::Span(InlineSpan::from_text(
+                                Style::create_ref(vec!["i".to_string()]),
+                                inner,
+                            ))],
+                        }
+                    }
+                };
+
+                flush_text(&mut buffer, &mut content);
+                content.extend(item);
+                i = next;
+                continue;
+            }
+        }
+
+        let at_boundary = i == 0 || !chars[i - 1].is_alphanumeric();
+
+        if at_boundary && URL_SCHEMES.iter().any(|s| starts_with_at(chars, i, s)) {
+            let mut end = (i..chars.len())
+                .find(|j| chars[*j].is_whitespace())
+                .unwrap_or(chars.len());
+            while end > i && ".,;:!?)".contains(chars[end - 1]) {
+                end -= 1;
+            }
+            let url: String = chars[i..end].iter().collect();
+
+            flush_text(&mut buffer, &mut content);
+            content.push(create_link(url.clone(), url));
+            i = end;
+            continue;
+        }
+
+        // Simple reference names, `word_`.
+        if c == '_' && !buffer.is_empty() {
+            let word_start = buffer
+                .rfind(|c: char| !(c.is_alphanumeric() || c == '-' || c == '.'))
+                .map(|p| p + 1)
+                .unwrap_or(0);
+            let word = buffer[word_start..].to_string();
+            let after = chars.get(i + 1);
+
+            if !word.is_empty()
+                && after.map(|a| !a.is_alphanumeric()).unwrap_or(true)
+                && targets.contains_key(&normalise_name(&word))
+            {
+                buffer.truncate(word_start);
+                flush_text(&mut buffer, &mut content);
+                content.push(create_reference(&word, targets));
+                i += 1;
+                continue;
+            }
+        }
+
+        buffer.push(c);
+        i += 1;
+    }
+
+    flush_text(&mut buffer, &mut content);
+    content
+}
+
+fn parse_inline(text: &str, targets: &Targets) -> Vec<InlineContent> {
+    parse_chars(&text.chars().collect::<Vec<char>>(), targets)
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_adornment(line: &str) -> bool {
+    let line = line.trim_end();
+    let first = match line.chars().next() {
+        Some(c) => c,
+        None => return false,
+    };
+
+    line.chars().count() >= 2 && ADORNMENT_CHARS.contains(first) && line.chars().all(|c| c == first)
+}
+
+fn collect_targets(lines: &[&str]) -> Targets {
+    lines
+        .iter()
+        .filter_map(|l| l.trim().strip_prefix(".. _"))
+        .filter_map(|l| l.split_once(": "))
+        .map(|(name, url)| {
+            (
+                normalise_name(name.trim_matches('`')),
+                url.trim().to_string(),
+            )
+        })
+        .collect()
+}
+
+fn list_marker(line: &str) -> Option<(bool, &str)> {
+    let line = line.trim_start();
+
+    if let Some(text) = ["* ", "- ", "+ "].iter().find_map(|m| line.strip_prefix(m)) {
+        return Some((false, text.trim()));
+    }
+
+    let (line, closing) = match line.strip_prefix('(') {
+        Some(l) => (l, ")"),
+        None => (line, ".)"),
+    };
+
+    let length = match line.starts_with('#') {
+        true => 1,
+        false => line.chars().take_while(|c| c.is_ascii_digit()).count(),
+    };
+
+    match length > 0 && line[length..].starts_with(|c| closing.contains(c)) {
+        true => line[length + 1..]
+            .strip_prefix(' ')
+            .map(|text| (true, text.trim())),
+        false => None,
+    }
+}
+
+// Reads an indented body, returning its dedented lines and the index after it.
+fn read_indented(lines: &[&str], start: usize, base: usize) -> (Vec<String>, usize) {
+    let mut end = start;
+
+    while end < lines.len() && (lines[end].trim().is_empty() || indent(lines[end]) > base) {
+        end += 1;
+    }
+
+    let body = &lines[start..end];
+    let margin = body
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| indent(l))
+        .min()
+        .unwrap_or(0);
+
+    let mut content: Vec<String> = body
+        .iter()
+        .map(|l| l.get(margin..).unwrap_or("").trim_end().to_string())
+        .skip_while(|l| l.is_empty())
+        .collect();
+
+    while content.last().map(|l| l.is_empty()).unwrap_or(false) {
+        content.pop();
+    }
+
+    (content, end)
+}
+
+fn parse_directive(
+    line: &str,
+    lines: &[&str],
+    i: usize,
+    targets: &Targets,
+    blocks: &mut Vec<Block>,
+) -> usize {
+    let base = indent(line);
+    let (name, argument) = line
+        .trim()
+        .strip_prefix(".. ")
+        .and_then(|d| d.split_once("::"))
+        .map(|(n, a)| (n.trim(), a.trim()))
+        .unwrap_or(("", ""));
+
+    let (body, next) = read_indented(lines, i + 1, base);
+
+    // Options, `:name: value`, come first in the body.
+    let options: HashMap<&str, &str> = body
+        .iter()
+        .take_while(|l| l.starts_with(':'))
+        .filter_map(|l| l[1..].split_once(':'))
+        .map(|(k, v)| (k, v.trim()))
+        .collect();
+    let content: Vec<&str> = body
+        .iter()
+        .skip_while(|l| l.starts_with(':'))
+        .skip_while(|l| l.is_empty())
+        .map(|l| l.as_str())
+        .collect();
+
+    match name {
+        "image" | "figure" => {
+            let get = |k: &str| options.get(k).map(|v| v.to_string());
+            // A figure's caption is the first paragraph of its body.
+            let caption = content
+                .iter()
+                .take_while(|l| !l.is_empty())
+                .map(|l| l.trim())
+                .collect::<Vec<&str>>()
+                .join(" ");
+
+            blocks.push(Block::image(ImageBlock::new(
+                Style::Default,
+                argument.to_string(),
+                get("title").unwrap_or(caption),
+                get("alt").unwrap_or_default(),
+                get("height"),
+                get("width"),
+            )));
+        }
+        n if CODE_DIRECTIVES.contains(&n) => {
+            let language = match argument.is_empty() {
+                true => None,
+                false => Some(argument.to_string()),
+            };
+
+            blocks.push(Block::code(CodeBlock::new(
+                Style::Default,
+                content.join("\n"),
+                language,
+            )));
+        }
+        // The bodies of other directives, such as admonitions, are kept as plain blocks with
+        // the argument as their first paragraph.
+        n if !n.is_empty() && !n.contains(char::is_whitespace) => {
+            let body: Vec<&str> = match argument.is_empty() {
+                true => content,
+                false => [argument, ""].into_iter().chain(content).collect(),
+            };
+
+            blocks.extend(parse_blocks(&body, targets));
+        }
+        // Comments, targets and substitutions are skipped.
+        _ => {}
+    }
+
+    next
+}
+
+fn parse_list(lines: &[&str], start: usize, targets: &Targets) -> (ListBlock, usize) {
+    let ordered = list_marker(lines[start]).map(|(o, _)| o).unwrap_or(false);
+    let base = indent(lines[start]);
+    let mut items = Vec::<String>::new();
+    let mut i = start;
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        if line.trim().is_empty() {
+            let next = (i..lines.len()).find(|j| !lines[*j].trim().is_empty());
+            match next.map(|j| (indent(lines[j]), list_marker(lines[j]))) {
+                Some((n, Some((o, _)))) if n > base || o == ordered => {
+                    i = next.unwrap();
+                    continue;
+                }
+                Some((n, None)) if n > base && !items.is_empty() => {
+                    i = next.unwrap();
+                    continue;
+                }
+                _ => break,
+            }
+        }
+
+        match (indent(line) >= base, list_marker(line)) {
+            // Nested items are flattened, the document model has no nested lists.
+            (true, Some((o, text))) if o == ordered || indent(line) > base => {
+                items.push(text.to_string())
+            }
+            (_, _) if indent(line) > base => {
+                if let Some(last) = items.last_mut() {
+                    last.push(' ');
+                    last.push_str(line.trim());
+                }
+            }
+            _ => break,
+        }
+
+        i += 1;
+    }
+
+    let items = items
+        .into_iter()
+        .map(|t| ListItem::new(Style::Default, parse_inline(&t, targets)))
+        .collect();
+
+    (ListBlock::new(Style::Default, ordered, items), i)
+}
+
+fn header_level(level: usize) -> HeaderLevel {
+    match level {
+        0 => HeaderLevel::H1,
+        1 => HeaderLevel::H2,
+        2 => HeaderLevel::H3,
+        3 => HeaderLevel::H4,
+        4 => HeaderLevel::H5,
+        _ => HeaderLevel::H6,
+    }
+}
+
+fn parse_blocks(lines: &[&str], targets: &Targets) -> Vec<Block> {
+    let mut blocks = Vec::<Block>::new();
+    // Section levels are given by the order in which adornment styles are first seen.
+    let mut styles = Vec::<(char, bool)>::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i].trim_end();
+
+        if line.is_empty() {
+            i += 1;
+            continue;
+        }
+
+        let next = lines.get(i + 1).map(|l| l.trim_end()).unwrap_or("");
+        let after = lines.get(i + 2).map(|l| l.trim_end()).unwrap_or("");
+
+        let title = match (is_adornment(line), is_adornment(next), is_adornment(after)) {
+            (true, false, true) if !next.is_empty() && line == after => {
+                Some((next.trim(), line.chars().next().unwrap(), true, 3))
+            }
+            (false, true, _) if indent(line) == 0 && next.len() >= line.chars().count() => {
+                Some((line.trim(), next.chars().next().unwrap(), false, 2))
+            }
+            _ => None,
+        };
+
+        if let Some((text, c, overline, length)) = title {
+            let level = match styles.iter().position(|s| *s == (c, overline)) {
+                Some(p) => p,
+                None => {
+                    styles.push((c, overline));
+                    styles.len() - 1
+                }
+            };
+
+            blocks.push(Block::header(HeaderBlock::new(
+                Style::Default,
+                header_level(level),
+                parse_inline(text, targets),
+                level < 2,
+            )));
+            i += length;
+            continue;
+        }
+
+        // Transitions.
+        if is_adornment(line) && line.len() >= 4 {
+            i += 1;
+            continue;
+        }
+
+        if line.trim_start().starts_with("..") {
+            i = parse_directive(line, lines, i, targets, &mut blocks);
+            continue;
+        }
+
+        if list_marker(line).is_some() {
+            let (list, next) = parse_list(lines, i, targets);
+            blocks.push(Block::list(list));
+            i = next;
+            continue;
+        }
+
+        let start = i;
+        while i < lines.len() && !lines[i].trim().is_empty() {
+            i += 1;
+        }
+
+        let mut text = lines[start..i]
+            .iter()
+            .map(|l| l.trim())
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        // A paragraph ending in `::` introduces a literal block.
+        let literal = text.ends_with("::");
+        if literal {
+            text = match text.strip_suffix("::").unwrap().trim_end() {
+                "" => "".to_string(),
+                t if text.ends_with(" ::") => t.to_string(),
+                t => format!("{}:", t),
+            };
+        }
+
+        if !text.is_empty() {
+            blocks.push(Block::paragraph(ParagraphBlock::new(
+                Style::Default,
+                parse_inline(&text, targets),
+            )));
+        }
+
+        if literal {
+            let (content, next) = read_indented(lines, i, indent(lines[start]));
+            if !content.is_empty() {
+                blocks.push(Block::code(CodeBlock::new(
+                    Style::Default,
+                    content.join("\n"),
+                    None,
+                )));
+            }
+            i = next;
+        }
+    }
+
+    blocks
+}
+
+pub fn parse_rst(input: &str) -> Vec<Block> {
+    let lines: Vec<&str> = input.lines().collect();
+    parse_blocks(&lines, &collect_targets(&lines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::markdown;
+
+    // Fixtures are compared as Markdown, which shows every part of the document model used here.
+    fn convert(input: &str) -> String {
+        markdown::render_document(parse_rst(input))
+    }
+
+    #[test]
+    fn sections() {
+        assert_eq!(
+            convert("=====\nTitle\n=====\n\nSection\n=======\n\nSub\n---\n\nAgain\n=======\n"),
+            "# Title\n\n## Section\n\n### Sub\n\n## Again\n"
+        );
+    }
+
+    #[test]
+    fn paragraphs_and_transitions() {
+        assert_eq!(
+            convert("One\n  two\n\n----\n\nThree\n\n.. a comment\n   continued"),
+            "One two\n\nThree\n"
+        );
+    }
+
+    #[test]
+    fn inline_markup() {
+        assert_eq!(
+            convert("**strong**, *emphasis*, ``literal``, `interpreted` and \\*escaped\\*"),
+            "**strong**, *emphasis*, `literal`, *interpreted* and \\*escaped\\*\n"
+        );
+    }
+
+    #[test]
+    fn links_and_targets() {
+        assert_eq!(
+            convert("See `the docs <https://a.b/docs>`_, Python_ and `Named Ref`__.\n\n.. _Python: https://python.org\n.. _Named Ref: https://named.example"),
+            "See [the docs](https://a.b/docs), [Python](https://python.org) and [Named Ref](https://named.example).\n"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            convert("* one\n* two\n\n  continued\n\n  * nested\n\n#. auto\n#. numbered"),
+            "* one\n* two continued\n* nested\n\n1. auto\n2. numbered\n"
+        );
+        assert_eq!(
+            convert("1. first\n2. second\n\n* other"),
+            "1. first\n2. second\n\n* other\n"
+        );
+    }
+
+    #[test]
+    fn literal_and_code_blocks() {
+        assert_eq!(
+            convert("Example::\n\n    fn main() {}\n\nExpanded ::\n\n    a\n\n::\n\n    b\n\n.. code-block:: rust\n\n   let x = 1;"),
+            "Example:\n\n```\nfn main() {}\n```\n\nExpanded\n\n```\na\n```\n\n```\nb\n```\n\n```rust\nlet x = 1;\n```\n"
+        );
+    }
+
+    #[test]
+    fn images_and_figures() {
+        assert_eq!(
+            convert(".. image:: a.png\n   :alt: Alt\n   :width: 200\n\n.. figure:: b.png\n   :alt: B\n\n   The caption."),
+            "![Alt](a.png){width:200}\n\n![B](b.png \"The caption.\"){}\n"
+        );
+    }
+
+    #[test]
+    fn other_directives_keep_their_bodies() {
+        assert_eq!(
+            convert(".. note::\n\n   Kept *here*, see `docs`_.\n\n.. warning:: Careful.\n\n   * one\n\n.. |sub| image:: c.png\n\n.. A comment.\n\n.. _docs: https://a.b"),
+            "Kept *here*, see [docs](https://a.b).\n\nCareful.\n\n* one\n"
+        );
+    }
+}
@@ -0,0 +1,516 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::core::{
+    documents::{Block, HeaderBlock, HeaderLevel},
+    formatting::Formatters,
+};
+
+use super::{
+    block_parser::{BlockToken, Input},
+    extensions::{BlockExtension, InlineExtension, InlineExtensions, Priority},
+    front_matter, includes, processing,
+};
+
+// Where a block came from, lines are counted from 1 and inclusive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSpan {
+    pub path: PathBuf,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug)]
+pub struct SourceDocument {
+    pub front_matter: HashMap<String, String>,
+    pub blocks: Vec<Block>,
+    // The source of each block, `spans[i]` is the span of `blocks[i]`.
+    pub spans: Vec<SourceSpan>,
+    // Problems that didn't stop the document loading, such as undefined link references.
+    pub diagnostics: Vec<String>,
+}
+
+// How the top level document and every document it includes are parsed.
+pub struct LoadOptions {
+    block_extensions: Vec<(Priority, Rc<dyn BlockExtension>)>,
+    inline_extensions: InlineExtensions,
+    formatters: Formatters,
+}
+
+impl LoadOptions {
+    pub fn new() -> LoadOptions {
+        LoadOptions {
+            block_extensions: vec![],
+            inline_extensions: InlineExtensions::new(),
+            formatters: Formatters::default(),
+        }
+    }
+
+    pub fn with_block_extension(
+        mut self,
+        extension: Box<dyn BlockExtension>,
+        priority: Priority,
+    ) -> LoadOptions {
+        self.block_extensions.push((priority, Rc::from(extension)));
+        self
+    }
+
+    pub fn with_inline_extension(mut self, extension: Box<dyn InlineExtension>) -> LoadOptions {
+        self.inline_extensions = self.inline_extensions.with_extension(extension);
+        self
+    }
+
+    pub fn with_formatters(mut self, formatters: Formatters) -> LoadOptions {
+        self.formatters = formatters;
+        self
+    }
+
+    fn input(&self, lines: Vec<&str>) -> Input {
+        self.block_extensions
+            .iter()
+            .fold(Input::new(lines), |input, (priority, extension)| {
+                input.with_shared_extension(Rc::clone(extension), *priority)
+            })
+    }
+}
+
+impl Default for LoadOptions {
+    fn default() -> LoadOptions {
+        LoadOptions::new()
+    }
+}
+
+struct IncludeDirective {
+    path: String,
+    shift: i32,
+}
+
+// `{{include path/to/file.md}}`, optionally with `shift=N` to move the included headings
+// down (or up, for negative values) by `N` levels.
+fn parse_directive(line: &str) -> Result<IncludeDirective, String> {
+    let inner = line
+        .trim()
+        .strip_prefix("{{include")
+        .and_then(|l| l.strip_suffix("}}"))
+        .unwrap_or("");
+
+    let mut parts = inner.split_whitespace();
+    let path = parts
+        .next()
+        .ok_or_else(|| "Include directive has no path".to_string())?
+        .trim_matches('"')
+        .to_string();
+
+    let mut shift = 0;
+
+    for part in parts {
+        match part.split_once('=') {
+            // Headings have six levels, so larger shifts can only be mistakes.
+            Some(("shift", v)) => {
+                shift = match v.parse() {
+                    Ok(n) if (-5..=5).contains(&n) => n,
+                    _ => return Err(format!("Invalid heading shift `{}` (-5 to 5)", v)),
+                }
+            }
+            _ => return Err(format!("Unknown include option `{}`", part)),
+        }
+    }
+
+    Ok(IncludeDirective { path, shift })
+}
+
+fn shift_level(level: &HeaderLevel, shift: i32) -> HeaderLevel {
+    let current = match level {
+        HeaderLevel::H1 => 1,
+        HeaderLevel::H2 => 2,
+        HeaderLevel::H3 => 3,
+        HeaderLevel::H4 => 4,
+        HeaderLevel::H5 => 5,
+        HeaderLevel::H6 => 6,
+    };
+
+    match shift.saturating_add(current).clamp(1, 6) {
+        1 => HeaderLevel::H1,
+        2 => HeaderLevel::H2,
+        3 => HeaderLevel::H3,
+        4 => HeaderLevel::H4,
+        5 => HeaderLevel::H5,
+        _ => HeaderLevel::H6,
+    }
+}
+
+fn shift_block(block: Block, shift: i32) -> Block {
+    match block {
+        Block::Header(h) if shift != 0 => {
+            let level = shift_level(&h.level, shift);
+            // Only h1 and h2 are indexed, as in `processing::create_header_block`.
+            let indexed = matches!(level, HeaderLevel::H1 | HeaderLevel::H2);
+            Block::header(HeaderBlock::new(h.style, level, h.content, indexed))
+        }
+        other => other,
+    }
+}
+
+fn display_chain(stack: &[PathBuf], path: &Path) -> String {
+    stack
+        .iter()
+        .chain(std::iter::once(&path.to_path_buf()))
+        .map(|p| p.display().to_string())
+        .collect::<Vec<String>>()
+        .join(" -> ")
+}
+
+fn load(
+    path: &Path,
+    shift: i32,
+    options: &LoadOptions,
+    stack: &mut Vec<PathBuf>,
+    document: &mut SourceDocument,
+) -> Result<(), String> {
+    let canonical = fs::canonicalize(path)
+        .map_err(|e| format!("Could not read `{}`: {}", path.display(), e))?;
+
+    if stack.contains(&canonical) {
+        return Err(format!(
+            "Include cycle: {}",
+            display_chain(stack, &canonical)
+        ));
+    }
+
+    let raw = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read `{}`: {}", path.display(), e))?;
+
+    let (front_matter, body) = front_matter::split_front_matter(&raw);
+
+    // Only the front matter of the top level document is kept.
+    if stack.is_empty() {
+        document.front_matter = front_matter;
+    }
+
+    // Spans are counted from the start of the file, not the end of the front matter.
+    let offset = raw.split('\n').count() - body.split('\n').count();
+    let base_path = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let tokens = options
+        .input(body.split('\n').collect())
+        .parse_blocks_with_spans(&options.formatters)
+        .into_iter()
+        .map(|(t, (start, end))| (t, (start + offset, end + offset)))
+        .collect::<Vec<(BlockToken, (usize, usize))>>();
+
+    // Link references are resolved within each file.
+    let mut references = processing::collect_references(&tokens);
+
+    stack.push(canonical);
+
+    let mut segment = Vec::<(BlockToken, (usize, usize))>::new();
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        let directive = match token {
+            (BlockToken::Include(line), (start, _)) => Some((line, start)),
+            t => {
+                segment.push(t);
+                None
+            }
+        };
+
+        // Blocks before a directive (or at the end of the document) are processed as usual.
+        if directive.is_some() || tokens.peek().is_none() {
+            let (processed, diagnostics) = processing::process_tokens_with_references(
+                std::mem::take(&mut segment),
+                &options.inline_extensions,
+                &mut references,
+            );
+
+            let (blocks, spans): (Vec<Block>, Vec<(usize, usize)>) = processed
+                .into_iter()
+                .map(|(b, span)| (shift_block(b, shift), span))
+                .unzip();
+
+            document.diagnostics.extend(
+                diagnostics
+                    .into_iter()
+                    .map(|d| format!("{} at `{}` line {}", d.message, path.display(), d.line)),
+            );
+
+            // Code block includes are relative to the file the code block is in.
+            let blocks = includes::resolve_includes(blocks, base_path)
+                .map_err(|e| format!("{} (in `{}`)", e, path.display()))?;
+
+            document.blocks.extend(blocks);
+            document
+                .spans
+                .extend(spans.into_iter().map(|(start, end)| SourceSpan {
+                    path: path.to_path_buf(),
+                    start_line: start,
+                    end_line: end,
+                }));
+        }
+
+        if let Some((line, start)) = directive {
+            let directive = parse_directive(&line)
+                .map_err(|e| format!("{} at `{}` line {}", e, path.display(), start))?;
+
+            load(
+                &base_path.join(&directive.path),
+                shift.saturating_add(directive.shift),
+                options,
+                stack,
+                document,
+            )
+            .map_err(|e| format!("{}\n  included from `{}` line {}", e, path.display(), start))?;
+        }
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+// Loads a document from disk, splicing in documents included with `{{include ...}}` and
+// reading files included into code blocks, each relative to the file they appear in.
+pub fn load_document(path: &Path) -> Result<SourceDocument, String> {
+    load_document_with(path, &LoadOptions::default())
+}
+
+// As `load_document`, parsing every document with the given extensions and formatters.
+pub fn load_document_with(path: &Path, options: &LoadOptions) -> Result<SourceDocument, String> {
+    let mut document = SourceDocument {
+        front_matter: HashMap::new(),
+        blocks: vec![],
+        spans: vec![],
+        diagnostics: vec![],
+    };
+
+    load(path, 0, options, &mut vec![], &mut document)?;
+
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::{CustomBlock, InlineContent, InlineCustom};
+
+    // Writes the files into a new directory and returns its path.
+    fn files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rdom-include-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+
+        for (file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        dir
+    }
+
+    fn levels(document: &SourceDocument) -> Vec<usize> {
+        document
+            .blocks
+            .iter()
+            .filter_map(|b| match b {
+                Block::Header(h) => Some(match h.level {
+                    HeaderLevel::H1 => 1,
+                    HeaderLevel::H2 => 2,
+                    HeaderLevel::H3 => 3,
+                    HeaderLevel::H4 => 4,
+                    HeaderLevel::H5 => 5,
+                    HeaderLevel::H6 => 6,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spans_point_at_the_included_file() {
+        let dir = files(
+            "spans",
+            &[
+                (
+                    "main.md",
+                    "# Guide\n\n{{include parts/install.md}}\n\nDone.",
+                ),
+                (
+                    "parts/install.md",
+                    "---\ntitle: Install\n---\n## Install\n\nRun the\ninstaller.",
+                ),
+            ],
+        );
+
+        let document = load_document(&dir.join("main.md")).unwrap();
+        let spans = document
+            .spans
+            .iter()
+            .map(|s| {
+                (
+                    s.path.file_name().unwrap().to_str().unwrap(),
+                    s.start_line,
+                    s.end_line,
+                )
+            })
+            .collect::<Vec<(&str, usize, usize)>>();
+
+        assert_eq!(document.blocks.len(), document.spans.len());
+        assert_eq!(
+            spans,
+            vec![
+                ("main.md", 1, 1),
+                ("install.md", 4, 4),
+                ("install.md", 6, 7),
+                ("main.md", 5, 5),
+            ]
+        );
+        assert!(document.front_matter.is_empty());
+    }
+
+    #[test]
+    fn headings_are_shifted() {
+        let dir = files(
+            "shift",
+            &[
+                ("main.md", "# Guide\n\n{{include a.md shift=1}}"),
+                (
+                    "a.md",
+                    "# A\n\n{{include b.md shift=4}}\n\n{{include c.md shift=-2}}",
+                ),
+                ("b.md", "# B\n\n### B.1"),
+                ("c.md", "#### C"),
+            ],
+        );
+
+        let document = load_document(&dir.join("main.md")).unwrap();
+
+        assert_eq!(levels(&document), vec![1, 2, 6, 6, 3]);
+        assert!(matches!(parse_directive("{{include a.md shift=x}}"), Err(e) if e.contains("`x`")));
+        assert_eq!(
+            parse_directive("{{include a.md shift=2147483647}}").err(),
+            Some("Invalid heading shift `2147483647` (-5 to 5)".to_string())
+        );
+        assert!(parse_directive("{{include a.md shift=-6}}").is_err());
+        assert!(matches!(
+            shift_level(&HeaderLevel::H6, i32::MAX),
+            HeaderLevel::H6
+        ));
+        assert!(
+            matches!(parse_directive("{{include a.md depth=1}}"), Err(e) if e.contains("`depth=1`"))
+        );
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let dir = files(
+            "cycle",
+            &[
+                ("a.md", "# A\n\n{{include b.md}}"),
+                ("b.md", "# B\n\n{{include a.md}}"),
+                ("c.md", "{{include c.md}}"),
+            ],
+        );
+
+        let error = load_document(&dir.join("a.md")).unwrap_err();
+
+        assert!(error.starts_with("Include cycle: "));
+        assert!(error.contains("a.md -> "));
+        assert!(error.contains("b.md -> "));
+        assert!(error.contains("included from"));
+
+        assert!(load_document(&dir.join("c.md"))
+            .unwrap_err()
+            .starts_with("Include cycle: "));
+        assert!(load_document(&dir.join("missing.md"))
+            .unwrap_err()
+            .starts_with("Could not read"));
+    }
+
+    struct Note;
+
+    impl BlockExtension for Note {
+        fn name(&self) -> &str {
+            "note"
+        }
+
+        fn prefix(&self) -> Option<&str> {
+            Some("!!")
+        }
+
+        fn parse(&self, lines: &[&str]) -> Option<(Block, usize)> {
+            Some((
+                Block::custom(CustomBlock::new(
+                    "note".to_string(),
+                    vec![],
+                    lines[0].to_string(),
+                )),
+                1,
+            ))
+        }
+    }
+
+    struct Mention;
+
+    impl InlineExtension for Mention {
+        fn name(&self) -> &str {
+            "mention"
+        }
+
+        fn triggers(&self) -> Vec<char> {
+            vec!['@']
+        }
+
+        fn parse(&self, input: &str, _: Option<char>) -> Option<(InlineContent, usize)> {
+            let end = input[1..]
+                .find(|c: char| !c.is_alphanumeric())
+                .map(|n| n + 1)
+                .unwrap_or(input.len());
+
+            Some((
+                InlineContent::Custom(InlineCustom::new(
+                    "mention".to_string(),
+                    input[..end].to_string(),
+                    input[..end].to_string(),
+                )),
+                end,
+            ))
+        }
+    }
+
+    #[test]
+    fn included_files_are_parsed_with_the_same_extensions() {
+        let dir = files(
+            "extensions",
+            &[
+                ("main.md", "!! top\n\n{{include part.md}}"),
+                ("part.md", "!! included\n\nThanks @max."),
+            ],
+        );
+
+        let options = LoadOptions::new()
+            .with_block_extension(Box::new(Note), Priority::First)
+            .with_inline_extension(Box::new(Mention));
+        let document = load_document_with(&dir.join("main.md"), &options).unwrap();
+
+        let notes = document
+            .blocks
+            .iter()
+            .filter(|b| matches!(b, Block::Custom(c) if c.kind == "note"))
+            .count();
+        let mentions = match document.blocks.last() {
+            Some(Block::Paragraph(p)) => p
+                .content
+                .iter()
+                .filter(|c| matches!(c, InlineContent::Custom(c) if c.kind == "mention"))
+                .count(),
+            _ => 0,
+        };
+
+        assert_eq!(notes, 2);
+        assert_eq!(mentions, 1);
+    }
+}
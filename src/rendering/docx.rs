@@ -0,0 +1,628 @@
+use std::{fs, path::Path};
+
+use crate::{
+    core::{
+        documents::{
+            Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, InlineContent, InlineRun,
+            ListBlock, ParagraphBlock, Style,
+        },
+        zip::ZipWriter,
+    },
+    rendering::html::{escape, is_remote},
+};
+
+// Relationship ids 1 and 2 are the styles and numbering parts, hyperlinks follow. Images
+// have their own `rIdImage` ids.
+const FIRST_LINK_ID: usize = 3;
+
+// Drawing sizes are in EMU, 9525 to a pixel at 96 dpi. Images are scaled down to fit the text
+// width of an A4 page with 1 inch margins.
+const EMU_PER_PIXEL: u64 = 9525;
+const TEXT_WIDTH: u64 = 5_731_510;
+
+// Numbering instance 1 is shared by every bulleted list, each ordered list gets its own
+// instance so numbering restarts.
+const BULLET_NUM_ID: usize = 1;
+
+const CONTENT_TYPES: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>
+<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">
+  <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>
+  <Default Extension=\"xml\" ContentType=\"application/xml\"/>
+  <Default Extension=\"png\" ContentType=\"image/png\"/>
+  <Default Extension=\"jpeg\" ContentType=\"image/jpeg\"/>
+  <Override PartName=\"/word/document.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml\"/>
+  <Override PartName=\"/word/styles.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml\"/>
+  <Override PartName=\"/word/numbering.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml\"/>
+</Types>
+";
+
+const PACKAGE_RELS: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>
+<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">
+  <Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"word/document.xml\"/>
+</Relationships>
+";
+
+const STYLES: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>
+<w:styles xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">
+  <w:style w:type=\"paragraph\" w:default=\"1\" w:styleId=\"Normal\">
+    <w:name w:val=\"Normal\"/>
+    <w:pPr><w:spacing w:after=\"160\"/></w:pPr>
+    <w:rPr><w:sz w:val=\"22\"/></w:rPr>
+  </w:style>
+  <w:style w:type=\"paragraph\" w:styleId=\"Heading1\">
+    <w:name w:val=\"heading 1\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/>
+    <w:pPr><w:keepNext/><w:spacing w:before=\"360\" w:after=\"120\"/><w:outlineLvl w:val=\"0\"/></w:pPr>
+    <w:rPr><w:b/><w:sz w:val=\"36\"/></w:rPr>
+  </w:style>
+  <w:style w:type=\"paragraph\" w:styleId=\"Heading2\">
+    <w:name w:val=\"heading 2\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/>
+    <w:pPr><w:keepNext/><w:spacing w:before=\"240\" w:after=\"120\"/><w:outlineLvl w:val=\"1\"/></w:pPr>
+    <w:rPr><w:b/><w:sz w:val=\"30\"/></w:rPr>
+  </w:style>
+  <w:style w:type=\"paragraph\" w:styleId=\"Heading3\">
+    <w:name w:val=\"heading 3\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/>
+    <w:pPr><w:keepNext/><w:spacing w:before=\"240\" w:after=\"80\"/><w:outlineLvl w:val=\"2\"/></w:pPr>
+    <w:rPr><w:b/><w:sz w:val=\"26\"/></w:rPr>
+  </w:style>
+  <w:style w:type=\"paragraph\" w:styleId=\"Heading4\">
+    <w:name w:val=\"heading 4\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/>
+    <w:pPr><w:keepNext/><w:outlineLvl w:val=\"3\"/></w:pPr>
+    <w:rPr><w:b/><w:i/><w:sz w:val=\"24\"/></w:rPr>
+  </w:style>
+  <w:style w:type=\"paragraph\" w:styleId=\"Heading5\">
+    <w:name w:val=\"heading 5\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/>
+    <w:pPr><w:keepNext/><w:outlineLvl w:val=\"4\"/></w:pPr>
+    <w:rPr><w:b/><w:sz w:val=\"22\"/></w:rPr>
+  </w:style>
+  <w:style w:type=\"paragraph\" w:styleId=\"Heading6\">
+    <w:name w:val=\"heading 6\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/>
+    <w:pPr><w:keepNext/><w:outlineLvl w:val=\"5\"/></w:pPr>
+    <w:rPr><w:i/><w:sz w:val=\"22\"/></w:rPr>
+  </w:style>
+  <w:style w:type=\"paragraph\" w:styleId=\"ListParagraph\">
+    <w:name w:val=\"List Paragraph\"/><w:basedOn w:val=\"Normal\"/>
+    <w:pPr><w:spacing w:after=\"40\"/><w:ind w:left=\"720\"/></w:pPr>
+  </w:style>
+  <w:style w:type=\"paragraph\" w:styleId=\"Code\">
+    <w:name w:val=\"Code\"/><w:basedOn w:val=\"Normal\"/>
+    <w:pPr><w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"F2F2F2\"/><w:spacing w:after=\"0\"/></w:pPr>
+    <w:rPr><w:rFonts w:ascii=\"Courier New\" w:hAnsi=\"Courier New\" w:cs=\"Courier New\"/><w:sz w:val=\"20\"/></w:rPr>
+  </w:style>
+  <w:style w:type=\"paragraph\" w:styleId=\"Caption\">
+    <w:name w:val=\"caption\"/><w:basedOn w:val=\"Normal\"/>
+    <w:rPr><w:i/><w:sz w:val=\"18\"/></w:rPr>
+  </w:style>
+  <w:style w:type=\"character\" w:styleId=\"CodeChar\">
+    <w:name w:val=\"Code Char\"/>
+    <w:rPr><w:rFonts w:ascii=\"Courier New\" w:hAnsi=\"Courier New\" w:cs=\"Courier New\"/></w:rPr>
+  </w:style>
+  <w:style w:type=\"character\" w:styleId=\"Hyperlink\">
+    <w:name w:val=\"Hyperlink\"/>
+    <w:rPr><w:color w:val=\"0563C1\"/><w:u w:val=\"single\"/></w:rPr>
+  </w:style>
+</w:styles>
+";
+
+const ABSTRACT_NUMBERING: &str = "  <w:abstractNum w:abstractNumId=\"0\">
+    <w:multiLevelType w:val=\"singleLevel\"/>
+    <w:lvl w:ilvl=\"0\"><w:start w:val=\"1\"/><w:numFmt w:val=\"bullet\"/><w:lvlText w:val=\"\u{2022}\"/><w:lvlJc w:val=\"left\"/><w:pPr><w:ind w:left=\"720\" w:hanging=\"360\"/></w:pPr></w:lvl>
+  </w:abstractNum>
+  <w:abstractNum w:abstractNumId=\"1\">
+    <w:multiLevelType w:val=\"singleLevel\"/>
+    <w:lvl w:ilvl=\"0\"><w:start w:val=\"1\"/><w:numFmt w:val=\"decimal\"/><w:lvlText w:val=\"%1.\"/><w:lvlJc w:val=\"left\"/><w:pPr><w:ind w:left=\"720\" w:hanging=\"360\"/></w:pPr></w:lvl>
+  </w:abstractNum>
+";
+
+struct DocxImage {
+    // The file name in `word/media`.
+    name: String,
+    data: Vec<u8>,
+}
+
+struct DocxState<'a> {
+    links: Vec<String>,
+    images: Vec<DocxImage>,
+    ordered_lists: usize,
+    base_path: &'a Path,
+}
+
+// Escaped for XML, leaving out the C0 control characters XML 1.0 doesn't allow even as
+// character references. Tabs and line breaks are allowed.
+fn escape_xml(s: &str) -> String {
+    let allowed = s
+        .chars()
+        .filter(|c| *c >= ' ' || matches!(c, '\t' | '\n' | '\r'))
+        .collect::<String>();
+
+    escape(&allowed)
+}
+
+fn render_text_run(text: &str, properties: &str) -> String {
+    let properties = match properties.is_empty() {
+        true => "".to_string(),
+        false => format!("<w:rPr>{}</w:rPr>", properties),
+    };
+
+    format!(
+        "<w:r>{}<w:t xml:space=\"preserve\">{}</w:t></w:r>",
+        properties,
+        escape_xml(text)
+    )
+}
+
+fn span_properties(style: &Style) -> String {
+    vec![
+        (style.has_class("code"), "<w:rStyle w:val=\"CodeChar\"/>"),
+        (style.has_class("b"), "<w:b/>"),
+        (style.has_class("i"), "<w:i/>"),
+        (style.has_class("s"), "<w:strike/>"),
+    ]
+    .into_iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, p)| p)
+    .collect()
+}
+
+// Runs in a link take the hyperlink character style, unless they are code (a run has one
+// character style).
+fn link_properties(style: &Style) -> String {
+    match style.has_class("code") {
+        true => span_properties(style),
+        false => format!("<w:rStyle w:val=\"Hyperlink\"/>{}", span_properties(style)),
+    }
+}
+
+fn render_hyperlink(url: String, runs: String, state: &mut DocxState<'_>) -> String {
+    state.links.push(url);
+
+    format!(
+        "<w:hyperlink r:id=\"rId{}\">{}</w:hyperlink>",
+        FIRST_LINK_ID + state.links.len() - 1,
+        runs
+    )
+}
+
+fn render_inline_content(content: Vec<InlineContent>, state: &mut DocxState<'_>) -> String {
+    InlineRun::group_by_link(InlineContent::runs(content))
+        .into_iter()
+        .map(|(link, runs)| match link {
+            Some(url) => {
+                let runs = runs
+                    .iter()
+                    .map(|r| render_text_run(&r.text, &link_properties(&r.style)))
+                    .collect::<Vec<String>>()
+                    .join("");
+
+                render_hyperlink(url, runs, state)
+            }
+            None => runs
+                .iter()
+                .map(|r| render_text_run(&r.text, &span_properties(&r.style)))
+                .collect::<Vec<String>>()
+                .join(""),
+        })
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+fn render_paragraph_with(properties: &str, runs: String) -> String {
+    format!("<w:p><w:pPr>{}</w:pPr>{}</w:p>", properties, runs)
+}
+
+fn render_header(block: HeaderBlock, state: &mut DocxState<'_>) -> String {
+    let style = match block.level {
+        HeaderLevel::H1 => "Heading1",
+        HeaderLevel::H2 => "Heading2",
+        HeaderLevel::H3 => "Heading3",
+        HeaderLevel::H4 => "Heading4",
+        HeaderLevel::H5 => "Heading5",
+        HeaderLevel::H6 => "Heading6",
+    };
+
+    render_paragraph_with(
+        &format!("<w:pStyle w:val=\"{}\"/>", style),
+        render_inline_content(block.content, state),
+    )
+}
+
+fn render_paragraph(block: ParagraphBlock, state: &mut DocxState<'_>) -> String {
+    format!("<w:p>{}</w:p>", render_inline_content(block.content, state))
+}
+
+fn render_code_block(block: CodeBlock) -> String {
+    // One paragraph per line, runs do not keep line breaks.
+    block
+        .content
+        .split('\n')
+        .map(|l| {
+            render_paragraph_with(
+                "<w:pStyle w:val=\"Code\"/>",
+                render_text_run(&l.replace('\t', "    "), ""),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+fn render_list_block(block: ListBlock, state: &mut DocxState<'_>) -> String {
+    let num_id = match block.ordered {
+        true => {
+            state.ordered_lists += 1;
+            BULLET_NUM_ID + state.ordered_lists
+        }
+        false => BULLET_NUM_ID,
+    };
+
+    block
+        .items
+        .into_iter()
+        .map(|i| {
+            render_paragraph_with(
+                &format!(
+                    "<w:pStyle w:val=\"ListParagraph\"/><w:numPr><w:ilvl w:val=\"0\"/><w:numId w:val=\"{}\"/></w:numPr>",
+                    num_id
+                ),
+                render_inline_content(i.content, state),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+// The extension the image is stored with and its size in pixels.
+fn image_info(data: &[u8]) -> Option<(&'static str, u64, u64)> {
+    let read_u16 = |i: usize| Some(u16::from_be_bytes([*data.get(i)?, *data.get(i + 1)?]) as u64);
+    let read_u32 = |i: usize| Some(u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?) as u64);
+
+    match data.get(0..8)? {
+        b"\x89PNG\r\n\x1a\n" => Some(("png", read_u32(16)?, read_u32(20)?)),
+        [0xFF, 0xD8, ..] => {
+            let mut i = 2;
+
+            // Walk the markers until a start of frame gives the dimensions.
+            while *data.get(i)? == 0xFF {
+                let marker = *data.get(i + 1)?;
+
+                if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+                    return Some(("jpeg", read_u16(i + 7)?, read_u16(i + 5)?));
+                }
+
+                i += 2 + read_u16(i + 2)? as usize;
+            }
+
+            None
+        }
+        _ => None,
+    }
+}
+
+fn image_width(value: &Option<String>, natural: u64) -> u64 {
+    let v = match value {
+        Some(v) => v.trim(),
+        None => return natural.min(TEXT_WIDTH),
+    };
+
+    let width = match v.strip_suffix('%') {
+        Some(p) => p
+            .trim()
+            .parse::<f64>()
+            .map(|p| p / 100.0 * TEXT_WIDTH as f64),
+        None => v
+            .trim_end_matches("px")
+            .trim()
+            .parse::<f64>()
+            .map(|px| px * EMU_PER_PIXEL as f64),
+    };
+
+    width.map(|w| w as u64).unwrap_or(natural).min(TEXT_WIDTH)
+}
+
+fn render_drawing(block: &ImageBlock, relationship: &str, size: (u64, u64), id: usize) -> String {
+    format!(
+        "<w:r><w:drawing><wp:inline distT=\"0\" distB=\"0\" distL=\"0\" distR=\"0\"><wp:extent cx=\"{cx}\" cy=\"{cy}\"/><wp:docPr id=\"{id}\" name=\"Picture {id}\" descr=\"{descr}\"/><a:graphic xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\"><a:graphicData uri=\"http://schemas.openxmlformats.org/drawingml/2006/picture\"><pic:pic xmlns:pic=\"http://schemas.openxmlformats.org/drawingml/2006/picture\"><pic:nvPicPr><pic:cNvPr id=\"{id}\" name=\"{name}\"/><pic:cNvPicPr/></pic:nvPicPr><pic:blipFill><a:blip r:embed=\"{relationship}\"/><a:stretch><a:fillRect/></a:stretch></pic:blipFill><pic:spPr><a:xfrm><a:off x=\"0\" y=\"0\"/><a:ext cx=\"{cx}\" cy=\"{cy}\"/></a:xfrm><a:prstGeom prst=\"rect\"><a:avLst/></a:prstGeom></pic:spPr></pic:pic></a:graphicData></a:graphic></wp:inline></w:drawing></w:r>",
+        cx = size.0,
+        cy = size.1,
+        id = id,
+        descr = escape_xml(&block.alt_text),
+        name = escape_xml(&block.source),
+        relationship = relationship,
+    )
+}
+
+// Remote images can't be embedded, a captioned link to the source is written instead.
+fn render_image_link(block: ImageBlock, state: &mut DocxState<'_>) -> String {
+    let text = match (block.alt_text.is_empty(), block.title.is_empty()) {
+        (false, _) => block.alt_text,
+        (true, false) => block.title,
+        (true, true) => block.source.clone(),
+    };
+
+    render_paragraph_with(
+        "<w:pStyle w:val=\"Caption\"/>",
+        render_hyperlink(
+            block.source,
+            render_text_run(
+                &format!("[Image: {}]", text),
+                &link_properties(&Style::Default),
+            ),
+            state,
+        ),
+    )
+}
+
+fn render_image_block(block: ImageBlock, state: &mut DocxState<'_>) -> Result<String, String> {
+    if is_remote(&block.source) {
+        return Ok(render_image_link(block, state));
+    }
+
+    let path = state.base_path.join(&block.source);
+    let data =
+        fs::read(&path).map_err(|e| format!("Could not read image `{}`: {}", path.display(), e))?;
+    let (extension, width, height) = image_info(&data).ok_or_else(|| {
+        format!(
+            "Unsupported image format `{}` (only PNG and JPEG are supported)",
+            path.display()
+        )
+    })?;
+
+    // The height follows the width to keep the aspect ratio.
+    let cx = image_width(&block.width, width * EMU_PER_PIXEL);
+    let cy = cx * height / width.max(1);
+
+    let id = state.images.len() + 1;
+    state.images.push(DocxImage {
+        name: format!("image{}.{}", id, extension),
+        data,
+    });
+
+    let picture = render_paragraph_with(
+        "",
+        render_drawing(&block, &format!("rIdImage{}", id), (cx, cy), id),
+    );
+
+    Ok(match block.title.is_empty() {
+        true => picture,
+        false => format!(
+            "{}{}",
+            picture,
+            render_paragraph_with(
+                "<w:pStyle w:val=\"Caption\"/>",
+                render_text_run(&block.title, "")
+            )
+        ),
+    })
+}
+
+fn render_body(blocks: Vec<Block>, state: &mut DocxState<'_>) -> Result<String, String> {
+    Ok(blocks
+        .into_iter()
+        .map(|b| {
+            Ok(match b {
+                Block::Header(h) => render_header(h, state),
+                Block::Paragraph(p) => render_paragraph(p, state),
+                Block::Code(c) => render_code_block(c),
+                Block::List(l) => render_list_block(l, state),
+                Block::Image(i) => render_image_block(i, state)?,
+                Block::Custom(c) => render_body(c.content, state)?,
+            })
+        })
+        .collect::<Result<Vec<String>, String>>()?
+        .join("\n"))
+}
+
+fn render_document_part(blocks: Vec<Block>, state: &mut DocxState<'_>) -> Result<String, String> {
+    let body = render_body(blocks, state)?;
+
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>
+<w:document xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\" xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\" xmlns:wp=\"http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing\">
+<w:body>
+{}
+<w:sectPr><w:pgSz w:w=\"11906\" w:h=\"16838\"/><w:pgMar w:top=\"1440\" w:right=\"1440\" w:bottom=\"1440\" w:left=\"1440\" w:header=\"708\" w:footer=\"708\" w:gutter=\"0\"/></w:sectPr>
+</w:body>
+</w:document>
+",
+        body
+    ))
+}
+
+fn render_numbering_part(state: &DocxState) -> String {
+    let ordered = (0..state.ordered_lists)
+        .map(|i| {
+            format!(
+                "  <w:num w:numId=\"{}\"><w:abstractNumId w:val=\"1\"/><w:lvlOverride w:ilvl=\"0\"><w:startOverride w:val=\"1\"/></w:lvlOverride></w:num>\n",
+                BULLET_NUM_ID + i + 1
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>
+<w:numbering xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">
+{}  <w:num w:numId=\"{}\"><w:abstractNumId w:val=\"0\"/></w:num>
+{}</w:numbering>
+",
+        ABSTRACT_NUMBERING, BULLET_NUM_ID, ordered
+    )
+}
+
+fn render_document_rels(state: &DocxState) -> String {
+    let links = state
+        .links
+        .iter()
+        .enumerate()
+        .map(|(i, url)| {
+            format!(
+                "  <Relationship Id=\"rId{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink\" Target=\"{}\" TargetMode=\"External\"/>\n",
+                FIRST_LINK_ID + i,
+                escape_xml(url)
+            )
+        })
+        .collect::<String>();
+
+    let images = state
+        .images
+        .iter()
+        .enumerate()
+        .map(|(i, image)| {
+            format!(
+                "  <Relationship Id=\"rIdImage{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/image\" Target=\"media/{}\"/>\n",
+                i + 1,
+                image.name
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>
+<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">
+  <Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles\" Target=\"styles.xml\"/>
+  <Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering\" Target=\"numbering.xml\"/>
+{}{}</Relationships>
+",
+        links, images
+    )
+}
+
+// Local images are read relative to `base_path` and embedded in the document.
+pub fn write(blocks: Vec<Block>, base_path: &Path) -> Result<Vec<u8>, String> {
+    let mut state = DocxState {
+        links: vec![],
+        images: vec![],
+        ordered_lists: 0,
+        base_path,
+    };
+
+    let document = render_document_part(blocks, &mut state)?;
+
+    let mut zip = ZipWriter::default();
+
+    zip.add_file("[Content_Types].xml", CONTENT_TYPES.as_bytes())?;
+    zip.add_file("_rels/.rels", PACKAGE_RELS.as_bytes())?;
+    zip.add_file("word/document.xml", document.as_bytes())?;
+    zip.add_file("word/styles.xml", STYLES.as_bytes())?;
+    zip.add_file(
+        "word/numbering.xml",
+        render_numbering_part(&state).as_bytes(),
+    )?;
+    zip.add_file(
+        "word/_rels/document.xml.rels",
+        render_document_rels(&state).as_bytes(),
+    )?;
+
+    for image in &state.images {
+        zip.add_file(&format!("word/media/{}", image.name), &image.data)?;
+    }
+
+    Ok(zip.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A PNG signature and header for a 200x100 image, enough to be embedded.
+    fn png_header() -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend_from_slice(&200u32.to_be_bytes());
+        data.extend_from_slice(&100u32.to_be_bytes());
+        data.extend_from_slice(&[8, 2, 0, 0, 0]);
+        data
+    }
+
+    fn image(source: &str, title: &str, width: Option<&str>) -> Block {
+        Block::image(ImageBlock::new(
+            Style::Default,
+            source.to_string(),
+            title.to_string(),
+            "Alt".to_string(),
+            None,
+            width.map(|w| w.to_string()),
+        ))
+    }
+
+    fn contains(data: &[u8], s: &str) -> bool {
+        data.windows(s.len()).any(|w| w == s.as_bytes())
+    }
+
+    #[test]
+    fn code_style_properties_are_in_schema_order() {
+        let code = &STYLES[STYLES.find("w:styleId=\"Code\"").unwrap()..];
+        let properties = &code[..code.find("</w:pPr>").unwrap()];
+
+        assert!(properties.find("<w:shd").unwrap() < properties.find("<w:spacing").unwrap());
+    }
+
+    #[test]
+    fn control_characters_are_left_out_of_text() {
+        assert_eq!(
+            render_text_run("a\u{0}b\u{8}\u{B}\u{C}\u{1B}[1m\tc\n<d>", ""),
+            "<w:r><w:t xml:space=\"preserve\">ab[1m\tc\n&lt;d&gt;</w:t></w:r>"
+        );
+    }
+
+    #[test]
+    fn local_images_are_embedded() {
+        let dir = std::env::temp_dir().join(format!("rdom-docx-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.png"), png_header()).unwrap();
+
+        let docx = write(
+            vec![
+                image("a.png", "A caption", None),
+                image("a.png", "", Some("50%")),
+            ],
+            &dir,
+        )
+        .unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(contains(&docx, "word/media/image1.png"));
+        assert!(contains(&docx, "word/media/image2.png"));
+        assert!(contains(
+            &docx,
+            "<Relationship Id=\"rIdImage1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/image\" Target=\"media/image1.png\"/>"
+        ));
+        // 200x100 pixels at 9525 EMU each, then half the text width.
+        assert!(contains(&docx, "<wp:extent cx=\"1905000\" cy=\"952500\"/>"));
+        assert!(contains(
+            &docx,
+            "<wp:extent cx=\"2865755\" cy=\"1432877\"/>"
+        ));
+        assert!(contains(&docx, "<a:blip r:embed=\"rIdImage2\"/>"));
+        assert!(contains(
+            &docx,
+            "<w:pStyle w:val=\"Caption\"/></w:pPr><w:r><w:t xml:space=\"preserve\">A caption</w:t>"
+        ));
+        assert!(!contains(&docx, "[Image:"));
+    }
+
+    #[test]
+    fn remote_images_are_linked() {
+        let docx = write(vec![image("https://a.b/c.png", "", None)], Path::new("")).unwrap();
+
+        assert!(contains(&docx, "[Image: Alt]"));
+        assert!(contains(
+            &docx,
+            "Target=\"https://a.b/c.png\" TargetMode=\"External\""
+        ));
+        assert!(!contains(&docx, "word/media/"));
+    }
+
+    #[test]
+    fn missing_and_unsupported_images_are_errors() {
+        let missing = write(
+            vec![image("missing.png", "", None)],
+            Path::new("/nonexistent"),
+        );
+        assert!(missing.unwrap_err().starts_with("Could not read image"));
+
+        assert_eq!(image_info(b"GIF89a\0\0\0\0"), None);
+        assert_eq!(
+            image_info(&[0xFF, 0xD8, 0xFF, 0xC0, 0, 17, 8, 0, 30, 0, 40, 3]),
+            Some(("jpeg", 40, 30))
+        );
+        assert_eq!(image_info(&[0xFF, 0xD8, 0xFF, 0xC0, 0, 17]), None);
+    }
+}
@@ -0,0 +1,567 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    core::{
+        documents::{Block, HeaderBlock, HeaderLevel, InlineContent},
+        zip::ZipWriter,
+    },
+    parsing::transclusion,
+    rendering::html,
+};
+
+pub struct EpubChapter {
+    blocks: Vec<Block>,
+    front_matter: HashMap<String, String>,
+    base_path: PathBuf,
+}
+
+struct NavEntry {
+    title: String,
+    href: String,
+    children: Vec<NavEntry>,
+}
+
+struct ImageResource {
+    id: String,
+    href: String,
+    media_type: &'static str,
+}
+
+const CONTAINER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">
+  <rootfiles>
+    <rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>
+  </rootfiles>
+</container>
+";
+
+impl EpubChapter {
+    pub fn new(
+        blocks: Vec<Block>,
+        front_matter: HashMap<String, String>,
+        base_path: PathBuf,
+    ) -> EpubChapter {
+        EpubChapter {
+            blocks,
+            front_matter,
+            base_path,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<EpubChapter, String> {
+        let document = transclusion::load_document(path)
+            .map_err(|e| format!("Could not build `{}`: {}", path.display(), e))?;
+
+        Ok(EpubChapter::new(
+            document.blocks,
+            document.front_matter,
+            path.parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+        ))
+    }
+
+    fn title(&self, index: usize) -> String {
+        self.blocks
+            .iter()
+            .find_map(|b| match b {
+                Block::Header(h) => Some(InlineContent::plain_text(&h.content)),
+                _ => None,
+            })
+            .or_else(|| self.front_matter.get("title").cloned())
+            .unwrap_or_else(|| format!("Chapter {}", index + 1))
+    }
+}
+
+fn media_type(path: &str) -> Option<&'static str> {
+    match Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .as_deref()
+    {
+        Some("png") => Some("image/png"),
+        Some("jpg") | Some("jpeg") => Some("image/jpeg"),
+        Some("gif") => Some("image/gif"),
+        Some("svg") => Some("image/svg+xml"),
+        Some("webp") => Some("image/webp"),
+        _ => None,
+    }
+}
+
+// Hrefs are URLs, so file names are percent-encoded except for unreserved characters and `/`.
+fn encode_href(path: &str) -> String {
+    path.bytes()
+        .map(|b| {
+            let unreserved = b.is_ascii_alphanumeric() || b"-._~/".contains(&b);
+            match unreserved {
+                true => (b as char).to_string(),
+                false => format!("%{:02X}", b),
+            }
+        })
+        .collect()
+}
+
+// Days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+
+    (yoe + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        (time % 3600) / 60,
+        time % 60
+    )
+}
+
+fn inline_image_sources(content: &mut [InlineContent]) -> Vec<&mut String> {
+    content
+        .iter_mut()
+        .flat_map(|c| match c {
+            InlineContent::Image(i) => vec![&mut i.source],
+            InlineContent::Span(s) => inline_image_sources(&mut s.content),
+            InlineContent::Link(l) => inline_image_sources(&mut l.content),
+            _ => vec![],
+        })
+        .collect()
+}
+
+// The sources of local images, including those inside text and in the blocks made by
+// extensions.
+fn local_images(blocks: &mut [Block]) -> Vec<&mut String> {
+    blocks
+        .iter_mut()
+        .flat_map(|b| match b {
+            Block::Image(i) => vec![&mut i.source],
+            Block::Header(h) => inline_image_sources(&mut h.content),
+            Block::Paragraph(p) => inline_image_sources(&mut p.content),
+            Block::List(l) => l
+                .items
+                .iter_mut()
+                .flat_map(|i| inline_image_sources(&mut i.content))
+                .collect(),
+            Block::Custom(c) => local_images(&mut c.content),
+            Block::Code(_) => vec![],
+        })
+        .filter(|source| !html::is_remote(source))
+        .collect()
+}
+
+fn package_images(
+    chapter: &mut EpubChapter,
+    zip: &mut ZipWriter,
+    images: &mut Vec<ImageResource>,
+    packaged: &mut HashMap<PathBuf, String>,
+) -> Result<(), String> {
+    for source in local_images(&mut chapter.blocks) {
+        let path = chapter.base_path.join(source.as_str());
+
+        if let Some(href) = packaged.get(&path) {
+            *source = href.clone();
+            continue;
+        }
+
+        let media_type =
+            media_type(source).ok_or_else(|| format!("Unsupported image type `{}`", source))?;
+
+        let data = fs::read(&path)
+            .map_err(|e| format!("Could not read image `{}`: {}", path.display(), e))?;
+
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("image");
+
+        // Prefixed with the index as images from different chapters may share a name.
+        let name = format!("images/{}-{}", images.len() + 1, file_name);
+        let href = encode_href(&name);
+
+        zip.add_file(&format!("OEBPS/{}", name), &data)?;
+
+        images.push(ImageResource {
+            id: format!("image-{}", images.len() + 1),
+            href: href.clone(),
+            media_type,
+        });
+
+        packaged.insert(path, href.clone());
+        *source = href;
+    }
+
+    Ok(())
+}
+
+// Headers inside custom blocks are rendered too, so they are walked in the same order to get
+// the ids `html::render` gives them.
+fn indexed_headers(blocks: &[Block]) -> Vec<&HeaderBlock> {
+    blocks
+        .iter()
+        .flat_map(|b| match b {
+            Block::Header(h) if h.indexed => vec![h],
+            Block::Custom(c) => indexed_headers(&c.content),
+            _ => vec![],
+        })
+        .collect()
+}
+
+fn nav_entries(blocks: &[Block], href: &str) -> Vec<NavEntry> {
+    let mut entries = Vec::<NavEntry>::new();
+    let mut ids = html::HeaderIds::default();
+
+    for header in indexed_headers(blocks) {
+        let title = InlineContent::plain_text(&header.content);
+
+        let entry = NavEntry {
+            href: format!("{}#{}", href, ids.id(&title)),
+            title,
+            children: vec![],
+        };
+
+        match (&header.level, entries.last_mut()) {
+            (HeaderLevel::H2, Some(parent)) => parent.children.push(entry),
+            _ => entries.push(entry),
+        }
+    }
+
+    entries
+}
+
+fn render_nav_entries(entries: &[NavEntry], indent: &str) -> String {
+    entries
+        .iter()
+        .map(|e| {
+            let children = match e.children.is_empty() {
+                true => "".to_string(),
+                false => format!(
+                    "\n{}  <ol>\n{}\n{}  </ol>\n{}",
+                    indent,
+                    render_nav_entries(&e.children, &format!("{}    ", indent)),
+                    indent,
+                    indent
+                ),
+            };
+
+            format!(
+                "{}<li><a href=\"{}\">{}</a>{}</li>",
+                indent,
+                html::escape(&e.href),
+                html::escape(&e.title),
+                children
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn render_xhtml(title: &str, language: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE html>
+<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"{}\" xml:lang=\"{}\">
+<head>
+  <meta charset=\"UTF-8\"/>
+  <title>{}</title>
+</head>
+<body>
+{}
+</body>
+</html>
+",
+        html::escape(language),
+        html::escape(language),
+        html::escape(title),
+        body
+    )
+}
+
+fn render_opf(
+    metadata: &HashMap<String, String>,
+    chapters: &[(String, String)],
+    images: &[ImageResource],
+) -> String {
+    let get = |key: &str| metadata.get(key).map(|v| html::escape(v));
+
+    let title = get("title").unwrap_or_else(|| "Untitled".to_string());
+    let language = get("language").unwrap_or_else(|| "en".to_string());
+    let identifier = get("identifier").unwrap_or_else(|| {
+        let raw_title = metadata
+            .get("title")
+            .map(|t| t.as_str())
+            .unwrap_or("Untitled");
+        format!("urn:rdom:{}", html::slug(raw_title))
+    });
+
+    let optional = vec![
+        get("author").map(|v| format!("    <dc:creator>{}</dc:creator>", v)),
+        get("publisher").map(|v| format!("    <dc:publisher>{}</dc:publisher>", v)),
+        get("description").map(|v| format!("    <dc:description>{}</dc:description>", v)),
+        get("date").map(|v| format!("    <dc:date>{}</dc:date>", v)),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|l| format!("{}\n", l))
+    .collect::<String>();
+
+    let chapter_items = chapters
+        .iter()
+        .map(|(id, href)| {
+            format!(
+                "    <item id=\"{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+                id, href
+            )
+        })
+        .collect::<String>();
+
+    let image_items = images
+        .iter()
+        .map(|i| {
+            format!(
+                "    <item id=\"{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                i.id,
+                html::escape(&i.href),
+                i.media_type
+            )
+        })
+        .collect::<String>();
+
+    let spine = chapters
+        .iter()
+        .map(|(id, _)| format!("    <itemref idref=\"{}\"/>\n", id))
+        .collect::<String>();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"{}\">
+  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+    <dc:identifier id=\"book-id\">{}</dc:identifier>
+    <dc:title>{}</dc:title>
+    <dc:language>{}</dc:language>
+{}    <meta property=\"dcterms:modified\">{}</meta>
+  </metadata>
+  <manifest>
+    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>
+{}{}  </manifest>
+  <spine>
+{}  </spine>
+</package>
+",
+        language,
+        identifier,
+        title,
+        language,
+        optional,
+        timestamp(),
+        chapter_items,
+        image_items,
+        spine
+    )
+}
+
+// Book metadata (`title`, `author`, `language`, `identifier`, `publisher`, `description` and
+// `date`) is taken from the front matter of the first chapter.
+pub fn write(chapters: Vec<EpubChapter>) -> Result<Vec<u8>, String> {
+    if chapters.is_empty() {
+        return Err("An EPUB needs at least one chapter".to_string());
+    }
+
+    let metadata = chapters[0].front_matter.clone();
+    let language = metadata
+        .get("language")
+        .cloned()
+        .unwrap_or_else(|| "en".to_string());
+
+    let mut zip = ZipWriter::default();
+
+    // The mimetype has to be the first entry and stored uncompressed.
+    zip.add_file("mimetype", b"application/epub+zip")?;
+    zip.add_file("META-INF/container.xml", CONTAINER.as_bytes())?;
+
+    let mut images = Vec::<ImageResource>::new();
+    let mut packaged = HashMap::<PathBuf, String>::new();
+    let mut manifest = Vec::<(String, String)>::new();
+    let mut nav = Vec::<NavEntry>::new();
+
+    for (i, mut chapter) in chapters.into_iter().enumerate() {
+        package_images(&mut chapter, &mut zip, &mut images, &mut packaged)?;
+
+        let id = format!("chapter-{}", i + 1);
+        let href = format!("{}.xhtml", id);
+        let title = chapter.title(i);
+
+        // The first header is the chapter entry itself, so it is not repeated below it.
+        let mut entries = nav_entries(&chapter.blocks, &href);
+        let children = match entries.first().map(|e| e.title == title) {
+            Some(true) => {
+                let first = entries.remove(0);
+                first.children.into_iter().chain(entries).collect()
+            }
+            _ => entries,
+        };
+
+        nav.push(NavEntry {
+            title: title.clone(),
+            href: href.clone(),
+            children,
+        });
+
+        let body = html::render(chapter.blocks).join("\n");
+
+        zip.add_file(
+            &format!("OEBPS/{}", href),
+            render_xhtml(&title, &language, &body).as_bytes(),
+        )?;
+
+        manifest.push((id, href));
+    }
+
+    let nav_body = format!(
+        "<nav epub:type=\"toc\" id=\"toc\">\n  <h1>Contents</h1>\n  <ol>\n{}\n  </ol>\n</nav>",
+        render_nav_entries(&nav, "    ")
+    );
+
+    let title = metadata
+        .get("title")
+        .cloned()
+        .unwrap_or_else(|| "Contents".to_string());
+
+    zip.add_file(
+        "OEBPS/nav.xhtml",
+        render_xhtml(&title, &language, &nav_body).as_bytes(),
+    )?;
+
+    zip.add_file(
+        "OEBPS/content.opf",
+        render_opf(&metadata, &manifest, &images).as_bytes(),
+    )?;
+
+    Ok(zip.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::{ImageBlock, InlineText, Style};
+
+    fn header(level: HeaderLevel, title: &str) -> Block {
+        Block::header(HeaderBlock::new(
+            Style::Default,
+            level,
+            vec![InlineContent::Text(InlineText::new(title.to_string()))],
+            true,
+        ))
+    }
+
+    fn contains(data: &[u8], s: &str) -> bool {
+        data.windows(s.len()).any(|w| w == s.as_bytes())
+    }
+
+    #[test]
+    fn nav_links_match_header_ids() {
+        let blocks = vec![
+            header(HeaderLevel::H1, "Guide"),
+            header(HeaderLevel::H2, "Usage"),
+            header(HeaderLevel::H1, "Reference"),
+            header(HeaderLevel::H2, "Usage"),
+            header(HeaderLevel::H2, "!!"),
+        ];
+
+        let epub = write(vec![EpubChapter::new(
+            blocks,
+            HashMap::from([("title".to_string(), "Book & Co".to_string())]),
+            PathBuf::new(),
+        )])
+        .unwrap();
+
+        assert_eq!(&epub[30..58], b"mimetypeapplication/epub+zip");
+        assert!(contains(&epub, "<dc:title>Book &amp; Co</dc:title>"));
+        assert!(contains(&epub, ">urn:rdom:book-co</dc:identifier>"));
+        assert!(contains(&epub, "<h2 id='usage-2'>Usage</h2>"));
+        assert!(contains(&epub, "<h2 id='section'>!!</h2>"));
+        assert!(contains(
+            &epub,
+            "<li><a href=\"chapter-1.xhtml#usage\">Usage</a></li>"
+        ));
+        assert!(contains(
+            &epub,
+            "<li><a href=\"chapter-1.xhtml#usage-2\">Usage</a></li>"
+        ));
+        assert!(contains(
+            &epub,
+            "<li><a href=\"chapter-1.xhtml#section\">!!</a></li>"
+        ));
+    }
+
+    #[test]
+    fn images_are_packaged_once() {
+        let dir = std::env::temp_dir().join(format!("rdom-epub-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.png"), b"\x89PNG").unwrap();
+        fs::write(dir.join("a b.png"), b"\x89PNG").unwrap();
+
+        let image = |source: &str| {
+            Block::image(ImageBlock::new(
+                Style::Default,
+                source.to_string(),
+                "".to_string(),
+                "".to_string(),
+                None,
+                None,
+            ))
+        };
+        let chapter = || EpubChapter::new(vec![image("a.png")], HashMap::new(), dir.clone());
+
+        let epub = write(vec![chapter(), chapter()]).unwrap();
+        let spaced = write(vec![EpubChapter::new(
+            vec![image("a b.png")],
+            HashMap::new(),
+            dir.clone(),
+        )])
+        .unwrap();
+        let missing = write(vec![EpubChapter::new(
+            vec![image("a.png")],
+            HashMap::new(),
+            dir.join("missing"),
+        )]);
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(contains(
+            &epub,
+            "<item id=\"image-1\" href=\"images/1-a.png\" media-type=\"image/png\"/>"
+        ));
+        assert!(!contains(&epub, "images/2-a.png"));
+        assert!(contains(
+            &spaced,
+            "<item id=\"image-1\" href=\"images/1-a%20b.png\" media-type=\"image/png\"/>"
+        ));
+        assert!(contains(&spaced, "src='images/1-a%20b.png'"));
+        assert!(contains(&spaced, "OEBPS/images/1-a b.png"));
+        assert!(missing.unwrap_err().starts_with("Could not read image"));
+        assert_eq!(
+            write(vec![]).err(),
+            Some("An EPUB needs at least one chapter".to_string())
+        );
+    }
+}
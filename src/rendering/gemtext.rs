@@ -0,0 +1,215 @@
+use crate::core::documents::{
+    Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, InlineContent, ListBlock,
+    ParagraphBlock,
+};
+
+fn render_link_line(url: &str, text: &str) -> String {
+    match text.trim().is_empty() {
+        true => format!("=> {}", url),
+        false => format!("=> {} {}", url, text.trim()),
+    }
+}
+
+// Gemtext has no inline markup, so content is flattened and links are collected to be
+// written as link lines after the block.
+fn flatten_inline_content(content: Vec<InlineContent>, links: &mut Vec<String>) -> String {
+    content
+        .into_iter()
+        .map(|c| match c {
+            InlineContent::Text(t) => t.content,
+            InlineContent::Span(s) => flatten_inline_content(s.content, links),
+            InlineContent::Link(l) => {
+                let text = InlineContent::plain_text(&l.content);
+                links.push(render_link_line(&l.url, &text));
+                text
+            }
+            InlineContent::Image(i) => {
+                links.push(render_link_line(
+                    &i.source,
+                    image_text(&i.alt_text, &i.title),
+                ));
+                i.alt_text
+            }
+            InlineContent::Custom(c) => c.content,
+        })
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+// A text line starting like a link, heading, quote, list item or preformatting toggle
+// would be read as one, a leading space keeps it a text line.
+fn guard_line(line: &str) -> String {
+    match ["=>", "#", ">", "* ", "```"]
+        .iter()
+        .any(|p| line.starts_with(p))
+    {
+        true => format!(" {}", line),
+        false => line.to_string(),
+    }
+}
+
+fn guard_lines(text: &str) -> String {
+    text.split('\n')
+        .map(guard_line)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn with_links(text: String, links: Vec<String>) -> String {
+    match links.is_empty() {
+        true => text,
+        false => format!("{}\n{}", text, links.join("\n")),
+    }
+}
+
+fn render_header(block: HeaderBlock) -> String {
+    let prefix = match block.level {
+        HeaderLevel::H1 => "#",
+        HeaderLevel::H2 => "##",
+        // Gemtext only has three heading levels.
+        _ => "###",
+    };
+
+    let mut links = Vec::<String>::new();
+    let text = flatten_inline_content(block.content, &mut links);
+
+    // Headings are a single line.
+    with_links(format!("{} {}", prefix, text.replace('\n', " ")), links)
+}
+
+fn render_paragraph(block: ParagraphBlock) -> String {
+    let mut links = Vec::<String>::new();
+    let text = flatten_inline_content(block.content, &mut links);
+
+    with_links(guard_lines(&text), links)
+}
+
+fn render_code_block(block: CodeBlock) -> String {
+    // Only a line starting with the toggle ends preformatted text.
+    let content = block
+        .content
+        .split('\n')
+        .map(|l| match l.starts_with("```") {
+            true => format!(" {}", l),
+            false => l.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    format!(
+        "```{}\n{}\n```",
+        block.language.unwrap_or_default(),
+        content
+    )
+}
+
+fn render_list_block(block: ListBlock) -> String {
+    let mut links = Vec::<String>::new();
+
+    // Gemtext lists are unordered, numbers are kept in the text for ordered lists.
+    let items = block
+        .items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let text = flatten_inline_content(item.content, &mut links);
+            // The first line follows the marker, so only the lines after it are guarded.
+            let text = match text.split_once('\n') {
+                Some((first, rest)) => format!("{}\n{}", first, guard_lines(rest)),
+                None => text,
+            };
+            match block.ordered {
+                true => format!("* {}. {}", i + 1, text),
+                false => format!("* {}", text),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    with_links(items, links)
+}
+
+fn image_text<'a>(alt_text: &'a str, title: &'a str) -> &'a str {
+    match alt_text.is_empty() {
+        true => title,
+        false => alt_text,
+    }
+}
+
+fn render_image_block(block: ImageBlock) -> String {
+    render_link_line(&block.source, image_text(&block.alt_text, &block.title))
+}
+
+pub fn render(blocks: Vec<Block>) -> Vec<String> {
+    blocks
+        .into_iter()
+        .map(|b| match b {
+            Block::Header(h) => render_header(h),
+            Block::Paragraph(p) => render_paragraph(p),
+            Block::Code(c) => render_code_block(c),
+            Block::List(l) => render_list_block(l),
+            Block::Image(i) => render_image_block(i),
+            Block::Custom(c) => render(c.content).join("\n\n"),
+        })
+        .collect::<Vec<String>>()
+}
+
+pub fn render_document(blocks: Vec<Block>) -> String {
+    format!("{}\n", render(blocks).join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::{InlineText, ListItem, Style};
+
+    fn text(s: &str) -> Vec<InlineContent> {
+        vec![InlineContent::Text(InlineText::new(s.to_string()))]
+    }
+
+    fn paragraph(s: &str) -> String {
+        render(vec![Block::paragraph(ParagraphBlock::new(
+            Style::Default,
+            text(s),
+        ))])
+        .join("")
+    }
+
+    #[test]
+    fn line_types_in_text_are_guarded() {
+        assert_eq!(paragraph("=> not a link"), " => not a link");
+        assert_eq!(paragraph("# not a heading"), " # not a heading");
+        assert_eq!(paragraph("> not a quote"), " > not a quote");
+        assert_eq!(paragraph("* not an item"), " * not an item");
+        assert_eq!(paragraph("```not a toggle"), " ```not a toggle");
+        assert_eq!(paragraph("a\n=> b\n#c\nd > e"), "a\n => b\n #c\nd > e");
+        assert_eq!(paragraph("*emphasis* and =>"), "*emphasis* and =>");
+    }
+
+    #[test]
+    fn list_item_lines_are_guarded() {
+        let list = Block::list(ListBlock::new_unordered(
+            Style::Default,
+            vec![
+                ListItem::new(Style::Default, text("=> first")),
+                ListItem::new(Style::Default, text("one\n# two")),
+            ],
+        ));
+
+        assert_eq!(render(vec![list]).join(""), "* => first\n* one\n # two");
+    }
+
+    #[test]
+    fn toggles_in_code_are_guarded() {
+        let code = Block::code(CodeBlock::new(
+            Style::Default,
+            "```\ninside\n```".to_string(),
+            Some("markdown".to_string()),
+        ));
+
+        assert_eq!(
+            render(vec![code]).join(""),
+            "```markdown\n ```\ninside\n ```\n```"
+        );
+    }
+}
@@ -0,0 +1,1162 @@
+use super::html::escape;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenKind {
+    Attribute,
+    Comment,
+    Constant,
+    Function,
+    Keyword,
+    Number,
+    Operator,
+    Property,
+    Punctuation,
+    String,
+    Tag,
+    Text,
+    Type,
+    Variable,
+}
+
+pub trait Grammar {
+    // The language name and any aliases, as used in code fence info strings.
+    fn names(&self) -> Vec<String>;
+
+    fn tokenize(&self, code: &str) -> Vec<(TokenKind, String)>;
+}
+
+// A configurable lexer for languages made up of keywords, comments, strings and numbers,
+// which covers most programming and configuration languages.
+pub struct SimpleGrammar {
+    names: Vec<String>,
+    keywords: Vec<String>,
+    types: Vec<String>,
+    constants: Vec<String>,
+    line_comments: Vec<String>,
+    block_comment: Option<(String, String)>,
+    quotes: Vec<char>,
+    triple_quotes: bool,
+    char_literals: bool,
+    attribute_prefixes: Vec<String>,
+    variable_prefix: Option<char>,
+    key_suffix: Option<char>,
+    section_headers: bool,
+    capitalised_types: bool,
+    macros: bool,
+    dashed_identifiers: bool,
+}
+
+pub struct MarkupGrammar {
+    names: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct TokenStyle {
+    colour: String,
+    bold: bool,
+    italic: bool,
+}
+
+pub struct Theme {
+    background: String,
+    foreground: String,
+    line_highlight: String,
+    styles: Vec<(TokenKind, TokenStyle)>,
+}
+
+pub struct Highlighter {
+    grammars: Vec<Box<dyn Grammar>>,
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+fn starts_with_at(chars: &[char], i: usize, s: &str) -> bool {
+    s.chars()
+        .enumerate()
+        .all(|(k, c)| chars.get(i + k) == Some(&c))
+}
+
+fn push_token(tokens: &mut Vec<(TokenKind, String)>, kind: TokenKind, text: String) {
+    match tokens.last_mut() {
+        Some((k, t)) if *k == kind => t.push_str(&text),
+        _ => tokens.push((kind, text)),
+    }
+}
+
+fn collect(chars: &[char], start: usize, end: usize) -> String {
+    chars[start..end.min(chars.len())].iter().collect()
+}
+
+// Finds the end of a quoted string starting at `start`, honouring backslash escapes.
+fn string_end(chars: &[char], start: usize, quote: &str) -> usize {
+    let mut i = start + quote.chars().count();
+
+    while i < chars.len() {
+        if chars[i] == '\\' {
+            i += 2;
+            continue;
+        }
+        if starts_with_at(chars, i, quote) {
+            return i + quote.chars().count();
+        }
+        i += 1;
+    }
+
+    chars.len()
+}
+
+fn next_non_space(chars: &[char], from: usize) -> Option<char> {
+    chars[from.min(chars.len())..]
+        .iter()
+        .find(|c| **c != ' ' && **c != '\t')
+        .copied()
+}
+
+impl TokenKind {
+    pub fn class(&self) -> &'static str {
+        match self {
+            TokenKind::Attribute => "hl-attribute",
+            TokenKind::Comment => "hl-comment",
+            TokenKind::Constant => "hl-constant",
+            TokenKind::Function => "hl-function",
+            TokenKind::Keyword => "hl-keyword",
+            TokenKind::Number => "hl-number",
+            TokenKind::Operator => "hl-operator",
+            TokenKind::Property => "hl-property",
+            TokenKind::Punctuation => "hl-punctuation",
+            TokenKind::String => "hl-string",
+            TokenKind::Tag => "hl-tag",
+            TokenKind::Text => "hl-text",
+            TokenKind::Type => "hl-type",
+            TokenKind::Variable => "hl-variable",
+        }
+    }
+}
+
+impl SimpleGrammar {
+    pub fn new(names: &[&str]) -> SimpleGrammar {
+        SimpleGrammar {
+            names: to_strings(names),
+            keywords: vec![],
+            types: vec![],
+            constants: vec![],
+            line_comments: vec![],
+            block_comment: None,
+            quotes: vec!['"'],
+            triple_quotes: false,
+            char_literals: false,
+            attribute_prefixes: vec![],
+            variable_prefix: None,
+            key_suffix: None,
+            section_headers: false,
+            capitalised_types: false,
+            macros: false,
+            dashed_identifiers: false,
+        }
+    }
+
+    pub fn with_keywords(mut self, keywords: &[&str]) -> SimpleGrammar {
+        self.keywords = to_strings(keywords);
+        self
+    }
+
+    pub fn with_types(mut self, types: &[&str]) -> SimpleGrammar {
+        self.types = to_strings(types);
+        self
+    }
+
+    pub fn with_constants(mut self, constants: &[&str]) -> SimpleGrammar {
+        self.constants = to_strings(constants);
+        self
+    }
+
+    pub fn with_line_comment(mut self, start: &str) -> SimpleGrammar {
+        self.line_comments.push(start.to_string());
+        self
+    }
+
+    pub fn with_block_comment(mut self, start: &str, end: &str) -> SimpleGrammar {
+        self.block_comment = Some((start.to_string(), end.to_string()));
+        self
+    }
+
+    pub fn with_quotes(mut self, quotes: &[char]) -> SimpleGrammar {
+        self.quotes = quotes.to_vec();
+        self
+    }
+
+    // `"""` and `'''` strings, as in Python and TOML.
+    pub fn with_triple_quotes(mut self) -> SimpleGrammar {
+        self.triple_quotes = true;
+        self
+    }
+
+    // `'a'` is a character and `'a` a lifetime, as in Rust.
+    pub fn with_char_literals(mut self) -> SimpleGrammar {
+        self.char_literals = true;
+        self
+    }
+
+    // Attributes and decorators, `#[` runs to the matching `]`, anything else to the end
+    // of the following name.
+    pub fn with_attribute_prefix(mut self, prefix: &str) -> SimpleGrammar {
+        self.attribute_prefixes.push(prefix.to_string());
+        self
+    }
+
+    pub fn with_variable_prefix(mut self, prefix: char) -> SimpleGrammar {
+        self.variable_prefix = Some(prefix);
+        self
+    }
+
+    // Names and strings followed by this character are highlighted as keys.
+    pub fn with_key_suffix(mut self, suffix: char) -> SimpleGrammar {
+        self.key_suffix = Some(suffix);
+        self
+    }
+
+    // `[section]` lines, as in TOML and INI files.
+    pub fn with_section_headers(mut self) -> SimpleGrammar {
+        self.section_headers = true;
+        self
+    }
+
+    pub fn with_capitalised_types(mut self) -> SimpleGrammar {
+        self.capitalised_types = true;
+        self
+    }
+
+    // `name!(...)` macro invocations, as in Rust.
+    pub fn with_macros(mut self) -> SimpleGrammar {
+        self.macros = true;
+        self
+    }
+
+    pub fn with_dashed_identifiers(mut self) -> SimpleGrammar {
+        self.dashed_identifiers = true;
+        self
+    }
+
+    fn is_identifier_char(&self, c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || (self.dashed_identifiers && c == '-')
+    }
+
+    fn classify_identifier(&self, chars: &[char], word: &str, end: usize) -> TokenKind {
+        let next = chars.get(end).copied();
+
+        if self.keywords.iter().any(|k| k == word) {
+            return TokenKind::Keyword;
+        }
+        if self.constants.iter().any(|k| k == word) {
+            return TokenKind::Constant;
+        }
+        if self.types.iter().any(|k| k == word) {
+            return TokenKind::Type;
+        }
+        if self.key_suffix.is_some() && next_non_space(chars, end) == self.key_suffix {
+            return TokenKind::Property;
+        }
+
+        match (next, self.macros && next == Some('!')) {
+            (Some('('), _) => TokenKind::Function,
+            (_, true) => TokenKind::Function,
+            _ if self.capitalised_types && word.starts_with(char::is_uppercase) => TokenKind::Type,
+            _ => TokenKind::Text,
+        }
+    }
+}
+
+impl Grammar for SimpleGrammar {
+    fn names(&self) -> Vec<String> {
+        self.names.clone()
+    }
+
+    fn tokenize(&self, code: &str) -> Vec<(TokenKind, String)> {
+        let chars: Vec<char> = code.chars().collect();
+        let mut tokens = Vec::<(TokenKind, String)>::new();
+        let mut line_start = true;
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            let after_word = i > 0 && self.is_identifier_char(chars[i - 1]);
+
+            if c.is_whitespace() {
+                line_start = line_start || c == '\n';
+                push_token(&mut tokens, TokenKind::Text, c.to_string());
+                i += 1;
+                continue;
+            }
+
+            let at_line_start = line_start;
+            line_start = false;
+
+            let attribute = self
+                .attribute_prefixes
+                .iter()
+                .find(|p| starts_with_at(&chars, i, p));
+
+            if let Some(prefix) = attribute {
+                let end = match prefix.ends_with('[') {
+                    true => {
+                        let mut depth = 0;
+                        let mut j = i;
+                        while j < chars.len() {
+                            match chars[j] {
+                                '[' => depth += 1,
+                                ']' if depth == 1 => break,
+                                ']' => depth -= 1,
+                                _ => {}
+                            }
+                            j += 1;
+                        }
+                        j + 1
+                    }
+                    false => {
+                        let mut j = i + prefix.chars().count();
+                        while j < chars.len()
+                            && (self.is_identifier_char(chars[j]) || chars[j] == '.')
+                        {
+                            j += 1;
+                        }
+                        j
+                    }
+                };
+
+                push_token(&mut tokens, TokenKind::Attribute, collect(&chars, i, end));
+                i = end;
+                continue;
+            }
+
+            let line_comment = self
+                .line_comments
+                .iter()
+                .any(|l| starts_with_at(&chars, i, l));
+
+            if line_comment && !after_word {
+                let end = (i..chars.len())
+                    .find(|j| chars[*j] == '\n')
+                    .unwrap_or(chars.len());
+                push_token(&mut tokens, TokenKind::Comment, collect(&chars, i, end));
+                i = end;
+                continue;
+            }
+
+            if let Some((start, end)) = &self.block_comment {
+                if starts_with_at(&chars, i, start) {
+                    let close = (i + start.chars().count()..chars.len())
+                        .find(|j| starts_with_at(&chars, *j, end))
+                        .map(|j| j + end.chars().count())
+                        .unwrap_or(chars.len());
+                    push_token(&mut tokens, TokenKind::Comment, collect(&chars, i, close));
+                    i = close;
+                    continue;
+                }
+            }
+
+            if self.section_headers && at_line_start && c == '[' {
+                let end = (i..chars.len())
+                    .find(|j| chars[*j] == '\n')
+                    .unwrap_or(chars.len());
+                let close = (i..end).rfind(|j| chars[*j] == ']').map(|j| j + 1);
+                if let Some(close) = close {
+                    push_token(&mut tokens, TokenKind::Type, collect(&chars, i, close));
+                    i = close;
+                    continue;
+                }
+            }
+
+            if self.quotes.contains(&c) {
+                let triple: String = [c, c, c].iter().collect();
+
+                let end = match (
+                    self.triple_quotes && starts_with_at(&chars, i, &triple),
+                    self.char_literals && c == '\'',
+                ) {
+                    (true, _) => string_end(&chars, i, &triple),
+                    (false, true) => {
+                        match chars.get(i + 1) == Some(&'\\') || chars.get(i + 2) == Some(&'\'') {
+                            true => string_end(&chars, i, "'"),
+                            false => {
+                                // A lifetime or label.
+                                let mut j = i + 1;
+                                while j < chars.len() && self.is_identifier_char(chars[j]) {
+                                    j += 1;
+                                }
+                                push_token(&mut tokens, TokenKind::Type, collect(&chars, i, j));
+                                i = j;
+                                continue;
+                            }
+                        }
+                    }
+                    _ => string_end(&chars, i, &c.to_string()),
+                };
+
+                let kind = match self.key_suffix.is_some()
+                    && next_non_space(&chars, end) == self.key_suffix
+                {
+                    true => TokenKind::Property,
+                    false => TokenKind::String,
+                };
+
+                push_token(&mut tokens, kind, collect(&chars, i, end));
+                i = end;
+                continue;
+            }
+
+            if Some(c) == self.variable_prefix {
+                let end = match chars.get(i + 1) {
+                    Some('{') => (i..chars.len())
+                        .find(|j| chars[*j] == '}')
+                        .map(|j| j + 1)
+                        .unwrap_or(chars.len()),
+                    Some(n) if n.is_alphabetic() || *n == '_' => {
+                        let mut j = i + 1;
+                        while j < chars.len() && (chars[j].is_alphanumeric() || chars[j] == '_') {
+                            j += 1;
+                        }
+                        j
+                    }
+                    Some(n) if n.is_ascii_digit() || "@#?*!$-".contains(*n) => i + 2,
+                    _ => i + 1,
+                };
+
+                let kind = match end > i + 1 {
+                    true => TokenKind::Variable,
+                    false => TokenKind::Operator,
+                };
+                push_token(&mut tokens, kind, collect(&chars, i, end));
+                i = end;
+                continue;
+            }
+
+            if c.is_ascii_digit() && !after_word {
+                let mut j = i;
+                while j < chars.len()
+                    && (chars[j].is_alphanumeric()
+                        || chars[j] == '_'
+                        || (chars[j] == '.'
+                            && chars
+                                .get(j + 1)
+                                .map(|n| n.is_ascii_digit())
+                                .unwrap_or(false)))
+                {
+                    j += 1;
+                }
+                push_token(&mut tokens, TokenKind::Number, collect(&chars, i, j));
+                i = j;
+                continue;
+            }
+
+            if c.is_alphabetic() || c == '_' {
+                let mut j = i;
+                while j < chars.len() && self.is_identifier_char(chars[j]) {
+                    j += 1;
+                }
+                let word = collect(&chars, i, j);
+                let kind = self.classify_identifier(&chars, &word, j);
+                push_token(&mut tokens, kind, word);
+                i = j;
+                continue;
+            }
+
+            let kind = match c {
+                c if "+-*/%=<>!&|^~?:".contains(c) => TokenKind::Operator,
+                c if "()[]{},;.".contains(c) => TokenKind::Punctuation,
+                _ => TokenKind::Text,
+            };
+            push_token(&mut tokens, kind, c.to_string());
+            i += 1;
+        }
+
+        tokens
+    }
+}
+
+impl MarkupGrammar {
+    pub fn new(names: &[&str]) -> MarkupGrammar {
+        MarkupGrammar {
+            names: to_strings(names),
+        }
+    }
+}
+
+impl Grammar for MarkupGrammar {
+    fn names(&self) -> Vec<String> {
+        self.names.clone()
+    }
+
+    fn tokenize(&self, code: &str) -> Vec<(TokenKind, String)> {
+        let chars: Vec<char> = code.chars().collect();
+        let mut tokens = Vec::<(TokenKind, String)>::new();
+        let mut i = 0;
+
+        let find = |from: usize, s: &str| {
+            (from..chars.len())
+                .find(|j| starts_with_at(&chars, *j, s))
+                .map(|j| j + s.chars().count())
+                .unwrap_or(chars.len())
+        };
+
+        while i < chars.len() {
+            let c = chars[i];
+
+            if starts_with_at(&chars, i, "<!--") {
+                let end = find(i + 4, "-->");
+                push_token(&mut tokens, TokenKind::Comment, collect(&chars, i, end));
+                i = end;
+                continue;
+            }
+
+            if starts_with_at(&chars, i, "<!") || starts_with_at(&chars, i, "<?") {
+                let end = find(i, ">");
+                push_token(&mut tokens, TokenKind::Keyword, collect(&chars, i, end));
+                i = end;
+                continue;
+            }
+
+            let opens_tag = c == '<'
+                && chars
+                    .get(i + 1)
+                    .map(|n| n.is_alphabetic() || *n == '/')
+                    .unwrap_or(false);
+
+            if opens_tag {
+                let mut j = i + 1 + (chars[i + 1] == '/') as usize;
+                push_token(&mut tokens, TokenKind::Punctuation, collect(&chars, i, j));
+
+                let name_start = j;
+                while j < chars.len() && !chars[j].is_whitespace() && !"/>".contains(chars[j]) {
+                    j += 1;
+                }
+                push_token(&mut tokens, TokenKind::Tag, collect(&chars, name_start, j));
+
+                while j < chars.len() && chars[j] != '>' {
+                    let a = chars[j];
+                    let start = j;
+
+                    let kind = match a {
+                        a if a.is_whitespace() => {
+                            j += 1;
+                            TokenKind::Text
+                        }
+                        '=' => {
+                            j += 1;
+                            TokenKind::Operator
+                        }
+                        '/' => {
+                            j += 1;
+                            TokenKind::Punctuation
+                        }
+                        '"' | '\'' => {
+                            j = find(j + 1, &a.to_string());
+                            TokenKind::String
+                        }
+                        _ if chars[j - 1] == '=' => {
+                            while j < chars.len() && !chars[j].is_whitespace() && chars[j] != '>' {
+                                j += 1;
+                            }
+                            TokenKind::String
+                        }
+                        _ => {
+                            while j < chars.len()
+                                && !chars[j].is_whitespace()
+                                && !"=/>".contains(chars[j])
+                            {
+                                j += 1;
+                            }
+                            TokenKind::Attribute
+                        }
+                    };
+
+                    push_token(&mut tokens, kind, collect(&chars, start, j));
+                }
+
+                let end = (j + 1).min(chars.len());
+                push_token(&mut tokens, TokenKind::Punctuation, collect(&chars, j, end));
+                i = end;
+                continue;
+            }
+
+            if c == '&' {
+                let end = (i + 1..chars.len().min(i + 12)).find(|j| chars[*j] == ';');
+                if let Some(end) = end.filter(|e| {
+                    chars[i + 1..*e]
+                        .iter()
+                        .all(|c| c.is_alphanumeric() || *c == '#')
+                }) {
+                    push_token(
+                        &mut tokens,
+                        TokenKind::Constant,
+                        collect(&chars, i, end + 1),
+                    );
+                    i = end + 1;
+                    continue;
+                }
+            }
+
+            push_token(&mut tokens, TokenKind::Text, c.to_string());
+            i += 1;
+        }
+
+        tokens
+    }
+}
+
+fn rust() -> SimpleGrammar {
+    SimpleGrammar::new(&["rust", "rs"])
+        .with_keywords(&[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+            "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+            "type", "unsafe", "use", "where", "while",
+        ])
+        .with_types(&[
+            "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "str", "u8",
+            "u16", "u32", "u64", "u128", "usize",
+        ])
+        .with_constants(&["true", "false", "None", "Some", "Ok", "Err"])
+        .with_line_comment("//")
+        .with_block_comment("/*", "*/")
+        .with_quotes(&['"', '\''])
+        .with_char_literals()
+        .with_attribute_prefix("#![")
+        .with_attribute_prefix("#[")
+        .with_capitalised_types()
+        .with_macros()
+}
+
+fn json() -> SimpleGrammar {
+    SimpleGrammar::new(&["json", "jsonc"])
+        .with_constants(&["true", "false", "null"])
+        .with_key_suffix(':')
+}
+
+fn toml() -> SimpleGrammar {
+    SimpleGrammar::new(&["toml"])
+        .with_constants(&["true", "false", "inf", "nan"])
+        .with_line_comment("#")
+        .with_quotes(&['"', '\''])
+        .with_triple_quotes()
+        .with_key_suffix('=')
+        .with_section_headers()
+        .with_dashed_identifiers()
+}
+
+fn shell() -> SimpleGrammar {
+    SimpleGrammar::new(&["shell", "sh", "bash", "zsh", "console"])
+        .with_keywords(&[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "readonly", "return", "select", "then", "until", "while",
+        ])
+        .with_types(&[
+            "alias", "cd", "echo", "eval", "exec", "exit", "printf", "read", "set", "shift",
+            "source", "test", "trap", "unset",
+        ])
+        .with_constants(&["true", "false"])
+        .with_line_comment("#")
+        .with_quotes(&['"', '\''])
+        .with_variable_prefix('$')
+        .with_dashed_identifiers()
+}
+
+fn python() -> SimpleGrammar {
+    SimpleGrammar::new(&["python", "py", "python3"])
+        .with_keywords(&[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+            "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in",
+            "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+            "with", "yield",
+        ])
+        .with_types(&[
+            "bool", "bytes", "dict", "float", "int", "list", "object", "set", "str", "tuple",
+        ])
+        .with_constants(&["True", "False", "None", "self"])
+        .with_line_comment("#")
+        .with_quotes(&['"', '\''])
+        .with_triple_quotes()
+        .with_attribute_prefix("@")
+        .with_capitalised_types()
+}
+
+fn javascript() -> SimpleGrammar {
+    SimpleGrammar::new(&["javascript", "js", "mjs", "typescript", "ts", "jsx", "tsx"])
+        .with_keywords(&[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "debugger",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "let",
+            "new",
+            "of",
+            "return",
+            "static",
+            "super",
+            "switch",
+            "this",
+            "throw",
+            "try",
+            "type",
+            "typeof",
+            "var",
+            "void",
+            "while",
+            "with",
+            "yield",
+        ])
+        .with_types(&[
+            "any", "boolean", "never", "number", "object", "string", "symbol", "unknown",
+        ])
+        .with_constants(&["true", "false", "null", "undefined", "NaN", "Infinity"])
+        .with_line_comment("//")
+        .with_block_comment("/*", "*/")
+        .with_quotes(&['"', '\'', '`'])
+        .with_attribute_prefix("@")
+        .with_capitalised_types()
+}
+
+impl Highlighter {
+    pub fn new() -> Highlighter {
+        Highlighter {
+            grammars: vec![
+                Box::new(rust()),
+                Box::new(json()),
+                Box::new(toml()),
+                Box::new(shell()),
+                Box::new(python()),
+                Box::new(javascript()),
+                Box::new(MarkupGrammar::new(&["html", "htm", "xhtml", "xml", "svg"])),
+            ],
+        }
+    }
+
+    pub fn empty() -> Highlighter {
+        Highlighter { grammars: vec![] }
+    }
+
+    // Grammars registered later take precedence over earlier ones with the same name.
+    pub fn register(&mut self, grammar: Box<dyn Grammar>) {
+        self.grammars.insert(0, grammar);
+    }
+
+    pub fn grammar(&self, language: &str) -> Option<&dyn Grammar> {
+        let language = language.trim().to_lowercase();
+
+        self.grammars
+            .iter()
+            .find(|g| g.names().iter().any(|n| n.to_lowercase() == language))
+            .map(|g| g.as_ref())
+    }
+
+    pub fn tokenize(&self, language: &str, code: &str) -> Option<Vec<(TokenKind, String)>> {
+        self.grammar(language).map(|g| g.tokenize(code))
+    }
+
+    pub fn highlight(&self, language: &str, code: &str) -> Option<String> {
+        self.tokenize(language, code)
+            .map(|tokens| render_tokens(&tokens))
+    }
+}
+
+impl Default for Highlighter {
+    fn default() -> Highlighter {
+        Highlighter::new()
+    }
+}
+
+pub fn render_tokens(tokens: &[(TokenKind, String)]) -> String {
+    tokens
+        .iter()
+        .map(|(kind, text)| match kind {
+            TokenKind::Text => escape(text),
+            k => format!("<span class='{}'>{}</span>", k.class(), escape(text)),
+        })
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+impl TokenStyle {
+    pub fn new(colour: &str) -> TokenStyle {
+        TokenStyle {
+            colour: colour.to_string(),
+            bold: false,
+            italic: false,
+        }
+    }
+
+    pub fn bold(mut self) -> TokenStyle {
+        self.bold = true;
+        self
+    }
+
+    pub fn italic(mut self) -> TokenStyle {
+        self.italic = true;
+        self
+    }
+}
+
+impl Theme {
+    pub fn new(background: &str, foreground: &str) -> Theme {
+        Theme {
+            background: background.to_string(),
+            foreground: foreground.to_string(),
+            line_highlight: "rgba(128, 128, 128, 0.2)".to_string(),
+            styles: vec![],
+        }
+    }
+
+    // Background of highlighted rows, see `CodeBlock::highlighted_lines`.
+    pub fn with_line_highlight(mut self, colour: &str) -> Theme {
+        self.line_highlight = colour.to_string();
+        self
+    }
+
+    pub fn with_style(mut self, kind: TokenKind, style: TokenStyle) -> Theme {
+        self.styles.retain(|(k, _)| *k != kind);
+        self.styles.push((kind, style));
+        self
+    }
+
+    pub fn light() -> Theme {
+        Theme::new("#fafafa", "#383a42")
+            .with_line_highlight("#e5e5e6")
+            .with_style(TokenKind::Attribute, TokenStyle::new("#986801"))
+            .with_style(TokenKind::Comment, TokenStyle::new("#a0a1a7").italic())
+            .with_style(TokenKind::Constant, TokenStyle::new("#986801"))
+            .with_style(TokenKind::Function, TokenStyle::new("#4078f2"))
+            .with_style(TokenKind::Keyword, TokenStyle::new("#a626a4").bold())
+            .with_style(TokenKind::Number, TokenStyle::new("#986801"))
+            .with_style(TokenKind::Operator, TokenStyle::new("#0184bc"))
+            .with_style(TokenKind::Property, TokenStyle::new("#e45649"))
+            .with_style(TokenKind::Punctuation, TokenStyle::new("#383a42"))
+            .with_style(TokenKind::String, TokenStyle::new("#50a14f"))
+            .with_style(TokenKind::Tag, TokenStyle::new("#e45649"))
+            .with_style(TokenKind::Type, TokenStyle::new("#c18401"))
+            .with_style(TokenKind::Variable, TokenStyle::new("#e45649"))
+    }
+
+    pub fn dark() -> Theme {
+        Theme::new("#282c34", "#abb2bf")
+            .with_line_highlight("#3e4451")
+            .with_style(TokenKind::Attribute, TokenStyle::new("#d19a66"))
+            .with_style(TokenKind::Comment, TokenStyle::new("#5c6370").italic())
+            .with_style(TokenKind::Constant, TokenStyle::new("#d19a66"))
+            .with_style(TokenKind::Function, TokenStyle::new("#61afef"))
+            .with_style(TokenKind::Keyword, TokenStyle::new("#c678dd").bold())
+            .with_style(TokenKind::Number, TokenStyle::new("#d19a66"))
+            .with_style(TokenKind::Operator, TokenStyle::new("#56b6c2"))
+            .with_style(TokenKind::Property, TokenStyle::new("#e06c75"))
+            .with_style(TokenKind::Punctuation, TokenStyle::new("#abb2bf"))
+            .with_style(TokenKind::String, TokenStyle::new("#98c379"))
+            .with_style(TokenKind::Tag, TokenStyle::new("#e06c75"))
+            .with_style(TokenKind::Type, TokenStyle::new("#e5c07b"))
+            .with_style(TokenKind::Variable, TokenStyle::new("#e06c75"))
+    }
+
+    pub fn stylesheet(&self) -> String {
+        let mut rules = vec![
+            format!(
+                "pre.highlight {{ background: {}; color: {}; }}",
+                self.background, self.foreground
+            ),
+            "pre .line { display: block; }".to_string(),
+            format!(
+                "pre .line.highlighted {{ background: {}; }}",
+                self.line_highlight
+            ),
+            "pre .line-number { display: inline-block; min-width: 2em; margin-right: 1em; \
+             text-align: right; opacity: 0.5; user-select: none; }"
+                .to_string(),
+            "figure.code-block figcaption { font-family: monospace; font-weight: bold; }"
+                .to_string(),
+        ];
+
+        for (kind, style) in &self.styles {
+            let mut declarations = vec![format!("color: {};", style.colour)];
+            if style.bold {
+                declarations.push("font-weight: bold;".to_string());
+            }
+            if style.italic {
+                declarations.push("font-style: italic;".to_string());
+            }
+
+            rules.push(format!(
+                "pre.highlight .{} {{ {} }}",
+                kind.class(),
+                declarations.join(" ")
+            ));
+        }
+
+        format!("{}\n", rules.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The non-text tokens, after checking that the tokens cover the whole input.
+    fn tokens(language: &str, code: &str) -> Vec<(TokenKind, String)> {
+        let tokens = Highlighter::new().tokenize(language, code).unwrap();
+
+        assert_eq!(
+            tokens.iter().map(|(_, t)| t.as_str()).collect::<String>(),
+            code
+        );
+
+        tokens
+            .into_iter()
+            .filter(|(kind, _)| *kind != TokenKind::Text)
+            .collect()
+    }
+
+    fn expected(tokens: &[(TokenKind, &str)]) -> Vec<(TokenKind, String)> {
+        tokens
+            .iter()
+            .map(|(kind, text)| (*kind, text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn rust() {
+        use TokenKind::*;
+
+        assert_eq!(
+            tokens(
+                "rust",
+                "#[derive(Debug)]\nfn main() { let x: u32 = 42; println!(\"hi\"); } // done"
+            ),
+            expected(&[
+                (Attribute, "#[derive(Debug)]"),
+                (Keyword, "fn"),
+                (Function, "main"),
+                (Punctuation, "()"),
+                (Punctuation, "{"),
+                (Keyword, "let"),
+                (Operator, ":"),
+                (Type, "u32"),
+                (Operator, "="),
+                (Number, "42"),
+                (Punctuation, ";"),
+                (Function, "println"),
+                (Operator, "!"),
+                (Punctuation, "("),
+                (String, "\"hi\""),
+                (Punctuation, ");"),
+                (Punctuation, "}"),
+                (Comment, "// done"),
+            ])
+        );
+        assert_eq!(
+            tokens("rs", "let c = 'a'; let r: &'a Foo;"),
+            expected(&[
+                (Keyword, "let"),
+                (Operator, "="),
+                (String, "'a'"),
+                (Punctuation, ";"),
+                (Keyword, "let"),
+                (Operator, ":"),
+                (Operator, "&"),
+                (Type, "'a"),
+                (Type, "Foo"),
+                (Punctuation, ";"),
+            ])
+        );
+    }
+
+    #[test]
+    fn json() {
+        use TokenKind::*;
+
+        assert_eq!(
+            tokens("json", "{\"key\": [1, true, null, \"v\"]}"),
+            expected(&[
+                (Punctuation, "{"),
+                (Property, "\"key\""),
+                (Operator, ":"),
+                (Punctuation, "["),
+                (Number, "1"),
+                (Punctuation, ","),
+                (Constant, "true"),
+                (Punctuation, ","),
+                (Constant, "null"),
+                (Punctuation, ","),
+                (String, "\"v\""),
+                (Punctuation, "]}"),
+            ])
+        );
+    }
+
+    #[test]
+    fn toml() {
+        use TokenKind::*;
+
+        assert_eq!(
+            tokens(
+                "toml",
+                "[package]\nname = \"rdom\" # c\nedition-year = 2021\nflag = true"
+            ),
+            expected(&[
+                (Type, "[package]"),
+                (Property, "name"),
+                (Operator, "="),
+                (String, "\"rdom\""),
+                (Comment, "# c"),
+                (Property, "edition-year"),
+                (Operator, "="),
+                (Number, "2021"),
+                (Property, "flag"),
+                (Operator, "="),
+                (Constant, "true"),
+            ])
+        );
+    }
+
+    #[test]
+    fn shell() {
+        use TokenKind::*;
+
+        assert_eq!(
+            tokens("bash", "if [ $HOME ]; then echo \"x\"; fi # c"),
+            expected(&[
+                (Keyword, "if"),
+                (Punctuation, "["),
+                (Variable, "$HOME"),
+                (Punctuation, "];"),
+                (Keyword, "then"),
+                (Type, "echo"),
+                (String, "\"x\""),
+                (Punctuation, ";"),
+                (Keyword, "fi"),
+                (Comment, "# c"),
+            ])
+        );
+    }
+
+    #[test]
+    fn python() {
+        use TokenKind::*;
+
+        assert_eq!(
+            tokens(
+                "python",
+                "@cache\ndef f(self):\n    \"\"\"doc\"\"\"\n    return None # c"
+            ),
+            expected(&[
+                (Attribute, "@cache"),
+                (Keyword, "def"),
+                (Function, "f"),
+                (Punctuation, "("),
+                (Constant, "self"),
+                (Punctuation, ")"),
+                (Operator, ":"),
+                (String, "\"\"\"doc\"\"\""),
+                (Keyword, "return"),
+                (Constant, "None"),
+                (Comment, "# c"),
+            ])
+        );
+    }
+
+    #[test]
+    fn javascript() {
+        use TokenKind::*;
+
+        assert_eq!(
+            tokens("ts", "const x = `t`; /* c */ new Map()"),
+            expected(&[
+                (Keyword, "const"),
+                (Operator, "="),
+                (String, "`t`"),
+                (Punctuation, ";"),
+                (Comment, "/* c */"),
+                (Keyword, "new"),
+                (Function, "Map"),
+                (Punctuation, "()"),
+            ])
+        );
+    }
+
+    #[test]
+    fn html() {
+        use TokenKind::*;
+
+        assert_eq!(
+            tokens("html", "<!-- c --><a href=\"x\">T &amp; U</a>"),
+            expected(&[
+                (Comment, "<!-- c -->"),
+                (Punctuation, "<"),
+                (Tag, "a"),
+                (Attribute, "href"),
+                (Operator, "="),
+                (String, "\"x\""),
+                (Punctuation, ">"),
+                (Constant, "&amp;"),
+                (Punctuation, "</"),
+                (Tag, "a"),
+                (Punctuation, ">"),
+            ])
+        );
+    }
+
+    #[test]
+    fn registered_grammars_take_precedence() {
+        let mut highlighter = Highlighter::new();
+
+        assert!(highlighter.tokenize("lisp", "(defun f)").is_none());
+
+        highlighter.register(Box::new(
+            SimpleGrammar::new(&["lisp", "JSON"])
+                .with_keywords(&["defun"])
+                .with_line_comment(";")
+                .with_quotes(&['"']),
+        ));
+
+        assert_eq!(
+            highlighter.tokenize("Lisp", "(defun f) ; c"),
+            Some(expected(&[
+                (TokenKind::Punctuation, "("),
+                (TokenKind::Keyword, "defun"),
+                (TokenKind::Text, " f"),
+                (TokenKind::Punctuation, ")"),
+                (TokenKind::Text, " "),
+                (TokenKind::Comment, "; c"),
+            ]))
+        );
+        assert_eq!(
+            highlighter.tokenize("json", "true"),
+            Some(expected(&[(TokenKind::Text, "true")]))
+        );
+        assert_eq!(
+            highlighter.highlight("lisp", "(defun \"<f>\")"),
+            Some(
+                "<span class='hl-punctuation'>(</span><span class='hl-keyword'>defun</span> \
+                 <span class='hl-string'>&quot;&lt;f&gt;&quot;</span>\
+                 <span class='hl-punctuation'>)</span>"
+                    .to_string()
+            )
+        );
+    }
+}
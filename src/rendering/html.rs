@@ -1,44 +1,168 @@
+use std::collections::HashSet;
+
 use crate::core::documents::{
-    Block, CodeBlock, HeaderBlock, ImageBlock, InlineContent, ListBlock, ListItem, ParagraphBlock,
-    Style,
+    Block, CodeBlock, CustomBlock, HeaderBlock, ImageBlock, InlineContent, InlineCustom,
+    InlineImage, ListBlock, ListItem, ParagraphBlock, Style,
 };
 
+use super::highlighting::{render_tokens, Highlighter, TokenKind};
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&#39;")
+        .replace('"', "&quot;")
+}
+
+// Sources that are not files next to the document, such as `https://` or `data:` URLs.
+pub fn is_remote(source: &str) -> bool {
+    source.contains("://") || source.starts_with("data:")
+}
+
+pub fn slug(s: &str) -> String {
+    s.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+// Ids for the anchors of indexed headers, unique within a document. Repeated titles get a
+// `-2`, `-3`, ... suffix and titles without letters or digits fall back to `section`.
+#[derive(Default)]
+pub struct HeaderIds {
+    used: HashSet<String>,
+}
+
+impl HeaderIds {
+    pub fn id(&mut self, title: &str) -> String {
+        let base = match slug(title) {
+            s if s.is_empty() => "section".to_string(),
+            s => s,
+        };
+
+        let id = (1..)
+            .map(|n| match n {
+                1 => base.clone(),
+                n => format!("{}-{}", base, n),
+            })
+            .find(|id| !self.used.contains(id))
+            .unwrap();
+
+        self.used.insert(id.clone());
+        id
+    }
+}
+
 fn render_style(style: Style) -> String {
     match style {
-        Style::Ref(c) => format!(" class='{}'", c.join(" ")),
+        Style::Ref(c) => format!(" class='{}'", escape(&c.join(" "))),
         Style::Custom(m) => {
             format!(
                 " style='{}'",
-                m.into_iter()
-                    .map(|(k, v)| { format!("{}: {}", k, v) })
-                    .collect::<Vec<String>>()
-                    .join("; ")
+                escape(
+                    &m.into_iter()
+                        .map(|(k, v)| { format!("{}: {}", k, v) })
+                        .collect::<Vec<String>>()
+                        .join("; ")
+                )
             )
         }
         Style::Default => "".to_string(),
     }
 }
 
+// Classes with a matching element, in the order they are nested (outermost first).
+const SEMANTIC_CLASSES: [(&str, &str); 4] =
+    [("b", "strong"), ("i", "em"), ("s", "del"), ("code", "code")];
+
+// Emphasis, strikethrough and code are written as their elements, any other classes (or
+// custom styles) as a span around them.
+fn render_span(style: Style, content: String) -> String {
+    let classes = match &style {
+        Style::Ref(c) => c.clone(),
+        _ => vec![],
+    };
+
+    let content = SEMANTIC_CLASSES
+        .iter()
+        .rev()
+        .filter(|(class, _)| classes.iter().any(|c| c == class))
+        .fold(content, |inner, (_, tag)| {
+            format!("<{}>{}</{}>", tag, inner, tag)
+        });
+
+    let rest: Vec<String> = classes
+        .into_iter()
+        .filter(|c| !SEMANTIC_CLASSES.iter().any(|(class, _)| class == c))
+        .collect();
+
+    match (style, rest.is_empty()) {
+        (Style::Ref(_), true) => content,
+        (Style::Ref(_), false) => {
+            format!("<span{}>{}</span>", render_style(Style::Ref(rest)), content)
+        }
+        (style, _) => format!("<span{}>{}</span>", render_style(style), content),
+    }
+}
+
+fn render_data_attributes(mut data: Vec<(String, String)>) -> String {
+    data.sort();
+
+    data.into_iter()
+        .map(|(k, v)| format!(" data-{}='{}'", escape(&slug(&k)), escape(&v)))
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+fn render_inline_custom(custom: InlineCustom) -> String {
+    format!(
+        "<span{}{}>{}</span>",
+        render_style(custom.style.with_class(&custom.kind)),
+        render_data_attributes(custom.data),
+        escape(&custom.content)
+    )
+}
+
+fn render_title(title: &str) -> String {
+    match title.is_empty() {
+        true => "".to_string(),
+        false => format!(" title='{}'", escape(title)),
+    }
+}
+
+fn render_inline_image(image: InlineImage) -> String {
+    format!(
+        "<img src='{}' alt='{}'{}{} />",
+        escape(&image.source),
+        escape(&image.alt_text),
+        render_title(&image.title),
+        render_style(image.style)
+    )
+}
+
 fn render_inline_content(content: Vec<InlineContent>) -> String {
     content
         .into_iter()
         .map(|c| match c {
-            InlineContent::Text(t) => t.content,
-            InlineContent::Span(s) => {
-                format!("<span{}>{}</span>", render_style(s.style), s.content)
-            }
+            InlineContent::Text(t) => escape(&t.content),
+            InlineContent::Span(s) => render_span(s.style, render_inline_content(s.content)),
             InlineContent::Link(l) => format!(
-                "<a href='{}'{}>{}</span>",
-                l.url,
+                "<a href='{}'{}{}>{}</a>",
+                escape(&l.url),
+                render_title(&l.title),
                 render_style(l.style),
-                l.content
+                render_inline_content(l.content)
             ),
+            InlineContent::Image(i) => render_inline_image(i),
+            InlineContent::Custom(c) => render_inline_custom(c),
         })
         .collect::<Vec<String>>()
         .join("")
 }
 
-fn render_header(block: HeaderBlock) -> String {
+fn render_header(block: HeaderBlock, ids: &mut HeaderIds) -> String {
     let tag = match block.level {
         crate::core::documents::HeaderLevel::H1 => "h1",
         crate::core::documents::HeaderLevel::H2 => "h2",
@@ -48,9 +172,19 @@ fn render_header(block: HeaderBlock) -> String {
         crate::core::documents::HeaderLevel::H6 => "h6",
     };
 
+    // Indexed headers get an anchor so they can be linked to from a table of contents.
+    let id = match block.indexed {
+        true => format!(
+            " id='{}'",
+            escape(&ids.id(&InlineContent::plain_text(&block.content)))
+        ),
+        false => "".to_string(),
+    };
+
     format!(
-        "<{}{}>{}</{}>",
+        "<{}{}{}>{}</{}>",
         tag,
+        id,
         render_style(block.style),
         render_inline_content(block.content),
         tag
@@ -65,13 +199,91 @@ fn render_paragraph(block: ParagraphBlock) -> String {
     )
 }
 
-fn render_code_block(block: CodeBlock) -> String {
-    let lang = match block.language {
-        Some(l) => format!(" class='language-{}'", l),
-        None => "".to_string(),
+// Splits highlighted or plain code into lines of markup, spans running over several lines
+// are closed and reopened on each.
+fn render_code_lines(content: &str, tokens: Option<Vec<(TokenKind, String)>>) -> Vec<String> {
+    let tokens = match tokens {
+        Some(t) => t,
+        None => return content.split('\n').map(escape).collect(),
+    };
+
+    let mut lines = vec![String::new()];
+
+    for (kind, text) in tokens {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(String::new());
+            }
+            if !part.is_empty() {
+                let markup = render_tokens(&[(kind, part.to_string())]);
+                lines.last_mut().unwrap().push_str(&markup);
+            }
+        }
+    }
+
+    lines
+}
+
+fn render_code_rows(block: &CodeBlock, tokens: Option<Vec<(TokenKind, String)>>) -> String {
+    let lines = render_code_lines(&block.content, tokens);
+    let count = lines.len();
+
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let class = match block.is_highlighted(i + 1) {
+                true => "line highlighted",
+                false => "line",
+            };
+
+            let number = match block.line_numbers {
+                Some(start) => format!("<span class='line-number'>{}</span>", start + i),
+                None => "".to_string(),
+            };
+
+            // The newline stays inside the row so the code reads correctly without styles.
+            let end = match i + 1 < count {
+                true => "\n",
+                false => "",
+            };
+
+            format!("<span class='{}'>{}{}{}</span>", class, number, line, end)
+        })
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+fn render_code_block(block: CodeBlock, highlighter: Option<&Highlighter>) -> String {
+    let tokens = match (&block.language, highlighter) {
+        (Some(l), Some(h)) => h.tokenize(l, &block.content),
+        _ => None,
+    };
+
+    let lang = match (&block.language, tokens.is_some()) {
+        (Some(l), true) => format!(" class='highlight language-{}'", escape(l)),
+        (Some(l), false) => format!(" class='language-{}'", escape(l)),
+        (None, _) => "".to_string(),
     };
 
-    format!("<pre{}><code>{}</code></pre>", lang, block.content)
+    let rows = block.line_numbers.is_some() || !block.highlighted_lines.is_empty();
+
+    let code = match (rows, tokens) {
+        (true, tokens) => render_code_rows(&block, tokens),
+        (false, Some(tokens)) => render_tokens(&tokens),
+        (false, None) => escape(&block.content),
+    };
+
+    let pre = format!("<pre{}><code>{}</code></pre>", lang, code);
+
+    match block.title {
+        Some(title) => format!(
+            "<figure class='code-block'><figcaption>{}</figcaption>{}</figure>",
+            escape(&title),
+            pre
+        ),
+        None => pre,
+    }
 }
 
 fn render_list_item(item: ListItem) -> String {
@@ -104,30 +316,137 @@ fn render_list_block(block: ListBlock) -> String {
 
 fn render_image_block(block: ImageBlock) -> String {
     let h = match block.height {
-        Some(h) => format!(" height='{}'", h),
+        Some(h) => format!(" height='{}'", escape(&h)),
         None => "".to_string(),
     };
 
     let w = match block.width {
-        Some(w) => format!(" width='{}'", w),
+        Some(w) => format!(" width='{}'", escape(&w)),
         None => "".to_string(),
     };
 
+    // Images on a line of their own are figures, captioned with the title.
+    let caption = match block.title.is_empty() {
+        true => "".to_string(),
+        false => format!("<figcaption>{}</figcaption>", escape(&block.title)),
+    };
+
     format!(
-        "<img src='{}' alt='{}' title='{}'{}{}>",
-        block.source, block.alt_text, block.title, h, w
+        "<figure><img src='{}' alt='{}' title='{}'{}{} />{}</figure>",
+        escape(&block.source),
+        escape(&block.alt_text),
+        escape(&block.title),
+        h,
+        w,
+        caption
     )
 }
 
-pub fn render(blocks: Vec<Block>) -> Vec<String> {
+fn render_custom_block(
+    block: CustomBlock,
+    highlighter: Option<&Highlighter>,
+    ids: &mut HeaderIds,
+) -> String {
+    format!(
+        "<div class='{}'{}>{}</div>",
+        escape(&block.kind),
+        render_data_attributes(block.data),
+        render_blocks(block.content, highlighter, ids).join("")
+    )
+}
+
+fn render_blocks(
+    blocks: Vec<Block>,
+    highlighter: Option<&Highlighter>,
+    ids: &mut HeaderIds,
+) -> Vec<String> {
     blocks
         .into_iter()
         .map(|b| match b {
-            Block::Header(h) => render_header(h),
+            Block::Header(h) => render_header(h, ids),
             Block::Paragraph(p) => render_paragraph(p),
-            Block::Code(c) => render_code_block(c),
+            Block::Code(c) => render_code_block(c, highlighter),
             Block::List(l) => render_list_block(l),
             Block::Image(i) => render_image_block(i),
+            Block::Custom(c) => render_custom_block(c, highlighter, ids),
         })
         .collect::<Vec<String>>()
 }
+
+pub fn render(blocks: Vec<Block>) -> Vec<String> {
+    render_blocks(blocks, None, &mut HeaderIds::default())
+}
+
+// Code blocks in a language known to the highlighter are highlighted with classed spans,
+// see `highlighting::Theme::stylesheet` for the matching styles.
+pub fn render_highlighted(blocks: Vec<Block>, highlighter: &Highlighter) -> Vec<String> {
+    render_blocks(blocks, Some(highlighter), &mut HeaderIds::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::{HeaderLevel, InlineText};
+
+    fn header(title: &str) -> Block {
+        Block::header(HeaderBlock::new(
+            Style::Default,
+            HeaderLevel::H2,
+            vec![InlineContent::Text(InlineText::new(title.to_string()))],
+            true,
+        ))
+    }
+
+    #[test]
+    fn header_ids_are_unique() {
+        let mut ids = HeaderIds::default();
+
+        assert_eq!(ids.id("Intro"), "intro");
+        assert_eq!(ids.id("intro!"), "intro-2");
+        assert_eq!(ids.id("Intro 2"), "intro-2-2");
+        assert_eq!(ids.id("Intro"), "intro-3");
+        assert_eq!(ids.id("???"), "section");
+        assert_eq!(ids.id(""), "section-2");
+        assert_eq!(ids.id("日本"), "日本");
+    }
+
+    #[test]
+    fn headers_in_custom_blocks_share_the_ids() {
+        let html = render(vec![
+            header("Setup"),
+            Block::custom(CustomBlock::new(
+                "note".to_string(),
+                vec![header("Setup")],
+                "".to_string(),
+            )),
+            header("Setup"),
+        ]);
+
+        assert_eq!(
+            html,
+            vec![
+                "<h2 id='setup'>Setup</h2>",
+                "<div class='note'><h2 id='setup-2'>Setup</h2></div>",
+                "<h2 id='setup-3'>Setup</h2>",
+            ]
+        );
+    }
+
+    #[test]
+    fn class_names_are_escaped() {
+        let custom = InlineCustom::new(
+            "x'><script>".to_string(),
+            "@a".to_string(),
+            "@a".to_string(),
+        );
+
+        assert_eq!(
+            render_inline_custom(custom),
+            "<span class='x&#39;&gt;&lt;script&gt;'>@a</span>"
+        );
+        assert_eq!(
+            render_style(Style::create_ref(vec!["a'b".to_string()])),
+            " class='a&#39;b'"
+        );
+    }
+}
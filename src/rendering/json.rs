@@ -0,0 +1,188 @@
+//! JSON export of the document model (requires the `json` feature).
+//!
+//! Documents are written as `{"version": 1, "blocks": [...]}`. The shape is fixed, any change
+//! to it must bump `JSON_VERSION` so cached documents can be rejected by `parsing::json::load`.
+//!
+//! * Blocks and inline content are objects tagged by `type` (`header`, `paragraph`, `code`,
+//!   `list`, `image`, `custom` and `text`, `span`, `link`, `image`, `custom`), with the
+//!   remaining keys matching the struct fields in `core::documents`.
+//! * Header levels are the strings `h1` to `h6`.
+//! * Styles are `{"type": "default"}`, `{"type": "ref", "value": ["b", "i"]}` or
+//!   `{"type": "custom", "value": {"color": "red"}}`.
+//! * Code blocks have `title` (a string or `null`), `highlighted_lines` (inclusive
+//!   `[start, end]` pairs) and `line_numbers` (the first line number or `null`).
+//! * The `content` of spans and links is a list of inline content, as spans can be nested.
+//! * Links have `title` (empty without one) and `reference` (the label of the link reference
+//!   definition for links written as references, or `null`).
+//! * Images inside text have `style`, `source`, `title` (empty without one) and `alt_text`.
+//! * Blocks made by parser extensions have `kind`, `data` (a list of `[key, value]` pairs),
+//!   `content` (the blocks it renders as) and `source` (the original text). Inline content
+//!   made by inline extensions has the same keys and a `style`, with `content` being the text
+//!   it renders as.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "blocks": [
+//!     {
+//!       "type": "paragraph",
+//!       "style": { "type": "default" },
+//!       "content": [
+//!         { "type": "text", "content": "Hello, " },
+//!         {
+//!           "type": "span",
+//!           "content": [{ "type": "text", "content": "World!" }],
+//!           "style": { "type": "ref", "value": ["b"] }
+//!         }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+use serde::Serialize;
+
+use crate::core::documents::Block;
+
+pub const JSON_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct JsonDocument<'a> {
+    version: u32,
+    blocks: &'a [Block],
+}
+
+pub fn render(blocks: Vec<Block>) -> Result<String, String> {
+    serde_json::to_string_pretty(&JsonDocument {
+        version: JSON_VERSION,
+        blocks: &blocks,
+    })
+    .map_err(|e| format!("Could not write JSON document: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        core::documents::{
+            CodeBlock, CustomBlock, HeaderBlock, ImageBlock, InlineContent, InlineCustom,
+            InlineImage, InlineLink, InlineSpan, InlineText, ListBlock, ListItem, ParagraphBlock,
+            Style,
+        },
+        parsing::json,
+    };
+
+    fn text(s: &str) -> InlineContent {
+        InlineContent::Text(InlineText::new(s.to_string()))
+    }
+
+    fn every_variant() -> Vec<Block> {
+        let inline = vec![
+            text("plain"),
+            InlineContent::Span(InlineSpan::new(
+                Style::create_ref(vec!["b".to_string(), "i".to_string()]),
+                vec![
+                    text("nested "),
+                    InlineContent::Span(InlineSpan::from_text(
+                        Style::create_ref(vec!["code".to_string()]),
+                        "code".to_string(),
+                    )),
+                ],
+            )),
+            InlineContent::Link(
+                InlineLink::from_text(
+                    Style::Default,
+                    "https://a.b".to_string(),
+                    "link".to_string(),
+                )
+                .with_title("Title".to_string())
+                .with_reference("Label".to_string()),
+            ),
+            InlineContent::Link(InlineLink::from_text(
+                Style::Default,
+                "b.md".to_string(),
+                "inline".to_string(),
+            )),
+            InlineContent::Image(InlineImage::new(
+                Style::Default,
+                "a.png".to_string(),
+                "A title".to_string(),
+                "alt".to_string(),
+            )),
+            InlineContent::Custom(
+                InlineCustom::new("mention".to_string(), "@a".to_string(), "@a".to_string())
+                    .with_data("user".to_string(), "a".to_string())
+                    .with_style(Style::create_ref(vec!["mention".to_string()])),
+            ),
+        ];
+
+        vec![
+            Block::header(HeaderBlock::h2(
+                Style::create_custom(HashMap::from([("id".to_string(), "x".to_string())])),
+                inline.clone(),
+                true,
+            )),
+            Block::paragraph(ParagraphBlock::new(Style::Default, inline.clone())),
+            Block::code(
+                CodeBlock::new(
+                    Style::Default,
+                    "fn main() {}".to_string(),
+                    Some("rust".to_string()),
+                )
+                .with_title("main.rs".to_string())
+                .with_highlighted_lines(vec![(1, 1)])
+                .with_line_numbers(3),
+            ),
+            Block::code(CodeBlock::new(Style::Default, "".to_string(), None)),
+            Block::list(ListBlock::new_ordered(
+                Style::Default,
+                vec![ListItem::new(Style::Default, inline.clone())],
+            )),
+            Block::list(ListBlock::new_unordered(
+                Style::Default,
+                vec![ListItem::new(Style::Default, vec![text("item")])],
+            )),
+            Block::image(ImageBlock::new(
+                Style::Default,
+                "a.png".to_string(),
+                "".to_string(),
+                "alt".to_string(),
+                Some("10px".to_string()),
+                None,
+            )),
+            Block::custom(
+                CustomBlock::new(
+                    "note".to_string(),
+                    vec![Block::paragraph(ParagraphBlock::new(
+                        Style::Default,
+                        inline,
+                    ))],
+                    "!!! note".to_string(),
+                )
+                .with_data("kind".to_string(), "warning".to_string()),
+            ),
+        ]
+    }
+
+    #[test]
+    fn every_variant_round_trips() {
+        let blocks = every_variant();
+        let rendered = render(blocks.clone()).unwrap();
+
+        assert!(rendered.contains("\"version\": 1"), "{}", rendered);
+        assert_eq!(
+            format!("{:?}", json::load(&rendered).unwrap()),
+            format!("{:?}", blocks)
+        );
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        assert_eq!(
+            json::load("{\"version\": 2, \"blocks\": []}").err(),
+            Some("Unsupported rdom JSON version 2 (expected 1)".to_string())
+        );
+        assert!(json::load("{\"version\": 1, \"blocks\": [{\"type\": \"table\"}]}").is_err());
+    }
+}
@@ -0,0 +1,421 @@
+use crate::core::documents::{
+    Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, InlineContent, ListBlock,
+    ParagraphBlock, Style,
+};
+
+const PREAMBLE: &str = "\\documentclass{article}\n\\usepackage[utf8]{inputenc}\n\\usepackage[T1]{fontenc}\n\\usepackage{graphicx}\n\\usepackage{listings}\n\\usepackage{hyperref}\n\\usepackage[normalem]{ulem}\n";
+
+pub fn escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\\' => "\\textbackslash{}".to_string(),
+            '{' => "\\{".to_string(),
+            '}' => "\\}".to_string(),
+            '$' => "\\$".to_string(),
+            '&' => "\\&".to_string(),
+            '#' => "\\#".to_string(),
+            '%' => "\\%".to_string(),
+            '_' => "\\_".to_string(),
+            '~' => "\\textasciitilde{}".to_string(),
+            '^' => "\\textasciicircum{}".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// Only these need escaping inside `\href`, the rest is taken verbatim.
+fn escape_url(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\\' | '#' | '%' | '{' | '}' => format!("\\{}", c),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// Image paths are file names, `\detokenize` keeps `_`, `&`, `~` and the like as written where
+// escapes such as `\_` would not be. `%`, `#` and unbalanced braces can't be written this way.
+fn render_path(s: &str) -> String {
+    format!("\\detokenize{{{}}}", s)
+}
+
+// `content` is already escaped.
+fn render_span(content: String, style: Style) -> String {
+    let content = match (
+        style.has_class("b"),
+        style.has_class("i"),
+        style.has_class("code"),
+    ) {
+        (_, _, true) => format!("\\texttt{{{}}}", content),
+        (true, true, false) => format!("\\textbf{{\\textit{{{}}}}}", content),
+        (true, false, false) => format!("\\textbf{{{}}}", content),
+        (false, true, false) => format!("\\textit{{{}}}", content),
+        (false, false, false) => content,
+    };
+
+    match style.has_class("s") {
+        true => format!("\\sout{{{}}}", content),
+        false => content,
+    }
+}
+
+fn render_inline_content(content: Vec<InlineContent>) -> String {
+    content
+        .into_iter()
+        .map(|c| match c {
+            InlineContent::Text(t) => escape(&t.content),
+            InlineContent::Span(s) => render_span(render_inline_content(s.content), s.style),
+            InlineContent::Link(l) => {
+                format!(
+                    "\\href{{{}}}{{{}}}",
+                    escape_url(&l.url),
+                    render_inline_content(l.content)
+                )
+            }
+            // Images inside text are scaled to the height of a line.
+            InlineContent::Image(i) => {
+                format!(
+                    "\\includegraphics[height=1em]{{{}}}",
+                    render_path(&i.source)
+                )
+            }
+            InlineContent::Custom(c) => render_span(escape(&c.content), c.style),
+        })
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+fn render_header(block: HeaderBlock) -> String {
+    let content = render_inline_content(block.content);
+
+    let command = match block.level {
+        HeaderLevel::H1 => "section",
+        HeaderLevel::H2 => "subsection",
+        HeaderLevel::H3 => "subsubsection",
+        HeaderLevel::H4 => "paragraph",
+        HeaderLevel::H5 => "subparagraph",
+        // There is no sixth sectioning level, so these are set in italics on their own line.
+        HeaderLevel::H6 => return format!("\\noindent\\textit{{{}}}\\par", content),
+    };
+
+    format!("\\{}{{{}}}", command, content)
+}
+
+fn render_paragraph(block: ParagraphBlock) -> String {
+    render_inline_content(block.content)
+}
+
+// The name `listings` knows a language by, it stops with an error on any other.
+fn listings_language(language: &str) -> Option<&'static str> {
+    match language.to_lowercase().as_str() {
+        "c" | "h" => Some("C"),
+        "cpp" | "c++" | "cc" | "hpp" => Some("C++"),
+        "csharp" | "cs" | "c#" => Some("[Sharp]C"),
+        "java" => Some("Java"),
+        "python" | "py" => Some("Python"),
+        "ruby" | "rb" => Some("Ruby"),
+        "perl" | "pl" => Some("Perl"),
+        "php" => Some("PHP"),
+        "sh" | "bash" | "shell" | "zsh" | "console" => Some("bash"),
+        "html" => Some("HTML"),
+        "xml" | "svg" => Some("XML"),
+        "sql" => Some("SQL"),
+        "haskell" | "hs" => Some("Haskell"),
+        "lisp" | "elisp" => Some("Lisp"),
+        "tex" | "latex" => Some("TeX"),
+        "make" | "makefile" => Some("make"),
+        "lua" => Some("Lua"),
+        "r" => Some("R"),
+        "matlab" => Some("Matlab"),
+        "fortran" => Some("Fortran"),
+        "pascal" => Some("Pascal"),
+        _ => None,
+    }
+}
+
+// Delimiters for `\verb`, tried in order.
+const VERB_DELIMITERS: [char; 8] = ['|', '!', '+', '=', '/', ':', ';', '@'];
+
+// A line as `\verb` pieces, each split off before it would contain every delimiter.
+fn render_verb_line(line: &str) -> String {
+    let mut pieces = Vec::<String>::new();
+    let mut piece = String::new();
+
+    for c in line.chars() {
+        if VERB_DELIMITERS
+            .iter()
+            .all(|d| *d == c || piece.contains(*d))
+        {
+            pieces.push(piece);
+            piece = String::new();
+        }
+        piece.push(c);
+    }
+    pieces.push(piece);
+
+    pieces
+        .into_iter()
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let d = VERB_DELIMITERS
+                .iter()
+                .find(|d| !p.contains(**d))
+                .unwrap_or(&'|');
+            format!("\\verb{}{}{}", d, p, d)
+        })
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+// Code containing the end of both environments is written a line at a time.
+fn render_verb_lines(content: &str) -> String {
+    let lines = content
+        .lines()
+        .map(|l| match l.is_empty() {
+            true => "\\mbox{}".to_string(),
+            false => render_verb_line(l),
+        })
+        .collect::<Vec<String>>()
+        .join("\\\\\n");
+
+    format!("\\par\\noindent\n{}\\par", lines)
+}
+
+// Code in other languages is still listed, just without highlighting. Code that contains the
+// end of its environment is written in the other one.
+fn render_code_block(block: CodeBlock) -> String {
+    let language = block
+        .language
+        .as_deref()
+        .filter(|l| !l.is_empty())
+        .map(listings_language);
+
+    match (
+        language,
+        block.content.contains("\\end{lstlisting}"),
+        block.content.contains("\\end{verbatim}"),
+    ) {
+        (_, true, true) => render_verb_lines(&block.content),
+        (Some(Some(l)), false, _) => format!(
+            "\\begin{{lstlisting}}[language={}]\n{}\n\\end{{lstlisting}}",
+            l, block.content
+        ),
+        (Some(None), false, _) | (None, false, true) => format!(
+            "\\begin{{lstlisting}}\n{}\n\\end{{lstlisting}}",
+            block.content
+        ),
+        _ => format!("\\begin{{verbatim}}\n{}\n\\end{{verbatim}}", block.content),
+    }
+}
+
+fn render_list_block(block: ListBlock) -> String {
+    let env = match block.ordered {
+        true => "enumerate",
+        false => "itemize",
+    };
+
+    format!(
+        "\\begin{{{}}}\n{}\n\\end{{{}}}",
+        env,
+        block
+            .items
+            .into_iter()
+            .map(|i| format!("  \\item {}", render_inline_content(i.content)))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        env
+    )
+}
+
+fn render_dimension(value: &str, relative_to: &str) -> String {
+    // Percentages are relative to the text block, other units are passed to LaTeX as is.
+    match value
+        .trim()
+        .strip_suffix('%')
+        .map(|p| p.trim().parse::<f64>())
+    {
+        Some(Ok(p)) => format!("{}{}", p / 100.0, relative_to),
+        _ => value.trim().to_string(),
+    }
+}
+
+fn render_image_block(block: ImageBlock) -> String {
+    let options = vec![
+        block
+            .width
+            .map(|w| format!("width={}", render_dimension(&w, "\\linewidth"))),
+        block
+            .height
+            .map(|h| format!("height={}", render_dimension(&h, "\\textheight"))),
+        // Keep the aspect ratio when both are given.
+        Some("keepaspectratio".to_string()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<String>>()
+    .join(",");
+
+    let caption = match block.title.is_empty() {
+        true => "".to_string(),
+        false => format!("\n\\caption{{{}}}", escape(&block.title)),
+    };
+
+    format!(
+        "\\begin{{figure}}[h]\n\\centering\n\\includegraphics[{}]{{{}}}{}\n\\end{{figure}}",
+        options,
+        render_path(&block.source),
+        caption
+    )
+}
+
+pub fn render(blocks: Vec<Block>) -> Vec<String> {
+    blocks
+        .into_iter()
+        .map(|b| match b {
+            Block::Header(h) => render_header(h),
+            Block::Paragraph(p) => render_paragraph(p),
+            Block::Code(c) => render_code_block(c),
+            Block::List(l) => render_list_block(l),
+            Block::Image(i) => render_image_block(i),
+            Block::Custom(c) => render(c.content).join("\n\n"),
+        })
+        .collect::<Vec<String>>()
+}
+
+pub fn render_document(blocks: Vec<Block>) -> String {
+    format!(
+        "{}\n\\begin{{document}}\n\n{}\n\n\\end{{document}}\n",
+        PREAMBLE,
+        render(blocks).join("\n\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::{InlineImage, InlineText};
+
+    fn code(language: Option<&str>) -> String {
+        render_code_block(CodeBlock::new(
+            Style::Default,
+            "x".to_string(),
+            language.map(|l| l.to_string()),
+        ))
+    }
+
+    #[test]
+    fn code_languages_are_mapped_to_listings_names() {
+        assert_eq!(
+            code(Some("py")),
+            "\\begin{lstlisting}[language=Python]\nx\n\\end{lstlisting}"
+        );
+        assert_eq!(
+            code(Some("Bash")),
+            "\\begin{lstlisting}[language=bash]\nx\n\\end{lstlisting}"
+        );
+        for unknown in ["rust", "toml", "json"] {
+            assert_eq!(
+                code(Some(unknown)),
+                "\\begin{lstlisting}\nx\n\\end{lstlisting}"
+            );
+        }
+        assert_eq!(code(Some("")), "\\begin{verbatim}\nx\n\\end{verbatim}");
+        assert_eq!(code(None), "\\begin{verbatim}\nx\n\\end{verbatim}");
+    }
+
+    #[test]
+    fn code_never_ends_its_environment() {
+        let render = |content: &str, language: Option<&str>| {
+            render_code_block(CodeBlock::new(
+                Style::Default,
+                content.to_string(),
+                language.map(|l| l.to_string()),
+            ))
+        };
+
+        assert_eq!(
+            render("\\end{verbatim}", None),
+            "\\begin{lstlisting}\n\\end{verbatim}\n\\end{lstlisting}"
+        );
+        assert_eq!(
+            render("\\end{lstlisting}", Some("py")),
+            "\\begin{verbatim}\n\\end{lstlisting}\n\\end{verbatim}"
+        );
+        assert_eq!(
+            render("\\end{verbatim}\n\n\\end{lstlisting} |a|", None),
+            "\\par\\noindent\n\\verb|\\end{verbatim}|\\\\\n\\mbox{}\\\\\n\\verb!\\end{lstlisting} |a|!\\par"
+        );
+        assert_eq!(render_verb_line("|!+=/:;@x"), "\\verb@|!+=/:;@\\verb|@x|");
+    }
+
+    #[test]
+    fn header_levels_are_distinct() {
+        let headers: Vec<String> = [
+            HeaderLevel::H1,
+            HeaderLevel::H2,
+            HeaderLevel::H3,
+            HeaderLevel::H4,
+            HeaderLevel::H5,
+            HeaderLevel::H6,
+        ]
+        .into_iter()
+        .map(|level| {
+            render_header(HeaderBlock::new(
+                Style::Default,
+                level,
+                vec![InlineContent::Text(InlineText::new("A_b".to_string()))],
+                false,
+            ))
+        })
+        .collect();
+
+        assert_eq!(
+            headers,
+            vec![
+                "\\section{A\\_b}",
+                "\\subsection{A\\_b}",
+                "\\subsubsection{A\\_b}",
+                "\\paragraph{A\\_b}",
+                "\\subparagraph{A\\_b}",
+                "\\noindent\\textit{A\\_b}\\par",
+            ]
+        );
+    }
+
+    #[test]
+    fn image_sources_are_detokenized() {
+        let block = render_image_block(ImageBlock::new(
+            Style::Default,
+            "img/a_b & c~1.png".to_string(),
+            "A & B".to_string(),
+            "".to_string(),
+            None,
+            Some("50%".to_string()),
+        ));
+
+        assert_eq!(
+            block,
+            "\\begin{figure}[h]\n\\centering\n\\includegraphics[width=0.5\\linewidth,keepaspectratio]{\\detokenize{img/a_b & c~1.png}}\n\\caption{A \\& B}\n\\end{figure}"
+        );
+        assert_eq!(
+            render_inline_content(vec![InlineContent::Image(InlineImage::new(
+                Style::Default,
+                "a b_1.png".to_string(),
+                "".to_string(),
+                "".to_string(),
+            ))]),
+            "\\includegraphics[height=1em]{\\detokenize{a b_1.png}}"
+        );
+    }
+
+    #[test]
+    fn preamble_sets_up_fonts_and_packages() {
+        let document = render_document(vec![]);
+
+        assert!(document.starts_with(
+            "\\documentclass{article}\n\\usepackage[utf8]{inputenc}\n\\usepackage[T1]{fontenc}\n"
+        ));
+        assert!(document.contains("\\usepackage{listings}\n"));
+        assert!(document.ends_with("\\begin{document}\n\n\n\n\\end{document}\n"));
+    }
+}
@@ -0,0 +1,549 @@
+use crate::{
+    core::documents::{
+        Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, InlineContent, InlineLink,
+        ListBlock, ParagraphBlock, Style,
+    },
+    parsing::{block_parser::LineType, references::normalise_label},
+};
+
+// Markup characters in text are escaped so they read back as text. Underscores inside words
+// and single tildes are never markup, and are left alone to keep the source readable.
+fn escape_text(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+
+    for (i, c) in chars.iter().enumerate() {
+        let before = i.checked_sub(1).map(|n| chars[n]);
+        let after = chars.get(i + 1).copied();
+
+        let escape = match c {
+            '\\' | '*' | '`' | '[' | ']' => true,
+            '_' => {
+                !(before.is_some_and(char::is_alphanumeric)
+                    && after.is_some_and(char::is_alphanumeric))
+            }
+            '~' => before == Some('~') || after == Some('~'),
+            _ => false,
+        };
+
+        if escape {
+            out.push('\\');
+        }
+        out.push(*c);
+    }
+
+    out
+}
+
+// Text that would start a block when it starts a line gets an escape, e.g. `\# not a header`
+// or `1\. not a list`. Other markup at the start of a line is already escaped as text.
+fn escape_line_start(line: &str) -> String {
+    let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+
+    let at = match LineType::new(line) {
+        LineType::Header | LineType::CodeBlockDelimited | LineType::Include => Some(indent),
+        LineType::OrderedListItem => line.find('.'),
+        LineType::LinkDefinition => line.find("]:").map(|i| i + 1),
+        _ => None,
+    };
+
+    match at {
+        Some(i) => format!("{}\\{}", &line[..i], &line[i..]),
+        None => line.to_string(),
+    }
+}
+
+fn escape_lines(text: String) -> String {
+    text.split('\n')
+        .map(escape_line_start)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn escape_with(s: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        if c == '\\' || special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+// Destinations with spaces are written in angle brackets.
+fn escape_url(url: &str) -> String {
+    let escaped = escape_with(url, &['(', ')', '<', '>']);
+
+    match url.contains(char::is_whitespace) || url.is_empty() {
+        true => format!("<{}>", escaped),
+        false => escaped,
+    }
+}
+
+fn render_title(title: &str) -> String {
+    match title.is_empty() {
+        true => "".to_string(),
+        false => format!(" \"{}\"", escape_with(title, &['"', '(', ')'])),
+    }
+}
+
+// Info string values are always quoted.
+fn render_attribute(name: &str, value: &str) -> String {
+    format!("{}=\"{}\"", name, escape_with(value, &['"']))
+}
+
+// The fence is longer than any run of backticks in the code, and padded with spaces when the
+// code starts or ends with one, or starts and ends with a space (which would be stripped).
+fn render_code(code: String) -> String {
+    let longest = code
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest + 1);
+
+    let padded = code.starts_with('`')
+        || code.ends_with('`')
+        || (code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty());
+
+    match padded {
+        true => format!("{} {} {}", fence, code, fence),
+        false => format!("{}{}{}", fence, code, fence),
+    }
+}
+
+// Emphasis is written with `*`, or with `_` where a `*` would run into a `*` in the content
+// or next to the span and change how the runs pair up. `_` can't be used inside words.
+fn emphasis_marker(content: &str, before: Option<char>, after: Option<char>) -> char {
+    let touches = |c: char| {
+        content.starts_with(c) || content.ends_with(c) || before == Some(c) || after == Some(c)
+    };
+    let in_word =
+        before.is_some_and(char::is_alphanumeric) || after.is_some_and(char::is_alphanumeric);
+
+    match touches('*') && !touches('_') && !in_word {
+        true => '_',
+        false => '*',
+    }
+}
+
+// Code is already fenced by `render_code`, the other classes are written around it. `before`
+// and `after` are the characters written next to the span.
+fn render_span(content: String, style: Style, before: Option<char>, after: Option<char>) -> String {
+    let strike = style.has_class("s");
+
+    // Inside a strikethrough the emphasis is next to its tildes.
+    let marker = match strike {
+        true => emphasis_marker(&content, Some('~'), Some('~')),
+        false => emphasis_marker(&content, before, after),
+    };
+
+    let delimiter = match (style.has_class("b"), style.has_class("i")) {
+        (true, true) => marker.to_string().repeat(3),
+        (true, false) => marker.to_string().repeat(2),
+        (false, true) => marker.to_string(),
+        (false, false) => "".to_string(),
+    };
+    let content = format!("{}{}{}", delimiter, content, delimiter);
+
+    match strike {
+        true => format!("~~{}~~", content),
+        false => content,
+    }
+}
+
+// Reference links are written as they were, their definitions are written at the end of the
+// document by `render_link_definitions`.
+fn render_link(link: InlineLink) -> String {
+    let text = render_inline_content(link.content);
+
+    match link.reference {
+        Some(label) if normalise_label(&label) == normalise_label(&text) => {
+            format!("[{}]", text)
+        }
+        Some(label) => format!("[{}][{}]", text, label),
+        None => format!(
+            "[{}]({}{})",
+            text,
+            escape_url(&link.url),
+            render_title(&link.title)
+        ),
+    }
+}
+
+fn collect_link_definitions(
+    content: &[InlineContent],
+    definitions: &mut Vec<String>,
+    seen: &mut Vec<String>,
+) {
+    for c in content {
+        match c {
+            InlineContent::Span(s) => collect_link_definitions(&s.content, definitions, seen),
+            InlineContent::Link(l) => match &l.reference {
+                Some(label) if !seen.contains(&normalise_label(label)) => {
+                    seen.push(normalise_label(label));
+                    definitions.push(format!(
+                        "[{}]: {}{}",
+                        label,
+                        escape_url(&l.url),
+                        render_title(&l.title)
+                    ));
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+// The definitions of the reference links in the blocks, in the order they are first used.
+fn render_link_definitions(blocks: &[Block]) -> Option<String> {
+    let mut definitions = Vec::<String>::new();
+    let mut seen = Vec::<String>::new();
+
+    for block in blocks {
+        match block {
+            Block::Header(h) => collect_link_definitions(&h.content, &mut definitions, &mut seen),
+            Block::Paragraph(p) => {
+                collect_link_definitions(&p.content, &mut definitions, &mut seen)
+            }
+            Block::List(l) => {
+                for item in &l.items {
+                    collect_link_definitions(&item.content, &mut definitions, &mut seen);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match definitions.is_empty() {
+        true => None,
+        false => Some(definitions.join("\n")),
+    }
+}
+
+enum Piece {
+    Written(String),
+    // The written content of a span, its delimiters depend on what's around it.
+    Span(String, Style),
+}
+
+// Content is written left to right so each span knows what comes before it. What comes after
+// it is only known for content that isn't a span, a span after it picks its own marker.
+fn render_inline_content(content: Vec<InlineContent>) -> String {
+    let pieces: Vec<Piece> = content
+        .into_iter()
+        .map(|c| match c {
+            InlineContent::Text(t) => Piece::Written(escape_text(&t.content)),
+            InlineContent::Span(s) => match s.style.has_class("code") {
+                true => Piece::Span(render_code(InlineContent::plain_text(&s.content)), s.style),
+                false => Piece::Span(render_inline_content(s.content), s.style),
+            },
+            InlineContent::Link(l) => Piece::Written(render_link(l)),
+            InlineContent::Image(i) => Piece::Written(format!(
+                "![{}]({}{})",
+                escape_text(&i.alt_text),
+                escape_url(&i.source),
+                render_title(&i.title)
+            )),
+            InlineContent::Custom(c) => Piece::Written(c.source),
+        })
+        .collect();
+
+    let mut out = String::new();
+
+    for (i, piece) in pieces.iter().enumerate() {
+        match piece {
+            Piece::Written(s) => out.push_str(s),
+            Piece::Span(content, style) => {
+                let after = match pieces.get(i + 1) {
+                    Some(Piece::Written(s)) => s.chars().next(),
+                    _ => None,
+                };
+                let span = render_span(content.clone(), style.clone(), out.chars().last(), after);
+                out.push_str(&span);
+            }
+        }
+    }
+
+    out
+}
+
+fn render_header(block: HeaderBlock) -> String {
+    let prefix = match block.level {
+        HeaderLevel::H1 => "#",
+        HeaderLevel::H2 => "##",
+        HeaderLevel::H3 => "###",
+        HeaderLevel::H4 => "####",
+        HeaderLevel::H5 => "#####",
+        HeaderLevel::H6 => "######",
+    };
+
+    format!("{} {}", prefix, render_inline_content(block.content))
+}
+
+// Include directives that weren't resolved are written as they were read.
+fn render_paragraph(block: ParagraphBlock) -> String {
+    match block.style.has_class("include") {
+        true => InlineContent::plain_text(&block.content),
+        false => escape_lines(render_inline_content(block.content)),
+    }
+}
+
+fn render_ranges(ranges: &[(usize, usize)]) -> String {
+    ranges
+        .iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn render_info_string(block: &CodeBlock) -> String {
+    let mut words = vec![block.language.clone().unwrap_or_default()];
+
+    if let Some(title) = &block.title {
+        words.push(render_attribute("title", title));
+    }
+
+    if !block.highlighted_lines.is_empty() {
+        words.push(format!("{{{}}}", render_ranges(&block.highlighted_lines)));
+    }
+
+    match block.line_numbers {
+        Some(1) => words.push("linenos".to_string()),
+        Some(n) => words.push(format!("linenos={}", n)),
+        None => {}
+    }
+
+    if let Some(include) = &block.include {
+        words.push(render_attribute("include", &include.path));
+
+        if let Some(lines) = &include.lines {
+            words.push(render_attribute("lines", lines));
+        }
+
+        if let Some(region) = &include.region {
+            words.push(render_attribute("region", region));
+        }
+    }
+
+    words.join(" ").trim_end().to_string()
+}
+
+fn render_code_block(block: CodeBlock) -> String {
+    // Blocks filled in from an include are usually empty in the source.
+    match block.content.is_empty() {
+        true => format!("```{}\n```", render_info_string(&block)),
+        false => format!("```{}\n{}\n```", render_info_string(&block), block.content),
+    }
+}
+
+fn render_list_block(block: ListBlock) -> String {
+    let ordered = block.ordered;
+
+    block
+        .items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| match ordered {
+            true => format!(
+                "{}. {}",
+                i + 1,
+                escape_lines(render_inline_content(item.content))
+            ),
+            false => format!("* {}", escape_lines(render_inline_content(item.content))),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn render_image_block(block: ImageBlock) -> String {
+    // The attribute braces are always written, `processing::create_image_block` expects them.
+    let attributes = vec![
+        block.height.map(|h| format!("height:{}", h)),
+        block.width.map(|w| format!("width:{}", w)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<String>>()
+    .join(", ");
+
+    format!(
+        "![{}]({}{}){{{}}}",
+        escape_text(&block.alt_text),
+        escape_url(&block.source),
+        render_title(&block.title),
+        attributes
+    )
+}
+
+pub fn render(blocks: Vec<Block>) -> Vec<String> {
+    blocks
+        .into_iter()
+        .map(|b| match b {
+            Block::Header(h) => render_header(h),
+            Block::Paragraph(p) => render_paragraph(p),
+            Block::Code(c) => render_code_block(c),
+            Block::List(l) => render_list_block(l),
+            Block::Image(i) => render_image_block(i),
+            // Extension syntax is written back as it was read.
+            Block::Custom(c) => c.source,
+        })
+        .collect::<Vec<String>>()
+}
+
+pub fn render_document(blocks: Vec<Block>) -> String {
+    let definitions = render_link_definitions(&blocks);
+
+    let mut parts = render(blocks);
+    parts.extend(definitions);
+
+    format!("{}\n", parts.join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::formatting::Formatters,
+        parsing::{block_parser::Input, processing},
+    };
+
+    fn parse(markdown: &str) -> Vec<Block> {
+        let tokens =
+            Input::new(markdown.split('\n').collect()).parse_blocks(&Formatters::default());
+        processing::process_tokens(tokens)
+    }
+
+    // Writes the parsed document and checks it reads back as the same blocks.
+    fn round_trip(markdown: &str) -> String {
+        let blocks = parse(markdown);
+        let written = render_document(blocks.clone());
+
+        assert_eq!(
+            format!("{:?}", parse(&written)),
+            format!("{:?}", blocks),
+            "written as:\n{}",
+            written
+        );
+
+        written
+    }
+
+    #[test]
+    fn headers_and_paragraphs() {
+        round_trip("# Title\n\n## Sub *title*\n\n###### Six\n\nSome **text**.\n");
+    }
+
+    #[test]
+    fn lists() {
+        let written = round_trip("* one\n* *two*\n\n1. first\n2. `second`\n");
+
+        assert!(written.contains("* one\n* *two*"));
+        assert!(written.contains("1. first\n2. `second`"));
+    }
+
+    #[test]
+    fn code_fences_with_info_strings() {
+        let written = round_trip(
+            "```rust title=\"say \\\"hi\\\".rs\" {1,3-4} linenos=5\nfn main() {}\n```\n",
+        );
+
+        assert!(written.starts_with("```rust title=\"say \\\"hi\\\".rs\" {1,3-4} linenos=5\n"));
+
+        // Invalid line ranges are kept so that building the formatted file still fails.
+        let written = round_trip("```rust include=main.rs lines=40-10\n```\n");
+
+        assert!(written.starts_with("```rust include=\"main.rs\" lines=\"40-10\"\n"));
+    }
+
+    #[test]
+    fn images_with_attributes() {
+        let written = round_trip(
+            "![A *cat*](<my cat \\(1\\).png> \"The \\\"cat\\\"\"){height:10px, width:50%}\n",
+        );
+
+        assert_eq!(
+            written,
+            "![A cat](<my cat \\(1\\).png> \"The \\\"cat\\\"\"){height:10px, width:50%}\n"
+        );
+        round_trip("![x](a.png){}\n\n![](b.png \"t\"){width:3}\n");
+    }
+
+    #[test]
+    fn nested_spans() {
+        round_trip("**bold *italic* and ~~gone `code`~~** [a *link*](https://example.com \"T\")\n");
+        round_trip("Literal \\*stars\\*, snake_case, `` a`b `` and \\[brackets\\]\n");
+    }
+
+    #[test]
+    fn adjacent_emphasis_keeps_its_meaning() {
+        assert_eq!(round_trip("*a*__\\a__\n"), "*a*__\\\\a__\n");
+        assert_eq!(round_trip("_*~*@_\n"), "_*~*@_\n");
+        round_trip("**a***b* and *a*x**b** and ~~*a*~~*b*\n");
+        round_trip("***a** b* and **b*a*** and *a `*` b*\n");
+        round_trip("x*a*__b__*c*y\n");
+    }
+
+    #[test]
+    fn code_spans_keep_their_spaces() {
+        assert_eq!(round_trip("``  a  ``\n"), "`  a  `\n");
+        assert_eq!(round_trip("` a `\n"), "`a`\n");
+        assert_eq!(round_trip("`  `\n"), "`  `\n");
+        assert_eq!(round_trip("`` `a ``\n"), "`` `a ``\n");
+    }
+
+    #[test]
+    fn code_keeps_other_classes() {
+        let blocks = vec![Block::paragraph(ParagraphBlock::new(
+            Style::Default,
+            vec![InlineContent::Span(
+                crate::core::documents::InlineSpan::from_text(
+                    Style::Ref(vec!["b".to_string(), "code".to_string()]),
+                    "x".to_string(),
+                ),
+            )],
+        ))];
+
+        assert_eq!(render_document(blocks), "**`x`**\n");
+    }
+
+    #[test]
+    fn block_markers_are_escaped() {
+        let cases = [
+            ("\\# not a header", "\\# not a header"),
+            ("1\\. not a list", "1\\. not a list"),
+            ("\\* not a list", "\\* not a list"),
+            ("\\{{include a.md}}", "\\{{include a.md}}"),
+            ("\\[label\\]: /url", "\\[label\\]: /url"),
+            ("\\`\\`\\`rust", "\\`\\`\\`rust"),
+            ("``` a``b ``` code", "```a``b``` code"),
+        ];
+
+        for (markdown, expected) in cases {
+            let blocks = parse(markdown);
+            assert!(matches!(blocks[0], Block::Paragraph(_)), "{}", markdown);
+            assert_eq!(round_trip(markdown).trim_end(), expected);
+        }
+
+        assert_eq!(round_trip("* 1\\. item").trim_end(), "* 1\\. item");
+        assert_eq!(round_trip("* \\# item").trim_end(), "* \\# item");
+    }
+
+    #[test]
+    fn reference_links_keep_their_definitions() {
+        let written = round_trip("See [the docs][Docs] and [docs].\n\n[docs]: </my docs> \"D\"\n");
+
+        assert!(written.ends_with("[Docs]: </my docs> \"D\"\n"));
+
+        // A shortcut link followed by a colon isn't written as a definition.
+        let written = round_trip("[docs]\\: x\n\n[docs]: /d\n");
+        assert!(written.starts_with("[docs]\\: x\n"));
+    }
+}
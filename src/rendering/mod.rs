@@ -1 +1,12 @@
+pub mod docx;
+pub mod epub;
+pub mod gemtext;
+pub mod highlighting;
 pub mod html;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod latex;
+pub mod markdown;
+pub mod pdf;
+pub mod roff;
+pub mod terminal;
@@ -0,0 +1,1197 @@
+use std::{fs, path::Path};
+
+use crate::core::{
+    documents::{
+        Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, InlineContent, ListBlock,
+        ParagraphBlock, Style,
+    },
+    inflate,
+};
+
+// A4 in points.
+const PAGE_WIDTH: f64 = 595.0;
+const PAGE_HEIGHT: f64 = 842.0;
+const MARGIN: f64 = 72.0;
+const CONTENT_WIDTH: f64 = PAGE_WIDTH - 2.0 * MARGIN;
+
+const BODY_SIZE: f64 = 11.0;
+const CODE_SIZE: f64 = 9.0;
+const LEADING: f64 = 1.4;
+const LIST_INDENT: f64 = 18.0;
+const BLOCK_SPACING: f64 = 8.0;
+const CODE_PADDING: f64 = 6.0;
+
+// Helvetica and Helvetica-Bold advance widths for ASCII 32 to 126, from the Adobe AFM files.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667,
+    611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556,
+    278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+}
+
+#[derive(Clone)]
+struct Run {
+    text: String,
+    font: Font,
+    size: f64,
+    link: Option<String>,
+}
+
+struct Word {
+    runs: Vec<Run>,
+    width: f64,
+}
+
+struct LinkAnnotation {
+    rect: (f64, f64, f64, f64),
+    url: String,
+}
+
+struct Page {
+    content: Vec<u8>,
+    links: Vec<LinkAnnotation>,
+}
+
+struct PdfImage {
+    dictionary: String,
+    data: Vec<u8>,
+    mask: Option<(String, Vec<u8>)>,
+    width: f64,
+    height: f64,
+}
+
+struct Layout<'a> {
+    pages: Vec<Page>,
+    y: f64,
+    images: Vec<PdfImage>,
+    base_path: &'a Path,
+}
+
+impl Font {
+    fn resource(&self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+            Font::Italic => "F3",
+            Font::BoldItalic => "F4",
+            Font::Mono => "F5",
+        }
+    }
+
+    fn base_font(&self) -> &'static str {
+        match self {
+            Font::Regular => "Helvetica",
+            Font::Bold => "Helvetica-Bold",
+            Font::Italic => "Helvetica-Oblique",
+            Font::BoldItalic => "Helvetica-BoldOblique",
+            Font::Mono => "Courier",
+        }
+    }
+
+    fn from_style(bold: bool, italic: bool, code: bool) -> Font {
+        match (bold, italic, code) {
+            (_, _, true) => Font::Mono,
+            (true, true, false) => Font::BoldItalic,
+            (true, false, false) => Font::Bold,
+            (false, true, false) => Font::Italic,
+            (false, false, false) => Font::Regular,
+        }
+    }
+
+    fn is_bold(&self) -> bool {
+        matches!(self, Font::Bold | Font::BoldItalic)
+    }
+
+    fn char_width(&self, b: u8) -> u16 {
+        let widths = match self {
+            Font::Mono => return 600,
+            Font::Bold | Font::BoldItalic => &HELVETICA_BOLD_WIDTHS,
+            Font::Regular | Font::Italic => &HELVETICA_WIDTHS,
+        };
+
+        match b {
+            32..=126 => widths[(b - 32) as usize],
+            0x95 => 350,
+            0x85 | 0x97 => 1000,
+            _ if self.is_bold() => 611,
+            _ => 556,
+        }
+    }
+
+    fn text_width(&self, text: &str, size: f64) -> f64 {
+        encode_text(text)
+            .iter()
+            .map(|b| self.char_width(*b) as f64)
+            .sum::<f64>()
+            * size
+            / 1000.0
+    }
+}
+
+// The standard fonts are written with WinAnsiEncoding, which matches Latin-1 apart from
+// a few punctuation characters. Anything else can not be shown and becomes `?`.
+fn encode_char(c: char) -> u8 {
+    match c {
+        '€' => 0x80,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '\t' => b' ',
+        c if (c as u32) < 0x80 || (0xA0..=0xFF).contains(&(c as u32)) => c as u32 as u8,
+        _ => b'?',
+    }
+}
+
+fn encode_text(text: &str) -> Vec<u8> {
+    text.chars().map(encode_char).collect()
+}
+
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut out = vec![b'('];
+
+    for b in encode_text(text) {
+        match b {
+            b'(' | b')' | b'\\' => {
+                out.push(b'\\');
+                out.push(b);
+            }
+            _ => out.push(b),
+        }
+    }
+
+    out.push(b')');
+    out
+}
+
+fn format_number(n: f64) -> String {
+    format!("{:.2}", n)
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+fn inline_runs(content: Vec<InlineContent>, base: Font, size: f64) -> Vec<Run> {
+    let bold = base.is_bold();
+
+    InlineContent::runs(content)
+        .into_iter()
+        .map(|r| Run {
+            font: match &r.style {
+                Style::Default => base,
+                style => span_font(style, bold),
+            },
+            text: r.text,
+            size,
+            link: r.link,
+        })
+        .collect()
+}
+
+fn span_font(style: &Style, bold: bool) -> Font {
+    Font::from_style(
+        bold || style.has_class("b"),
+        style.has_class("i"),
+        style.has_class("code"),
+    )
+}
+
+fn split_words(runs: Vec<Run>) -> Vec<Word> {
+    let mut words = Vec::<Word>::new();
+    let mut glue = false;
+
+    for run in runs {
+        for (i, part) in run.text.split(char::is_whitespace).enumerate() {
+            if i > 0 {
+                glue = false;
+            }
+
+            if part.is_empty() {
+                continue;
+            }
+
+            let piece = Run {
+                text: part.to_string(),
+                ..run.clone()
+            };
+            let width = run.font.text_width(part, run.size);
+
+            match (glue, words.last_mut()) {
+                (true, Some(w)) => {
+                    w.width += width;
+                    w.runs.push(piece);
+                }
+                _ => words.push(Word {
+                    runs: vec![piece],
+                    width,
+                }),
+            }
+
+            glue = true;
+        }
+    }
+
+    words
+}
+
+fn wrap_words(words: Vec<Word>, width: f64) -> Vec<Vec<Word>> {
+    let mut lines = Vec::<Vec<Word>>::new();
+    let mut line = Vec::<Word>::new();
+    let mut line_width = 0.0;
+
+    for w in words {
+        let space = w.runs[0].font.text_width(" ", w.runs[0].size);
+
+        if !line.is_empty() && line_width + space + w.width > width {
+            lines.push(line);
+            line = Vec::new();
+            line_width = 0.0;
+        }
+
+        if !line.is_empty() {
+            line_width += space;
+        }
+
+        line_width += w.width;
+        line.push(w);
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn image_dimension(value: &Option<String>, relative_to: f64) -> Option<f64> {
+    let v = value.as_ref()?.trim();
+
+    match v.strip_suffix('%') {
+        Some(p) => p
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .map(|p| p / 100.0 * relative_to),
+        // Pixels are taken at 96 dpi.
+        None => v
+            .trim_end_matches("px")
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .map(|px| px * 0.75),
+    }
+}
+
+fn read_u32(data: &[u8], i: usize) -> Result<u32, String> {
+    data.get(i..i + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "PNG chunk is truncated".to_string())
+}
+
+fn load_jpeg(data: Vec<u8>) -> Result<PdfImage, String> {
+    let mut i = 2;
+
+    // Walk the markers until a start of frame gives the dimensions.
+    while i + 9 < data.len() {
+        if data[i] != 0xFF {
+            return Err("Invalid JPEG marker".to_string());
+        }
+
+        let marker = data[i + 1];
+        let length = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+
+        if (0xC0..=0xCF).contains(&marker) && marker != 0xC4 && marker != 0xC8 && marker != 0xCC {
+            let height = u16::from_be_bytes([data[i + 5], data[i + 6]]);
+            let width = u16::from_be_bytes([data[i + 7], data[i + 8]]);
+            let colour_space = match data[i + 9] {
+                1 => "/DeviceGray",
+                4 => "/DeviceCMYK",
+                _ => "/DeviceRGB",
+            };
+
+            return Ok(PdfImage {
+                dictionary: format!(
+                    "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent 8 /Filter /DCTDecode",
+                    width, height, colour_space
+                ),
+                data,
+                mask: None,
+                width: width as f64,
+                height: height as f64,
+            });
+        }
+
+        i += 2 + length;
+    }
+
+    Err("JPEG has no frame header".to_string())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+
+    match (pa <= pb && pa <= pc, pb <= pc) {
+        (true, _) => a,
+        (false, true) => b,
+        (false, false) => c,
+    }
+}
+
+fn unfilter_png(data: &[u8], stride: usize, bpp: usize, rows: usize) -> Result<Vec<u8>, String> {
+    let mut out = vec![0u8; stride * rows];
+
+    for row in 0..rows {
+        let start = row * (stride + 1);
+        let filter = *data
+            .get(start)
+            .ok_or_else(|| "PNG image data is truncated".to_string())?;
+        let line = data
+            .get(start + 1..start + 1 + stride)
+            .ok_or_else(|| "PNG image data is truncated".to_string())?;
+
+        for x in 0..stride {
+            let a = if x >= bpp {
+                out[row * stride + x - bpp]
+            } else {
+                0
+            };
+            let b = if row > 0 {
+                out[(row - 1) * stride + x]
+            } else {
+                0
+            };
+            let c = if row > 0 && x >= bpp {
+                out[(row - 1) * stride + x - bpp]
+            } else {
+                0
+            };
+
+            out[row * stride + x] = match filter {
+                0 => line[x],
+                1 => line[x].wrapping_add(a),
+                2 => line[x].wrapping_add(b),
+                3 => line[x].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+                4 => line[x].wrapping_add(paeth(a, b, c)),
+                _ => return Err("Invalid PNG filter".to_string()),
+            };
+        }
+    }
+
+    Ok(out)
+}
+
+fn load_png(data: Vec<u8>) -> Result<PdfImage, String> {
+    let mut i = 8;
+    let mut header: Option<(u32, u32, u8, u8, u8)> = None;
+    let mut palette = Vec::<u8>::new();
+    let mut compressed = Vec::<u8>::new();
+
+    while i + 8 <= data.len() {
+        let length = read_u32(&data, i)? as usize;
+        let kind = &data[i + 4..i + 8];
+        let body = (i + 8)
+            .checked_add(length)
+            .and_then(|end| data.get(i + 8..end))
+            .ok_or_else(|| "PNG chunk is truncated".to_string())?;
+
+        match (kind, body.len() >= 13) {
+            (b"IHDR", true) => {
+                header = Some((
+                    read_u32(body, 0)?,
+                    read_u32(body, 4)?,
+                    body[8],
+                    body[9],
+                    body[12],
+                ))
+            }
+            (b"IHDR", false) => return Err("PNG header is truncated".to_string()),
+            (b"PLTE", _) => palette = body.to_vec(),
+            (b"IDAT", _) => compressed.extend_from_slice(body),
+            (b"IEND", _) => break,
+            _ => {}
+        }
+
+        // Length, type, data and CRC.
+        i += 12 + length;
+    }
+
+    let (width, height, depth, colour_type, interlace) =
+        header.ok_or_else(|| "PNG has no header".to_string())?;
+
+    if interlace != 0 {
+        return Err("Interlaced PNG images are not supported".to_string());
+    }
+
+    // Indexed colour needs a palette of whole RGB entries.
+    if colour_type == 3 && (palette.is_empty() || !palette.len().is_multiple_of(3)) {
+        return Err("PNG palette is missing or invalid".to_string());
+    }
+
+    let dictionary = format!(
+        "/Type /XObject /Subtype /Image /Width {} /Height {} /BitsPerComponent {}",
+        width, height, depth
+    );
+
+    match colour_type {
+        // Without alpha the compressed data can be used as is with the PNG predictors.
+        0 | 2 | 3 => {
+            let (colours, colour_space) = match colour_type {
+                0 => (1, "/DeviceGray".to_string()),
+                2 => (3, "/DeviceRGB".to_string()),
+                _ => (
+                    1,
+                    format!(
+                        "[/Indexed /DeviceRGB {} <{}>]",
+                        palette.len() / 3 - 1,
+                        palette
+                            .iter()
+                            .map(|b| format!("{:02X}", b))
+                            .collect::<String>()
+                    ),
+                ),
+            };
+
+            Ok(PdfImage {
+                dictionary: format!(
+                    "{} /ColorSpace {} /Filter /FlateDecode /DecodeParms << /Predictor 15 /Colors {} /BitsPerComponent {} /Columns {} >>",
+                    dictionary, colour_space, colours, depth, width
+                ),
+                data: compressed,
+                mask: None,
+                width: width as f64,
+                height: height as f64,
+            })
+        }
+        // Alpha has to be split out into a soft mask, so the pixels are decoded.
+        4 | 6 => {
+            let channels = if colour_type == 4 { 2 } else { 4 };
+            let sample = (depth / 8).max(1) as usize;
+            let bpp = channels * sample;
+            let stride = width as usize * bpp;
+            let pixels = unfilter_png(
+                &inflate::inflate_zlib(&compressed)?,
+                stride,
+                bpp,
+                height as usize,
+            )?;
+
+            let mut colour = Vec::<u8>::new();
+            let mut alpha = Vec::<u8>::new();
+
+            // 16 bit samples are reduced to their high byte.
+            for pixel in pixels.chunks(bpp) {
+                for c in 0..channels - 1 {
+                    colour.push(pixel[c * sample]);
+                }
+                alpha.push(pixel[(channels - 1) * sample]);
+            }
+
+            let colour_space = if colour_type == 4 {
+                "/DeviceGray"
+            } else {
+                "/DeviceRGB"
+            };
+
+            Ok(PdfImage {
+                dictionary: format!(
+                    "/Type /XObject /Subtype /Image /Width {} /Height {} /BitsPerComponent 8 /ColorSpace {}",
+                    width, height, colour_space
+                ),
+                data: colour,
+                mask: Some((
+                    format!(
+                        "/Type /XObject /Subtype /Image /Width {} /Height {} /BitsPerComponent 8 /ColorSpace /DeviceGray",
+                        width, height
+                    ),
+                    alpha,
+                )),
+                width: width as f64,
+                height: height as f64,
+            })
+        }
+        _ => Err("Unsupported PNG colour type".to_string()),
+    }
+}
+
+fn load_image(path: &Path) -> Result<PdfImage, String> {
+    let data =
+        fs::read(path).map_err(|e| format!("Could not read image `{}`: {}", path.display(), e))?;
+
+    match (data.get(0..2), data.get(0..8)) {
+        (Some([0xFF, 0xD8]), _) => load_jpeg(data),
+        (_, Some(b"\x89PNG\r\n\x1a\n")) => load_png(data),
+        _ => Err(format!(
+            "Unsupported image format `{}` (only PNG and JPEG are supported)",
+            path.display()
+        )),
+    }
+}
+
+impl<'a> Layout<'a> {
+    fn new(base_path: &'a Path) -> Layout<'a> {
+        Layout {
+            pages: vec![Page {
+                content: vec![],
+                links: vec![],
+            }],
+            y: PAGE_HEIGHT - MARGIN,
+            images: vec![],
+            base_path,
+        }
+    }
+
+    fn page(&mut self) -> &mut Page {
+        self.pages.last_mut().unwrap()
+    }
+
+    fn write(&mut self, s: &str) {
+        self.page().content.extend_from_slice(s.as_bytes());
+    }
+
+    fn ensure_space(&mut self, height: f64) {
+        if self.y - height < MARGIN && self.y < PAGE_HEIGHT - MARGIN {
+            self.pages.push(Page {
+                content: vec![],
+                links: vec![],
+            });
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+    }
+
+    fn space(&mut self, height: f64) {
+        // Spacing is dropped at the top of a page.
+        if self.y < PAGE_HEIGHT - MARGIN {
+            self.y -= height;
+        }
+    }
+
+    fn draw_text(&mut self, text: &str, font: Font, size: f64, x: f64, baseline: f64) {
+        self.write(&format!(
+            "BT /{} {} Tf {} {} Td ",
+            font.resource(),
+            format_number(size),
+            format_number(x),
+            format_number(baseline)
+        ));
+        let s = pdf_string(text);
+        self.page().content.extend_from_slice(&s);
+        self.write(" Tj ET\n");
+    }
+
+    fn draw_line(&mut self, words: Vec<Word>, x: f64, line_height: f64) {
+        self.ensure_space(line_height);
+
+        let size = words
+            .iter()
+            .flat_map(|w| w.runs.iter().map(|r| r.size))
+            .fold(0.0, f64::max);
+        let baseline = self.y - size;
+        let mut cursor = x;
+
+        for (i, word) in words.into_iter().enumerate() {
+            if i > 0 {
+                cursor += word.runs[0].font.text_width(" ", word.runs[0].size);
+            }
+
+            for run in word.runs {
+                let width = run.font.text_width(&run.text, run.size);
+
+                match &run.link {
+                    Some(url) => {
+                        self.write("0 0 0.75 rg\n");
+                        self.draw_text(&run.text, run.font, run.size, cursor, baseline);
+                        self.write("0 g\n");
+                        self.page().links.push(LinkAnnotation {
+                            rect: (
+                                cursor,
+                                baseline - run.size * 0.25,
+                                cursor + width,
+                                baseline + run.size,
+                            ),
+                            url: url.clone(),
+                        });
+                    }
+                    None => self.draw_text(&run.text, run.font, run.size, cursor, baseline),
+                }
+
+                cursor += width;
+            }
+        }
+
+        self.y -= line_height;
+    }
+
+    fn draw_paragraph(&mut self, runs: Vec<Run>, x: f64, width: f64) {
+        let size = runs.iter().map(|r| r.size).fold(BODY_SIZE, f64::max);
+
+        for line in wrap_words(split_words(runs), width) {
+            self.draw_line(line, x, size * LEADING);
+        }
+    }
+
+    fn render_header(&mut self, block: HeaderBlock) {
+        let size = match block.level {
+            HeaderLevel::H1 => 24.0,
+            HeaderLevel::H2 => 20.0,
+            HeaderLevel::H3 => 16.0,
+            HeaderLevel::H4 => 14.0,
+            HeaderLevel::H5 => 12.0,
+            HeaderLevel::H6 => 11.0,
+        };
+
+        self.space(size * 0.5);
+        // Keep the header with at least one line of what follows.
+        self.ensure_space(size * LEADING + BODY_SIZE * LEADING);
+        self.draw_paragraph(
+            inline_runs(block.content, Font::Bold, size),
+            MARGIN,
+            CONTENT_WIDTH,
+        );
+    }
+
+    fn render_paragraph(&mut self, block: ParagraphBlock) {
+        self.draw_paragraph(
+            inline_runs(block.content, Font::Regular, BODY_SIZE),
+            MARGIN,
+            CONTENT_WIDTH,
+        );
+    }
+
+    fn render_list(&mut self, block: ListBlock) {
+        for (i, item) in block.items.into_iter().enumerate() {
+            let marker = match block.ordered {
+                true => format!("{}.", i + 1),
+                false => "•".to_string(),
+            };
+
+            self.ensure_space(BODY_SIZE * LEADING);
+            self.draw_text(
+                &marker,
+                Font::Regular,
+                BODY_SIZE,
+                MARGIN + LIST_INDENT - Font::Regular.text_width(&marker, BODY_SIZE) - 4.0,
+                self.y - BODY_SIZE,
+            );
+            self.draw_paragraph(
+                inline_runs(item.content, Font::Regular, BODY_SIZE),
+                MARGIN + LIST_INDENT,
+                CONTENT_WIDTH - LIST_INDENT,
+            );
+        }
+    }
+
+    fn render_code(&mut self, block: CodeBlock) {
+        let line_height = CODE_SIZE * 1.3;
+        let per_line = ((CONTENT_WIDTH - 2.0 * CODE_PADDING) / (CODE_SIZE * 0.6)) as usize;
+
+        let lines: Vec<String> = block
+            .content
+            .split('\n')
+            .flat_map(|l| {
+                let chars: Vec<char> = l.replace('\t', "    ").chars().collect();
+                match chars.is_empty() {
+                    true => vec!["".to_string()],
+                    false => chars.chunks(per_line).map(|c| c.iter().collect()).collect(),
+                }
+            })
+            .collect();
+
+        let count = lines.len();
+
+        for (i, line) in lines.into_iter().enumerate() {
+            // Padding above the first and below the last line is part of their background.
+            let top = if i == 0 { CODE_PADDING } else { 0.0 };
+            let bottom = if i + 1 == count { CODE_PADDING } else { 0.0 };
+
+            self.ensure_space(top + line_height + bottom);
+
+            self.write(&format!(
+                "0.95 g {} {} {} {} re f 0 g\n",
+                format_number(MARGIN),
+                format_number(self.y - top - line_height - bottom),
+                format_number(CONTENT_WIDTH),
+                format_number(top + line_height + bottom)
+            ));
+
+            self.y -= top;
+            self.draw_text(
+                &line,
+                Font::Mono,
+                CODE_SIZE,
+                MARGIN + CODE_PADDING,
+                self.y - CODE_SIZE,
+            );
+            self.y -= line_height + bottom;
+        }
+    }
+
+    fn render_image(&mut self, block: ImageBlock) -> Result<(), String> {
+        if block.source.contains("://") {
+            // Remote images are not fetched, a link is written in their place.
+            let run = Run {
+                text: format!("[Image: {}]", block.alt_text),
+                font: Font::Italic,
+                size: BODY_SIZE,
+                link: Some(block.source),
+            };
+            self.draw_paragraph(vec![run], MARGIN, CONTENT_WIDTH);
+            return Ok(());
+        }
+
+        let image = load_image(&self.base_path.join(&block.source))?;
+        let max_height = PAGE_HEIGHT - 2.0 * MARGIN;
+
+        let (w, h) = match (
+            image_dimension(&block.width, CONTENT_WIDTH),
+            image_dimension(&block.height, max_height),
+        ) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, w * image.height / image.width),
+            (None, Some(h)) => (h * image.width / image.height, h),
+            (None, None) => (image.width * 0.75, image.height * 0.75),
+        };
+
+        // Scale down to fit the page, keeping the aspect ratio.
+        let scale = (CONTENT_WIDTH / w).min(max_height / h).min(1.0);
+        let (w, h) = (w * scale, h * scale);
+
+        self.ensure_space(h);
+
+        let name = format!("Im{}", self.images.len() + 1);
+        self.images.push(image);
+
+        self.write(&format!(
+            "q {} 0 0 {} {} {} cm /{} Do Q\n",
+            format_number(w),
+            format_number(h),
+            format_number(MARGIN + (CONTENT_WIDTH - w) / 2.0),
+            format_number(self.y - h),
+            name
+        ));
+        self.y -= h;
+
+        if !block.title.is_empty() {
+            self.space(4.0);
+            let run = Run {
+                text: block.title,
+                font: Font::Italic,
+                size: CODE_SIZE,
+                link: None,
+            };
+            self.draw_paragraph(vec![run], MARGIN, CONTENT_WIDTH);
+        }
+
+        Ok(())
+    }
+}
+
+fn write_object(out: &mut Vec<u8>, offsets: &mut Vec<usize>, body: &[u8]) {
+    offsets.push(out.len());
+    out.extend_from_slice(format!("{} 0 obj\n", offsets.len()).as_bytes());
+    out.extend_from_slice(body);
+    out.extend_from_slice(b"\nendobj\n");
+}
+
+fn stream_object(dictionary: &str, data: &[u8]) -> Vec<u8> {
+    let mut body = format!("<< {} /Length {} >>\nstream\n", dictionary, data.len()).into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(b"\nendstream");
+    body
+}
+
+fn write_document(layout: Layout) -> Vec<u8> {
+    let fonts = [
+        Font::Regular,
+        Font::Bold,
+        Font::Italic,
+        Font::BoldItalic,
+        Font::Mono,
+    ];
+
+    // Object numbers: 1 catalog, 2 page tree, 3 resources, then fonts, images and pages.
+    let font_start = 4;
+    let image_start = font_start + fonts.len();
+    let image_numbers: Vec<(usize, Option<usize>)> = layout
+        .images
+        .iter()
+        .scan(image_start, |next, i| {
+            let number = *next;
+            let mask = i.mask.as_ref().map(|_| number + 1);
+            *next += if mask.is_some() { 2 } else { 1 };
+            Some((number, mask))
+        })
+        .collect();
+    let page_start = image_start
+        + layout
+            .images
+            .iter()
+            .map(|i| if i.mask.is_some() { 2 } else { 1 })
+            .sum::<usize>();
+
+    // Each page takes a page object, a content stream and one object per link.
+    let mut page_numbers = Vec::<usize>::new();
+    let mut next = page_start;
+    for page in &layout.pages {
+        page_numbers.push(next);
+        next += 2 + page.links.len();
+    }
+
+    // 1.5 for the 16 bit samples PNG images can have.
+    let mut out = b"%PDF-1.5\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = Vec::<usize>::new();
+
+    write_object(&mut out, &mut offsets, b"<< /Type /Catalog /Pages 2 0 R >>");
+
+    write_object(
+        &mut out,
+        &mut offsets,
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_numbers
+                .iter()
+                .map(|n| format!("{} 0 R", n))
+                .collect::<Vec<String>>()
+                .join(" "),
+            page_numbers.len()
+        )
+        .as_bytes(),
+    );
+
+    write_object(
+        &mut out,
+        &mut offsets,
+        format!(
+            "<< /Font << {} >> /XObject << {} >> >>",
+            fonts
+                .iter()
+                .enumerate()
+                .map(|(i, f)| format!("/{} {} 0 R", f.resource(), font_start + i))
+                .collect::<Vec<String>>()
+                .join(" "),
+            image_numbers
+                .iter()
+                .enumerate()
+                .map(|(i, (n, _))| format!("/Im{} {} 0 R", i + 1, n))
+                .collect::<Vec<String>>()
+                .join(" ")
+        )
+        .as_bytes(),
+    );
+
+    for f in fonts.iter() {
+        write_object(
+            &mut out,
+            &mut offsets,
+            format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                f.base_font()
+            )
+            .as_bytes(),
+        );
+    }
+
+    for (image, (_, mask_number)) in layout.images.iter().zip(image_numbers.iter()) {
+        let dictionary = match mask_number {
+            Some(m) => format!("{} /SMask {} 0 R", image.dictionary, m),
+            None => image.dictionary.clone(),
+        };
+
+        write_object(
+            &mut out,
+            &mut offsets,
+            &stream_object(&dictionary, &image.data),
+        );
+
+        if let Some((mask_dictionary, mask_data)) = &image.mask {
+            write_object(
+                &mut out,
+                &mut offsets,
+                &stream_object(mask_dictionary, mask_data),
+            );
+        }
+    }
+
+    for (page, number) in layout.pages.iter().zip(page_numbers.iter()) {
+        let annotations = (0..page.links.len())
+            .map(|i| format!("{} 0 R", number + 2 + i))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        write_object(
+            &mut out,
+            &mut offsets,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources 3 0 R /Contents {} 0 R /Annots [{}] >>",
+                format_number(PAGE_WIDTH),
+                format_number(PAGE_HEIGHT),
+                number + 1,
+                annotations
+            )
+            .as_bytes(),
+        );
+
+        write_object(&mut out, &mut offsets, &stream_object("", &page.content));
+
+        for link in &page.links {
+            let (x1, y1, x2, y2) = link.rect;
+            let mut body = format!(
+                "<< /Type /Annot /Subtype /Link /Border [0 0 0] /Rect [{} {} {} {}] /A << /S /URI /URI ",
+                format_number(x1),
+                format_number(y1),
+                format_number(x2),
+                format_number(y2)
+            )
+            .into_bytes();
+            body.extend_from_slice(&pdf_string(&link.url));
+            body.extend_from_slice(b" >> >>");
+
+            write_object(&mut out, &mut offsets, &body);
+        }
+    }
+
+    let xref = out.len();
+    out.extend_from_slice(
+        format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1).as_bytes(),
+    );
+    for o in &offsets {
+        out.extend_from_slice(format!("{:010} 00000 n \n", o).as_bytes());
+    }
+    out.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            offsets.len() + 1,
+            xref
+        )
+        .as_bytes(),
+    );
+
+    out
+}
+
+fn render_blocks(layout: &mut Layout, blocks: Vec<Block>) -> Result<(), String> {
+    for (i, block) in blocks.into_iter().enumerate() {
+        if i > 0 {
+            layout.space(BLOCK_SPACING);
+        }
+
+        match block {
+            Block::Header(h) => layout.render_header(h),
+            Block::Paragraph(p) => layout.render_paragraph(p),
+            Block::Code(c) => layout.render_code(c),
+            Block::List(l) => layout.render_list(l),
+            Block::Image(i) => layout.render_image(i)?,
+            Block::Custom(c) => render_blocks(layout, c.content)?,
+        }
+    }
+
+    Ok(())
+}
+
+// Local image sources are resolved relative to `base_path`.
+pub fn write(blocks: Vec<Block>, base_path: &Path) -> Result<Vec<u8>, String> {
+    let mut layout = Layout::new(base_path);
+    render_blocks(&mut layout, blocks)?;
+
+    Ok(write_document(layout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::{InlineText, ParagraphBlock};
+
+    // CRCs aren't checked, so they are left as zero.
+    fn png(chunks: &[(&[u8], Vec<u8>)]) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        for (kind, body) in chunks {
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            data.extend_from_slice(kind);
+            data.extend_from_slice(body);
+            data.extend_from_slice(&[0, 0, 0, 0]);
+        }
+
+        data
+    }
+
+    fn header(depth: u8, colour_type: u8) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 1, 0, 0, 0, 1];
+        body.extend_from_slice(&[depth, colour_type, 0, 0, 0]);
+        body
+    }
+
+    fn find(data: &[u8], s: &str) -> Option<usize> {
+        data.windows(s.len()).position(|w| w == s.as_bytes())
+    }
+
+    #[test]
+    fn png_images() {
+        let rgb = load_png(png(&[
+            (b"IHDR", header(8, 2)),
+            (b"IDAT", vec![120, 156, 99, 248, 207, 192, 0, 0, 3, 1, 1, 0]),
+            (b"IEND", vec![]),
+        ]))
+        .unwrap();
+
+        assert!(rgb
+            .dictionary
+            .contains("/Width 1 /Height 1 /BitsPerComponent 8"));
+        assert!(rgb
+            .dictionary
+            .contains("/ColorSpace /DeviceRGB /Filter /FlateDecode"));
+        assert!(rgb.mask.is_none());
+
+        let rgba = load_png(png(&[
+            (b"IHDR", header(8, 6)),
+            (
+                b"IDAT",
+                vec![120, 156, 99, 224, 18, 145, 211, 0, 0, 0, 205, 0, 101],
+            ),
+            (b"IEND", vec![]),
+        ]))
+        .unwrap();
+
+        assert_eq!(rgba.data, vec![10, 20, 30]);
+        assert_eq!(rgba.mask.map(|(_, alpha)| alpha), Some(vec![40]));
+
+        let indexed = load_png(png(&[
+            (b"IHDR", header(8, 3)),
+            (b"PLTE", vec![255, 0, 0, 0, 0, 255]),
+            (b"IDAT", vec![]),
+        ]))
+        .unwrap();
+
+        assert!(indexed
+            .dictionary
+            .contains("/ColorSpace [/Indexed /DeviceRGB 1 <FF00000000FF>]"));
+    }
+
+    #[test]
+    fn invalid_png_images_are_errors() {
+        let error = |data: Vec<u8>| load_png(data).err().unwrap();
+
+        assert_eq!(
+            error(png(&[(b"IHDR", vec![0, 0, 0, 1, 0, 0, 0, 1])])),
+            "PNG header is truncated"
+        );
+
+        let mut truncated = png(&[(b"IHDR", header(8, 2))]);
+        truncated.truncate(20);
+        assert_eq!(error(truncated), "PNG chunk is truncated");
+
+        let mut length = b"\x89PNG\r\n\x1a\n".to_vec();
+        length.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        length.extend_from_slice(b"IDAT");
+        assert_eq!(error(length), "PNG chunk is truncated");
+
+        assert_eq!(
+            error(png(&[(b"IHDR", header(8, 3)), (b"IDAT", vec![])])),
+            "PNG palette is missing or invalid"
+        );
+        assert_eq!(
+            error(png(&[
+                (b"IHDR", header(8, 3)),
+                (b"PLTE", vec![255, 0]),
+                (b"IDAT", vec![])
+            ])),
+            "PNG palette is missing or invalid"
+        );
+        assert_eq!(error(png(&[(b"IEND", vec![])])), "PNG has no header");
+    }
+
+    #[test]
+    fn rendered_documents_have_a_valid_structure() {
+        let dir = std::env::temp_dir().join(format!("rdom-pdf-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("a.png"),
+            png(&[
+                (b"IHDR", header(16, 2)),
+                (b"IDAT", vec![]),
+                (b"IEND", vec![]),
+            ]),
+        )
+        .unwrap();
+
+        let blocks = vec![
+            Block::paragraph(ParagraphBlock::new(
+                Style::Default,
+                vec![InlineContent::Text(InlineText::new(
+                    "Hello (PDF)".to_string(),
+                ))],
+            )),
+            Block::image(ImageBlock::new(
+                Style::Default,
+                "a.png".to_string(),
+                "".to_string(),
+                "".to_string(),
+                None,
+                None,
+            )),
+        ];
+
+        let pdf = write(blocks, &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(pdf.starts_with(b"%PDF-1.5\n"));
+        assert!(find(&pdf, "/BitsPerComponent 16").is_some());
+        assert!(pdf.ends_with(b"%%EOF\n"));
+
+        // Follow `startxref` to the table and check every entry points at its object.
+        let tail = String::from_utf8_lossy(&pdf[find(&pdf, "trailer").unwrap()..]).to_string();
+        let start = tail
+            .lines()
+            .skip_while(|l| *l != "startxref")
+            .nth(1)
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+
+        let table = String::from_utf8_lossy(&pdf[start..]).to_string();
+        let mut lines = table.lines();
+        assert_eq!(lines.next(), Some("xref"));
+
+        let count = lines.next().unwrap()[2..].parse::<usize>().unwrap();
+        assert_eq!(lines.next(), Some("0000000000 65535 f "));
+        assert!(tail.contains(&format!("/Size {} /Root 1 0 R", count)));
+
+        for n in 1..count {
+            let entry = lines.next().unwrap();
+            assert!(entry.ends_with(" 00000 n "), "{}", entry);
+
+            let offset = entry[..10].parse::<usize>().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj\n", n).as_bytes()));
+        }
+
+        // Words are drawn one at a time.
+        assert!(find(&pdf, "(Hello) Tj").is_some());
+        assert!(find(&pdf, "(\\(PDF\\)) Tj").is_some());
+    }
+}
@@ -0,0 +1,278 @@
+use crate::core::documents::{
+    Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, InlineContent, InlineRun, ListBlock,
+    ParagraphBlock, Style,
+};
+
+pub struct ManPageMetadata {
+    title: String,
+    section: String,
+    date: String,
+    source: Option<String>,
+    manual: Option<String>,
+}
+
+impl ManPageMetadata {
+    pub fn new(title: String, section: String, date: String) -> ManPageMetadata {
+        ManPageMetadata {
+            title,
+            section,
+            date,
+            source: None,
+            manual: None,
+        }
+    }
+
+    pub fn with_source(mut self, source: String) -> ManPageMetadata {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_manual(mut self, manual: String) -> ManPageMetadata {
+        self.manual = Some(manual);
+        self
+    }
+}
+
+pub fn escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\\' => "\\e".to_string(),
+            '-' => "\\-".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn escape_line_start(line: String) -> String {
+    // A leading `.` or `'` would be read as a request, `\&` is a zero width guard.
+    match line.starts_with('.') || line.starts_with('\'') {
+        true => format!("\\&{}", line),
+        false => line,
+    }
+}
+
+fn escape_lines(text: String) -> String {
+    text.split('\n')
+        .map(|l| escape_line_start(l.to_string()))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn quote_argument(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\e").replace('"', "\\(dq"))
+}
+
+fn render_span(content: String, style: Style) -> String {
+    match (
+        style.has_class("b"),
+        style.has_class("i"),
+        style.has_class("code"),
+    ) {
+        (_, _, true) => format!("\\f(CW{}\\fR", escape(&content)),
+        (true, true, false) => format!("\\f(BI{}\\fR", escape(&content)),
+        (true, false, false) => format!("\\fB{}\\fR", escape(&content)),
+        (false, true, false) => format!("\\fI{}\\fR", escape(&content)),
+        (false, false, false) => escape(&content),
+    }
+}
+
+// Font escapes don't nest, so nested spans are rendered as runs with their combined style.
+fn render_inline_content(content: Vec<InlineContent>) -> String {
+    let text = InlineRun::group_by_link(InlineContent::runs(content))
+        .into_iter()
+        .map(|(link, runs)| {
+            let text = runs
+                .into_iter()
+                .map(|r| render_span(r.text, r.style))
+                .collect::<Vec<String>>()
+                .join("");
+
+            match link {
+                Some(url) => format!("{} \\(la\\fI{}\\fR\\(ra", text, escape(&url)),
+                None => text,
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("");
+
+    // Line breaks in the text start new input lines, each of which could be read as a request.
+    escape_lines(text)
+}
+
+fn render_header(block: HeaderBlock) -> String {
+    let macro_name = match block.level {
+        HeaderLevel::H1 => ".SH",
+        _ => ".SS",
+    };
+
+    // The heading is the macro's argument, so it has to stay on the macro's line. It is quoted
+    // so that it is a single argument, with quotes inside it doubled.
+    format!(
+        "{} \"{}\"",
+        macro_name,
+        render_inline_content(block.content)
+            .replace('\n', " ")
+            .replace('"', "\"\"")
+    )
+}
+
+fn render_paragraph(block: ParagraphBlock) -> String {
+    format!(".PP\n{}", render_inline_content(block.content))
+}
+
+fn render_code_block(block: CodeBlock) -> String {
+    format!(".EX\n{}\n.EE", escape_lines(escape(&block.content)))
+}
+
+fn render_list_block(block: ListBlock) -> String {
+    let ordered = block.ordered;
+
+    block
+        .items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let tag = match ordered {
+                true => format!("{}. 4", i + 1),
+                false => "\\(bu 2".to_string(),
+            };
+
+            format!(".IP {}\n{}", tag, render_inline_content(item.content))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn render_image_block(block: ImageBlock) -> String {
+    format!(
+        ".PP\n[{}] \\(la\\fI{}\\fR\\(ra",
+        escape(&block.alt_text),
+        escape(&block.source)
+    )
+}
+
+pub fn render(blocks: Vec<Block>) -> Vec<String> {
+    blocks
+        .into_iter()
+        .map(|b| match b {
+            Block::Header(h) => render_header(h),
+            Block::Paragraph(p) => render_paragraph(p),
+            Block::Code(c) => render_code_block(c),
+            Block::List(l) => render_list_block(l),
+            Block::Image(i) => render_image_block(i),
+            Block::Custom(c) => render(c.content).join("\n"),
+        })
+        .collect::<Vec<String>>()
+}
+
+pub fn render_document(blocks: Vec<Block>, metadata: &ManPageMetadata) -> String {
+    let mut header = vec![
+        quote_argument(&metadata.title.to_uppercase()),
+        quote_argument(&metadata.section),
+        quote_argument(&metadata.date),
+    ];
+
+    // Arguments are positional, so an empty source is needed ahead of the manual name.
+    match (&metadata.source, &metadata.manual) {
+        (None, None) => {}
+        (s, m) => {
+            header.push(quote_argument(s.as_deref().unwrap_or("")));
+            if let Some(m) = m {
+                header.push(quote_argument(m));
+            }
+        }
+    }
+
+    format!(".TH {}\n{}\n", header.join(" "), render(blocks).join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::{InlineSpan, InlineText, ListItem};
+
+    fn text(s: &str) -> InlineContent {
+        InlineContent::Text(InlineText::new(s.to_string()))
+    }
+
+    fn span(class: &str, s: &str) -> InlineContent {
+        InlineContent::Span(InlineSpan::from_text(
+            Style::Ref(vec![class.to_string()]),
+            s.to_string(),
+        ))
+    }
+
+    #[test]
+    fn requests_are_guarded_on_every_line() {
+        let paragraph = Block::paragraph(ParagraphBlock::new(
+            Style::Default,
+            vec![text(".start\nmiddle\n'quote\n.end")],
+        ));
+
+        assert_eq!(
+            render(vec![paragraph]),
+            vec![".PP\n\\&.start\nmiddle\n\\&'quote\n\\&.end"]
+        );
+
+        let list = Block::list(ListBlock::new_unordered(
+            Style::Default,
+            vec![ListItem::new(Style::Default, vec![text("one\n.two")])],
+        ));
+
+        assert_eq!(render(vec![list]), vec![".IP \\(bu 2\none\n\\&.two"]);
+
+        let code = Block::code(CodeBlock::new(
+            Style::Default,
+            "a\n.b\n'c".to_string(),
+            None,
+        ));
+
+        assert_eq!(render(vec![code]), vec![".EX\na\n\\&.b\n\\&'c\n.EE"]);
+    }
+
+    #[test]
+    fn headings_stay_on_the_macro_line() {
+        let header = Block::header(HeaderBlock::new(
+            Style::Default,
+            HeaderLevel::H2,
+            vec![text("Two\n\"lines\"")],
+            true,
+        ));
+
+        assert_eq!(render(vec![header]), vec![".SS \"Two \"\"lines\"\"\""]);
+    }
+
+    #[test]
+    fn fonts() {
+        let paragraph = Block::paragraph(ParagraphBlock::new(
+            Style::Default,
+            vec![
+                span("code", "a-b"),
+                text(" "),
+                span("b", "bold"),
+                text(" "),
+                span("i", "c:\\"),
+            ],
+        ));
+
+        assert_eq!(
+            render(vec![paragraph]),
+            vec![".PP\n\\f(CWa\\-b\\fR \\fBbold\\fR \\fIc:\\e\\fR"]
+        );
+    }
+
+    #[test]
+    fn title_arguments_are_quoted() {
+        let metadata = ManPageMetadata::new(
+            "rdom".to_string(),
+            "1".to_string(),
+            "2024-01-01".to_string(),
+        )
+        .with_manual("Say \"hi\"".to_string());
+
+        assert_eq!(
+            render_document(vec![], &metadata),
+            ".TH \"RDOM\" \"1\" \"2024-01-01\" \"\" \"Say \\(dqhi\\(dq\"\n\n"
+        );
+    }
+}
@@ -0,0 +1,724 @@
+use crate::core::documents::{
+    Block, CodeBlock, HeaderBlock, HeaderLevel, ImageBlock, InlineContent, InlineRun, ListBlock,
+    ParagraphBlock, Style,
+};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const ITALIC: &str = "\x1b[3m";
+const STRIKE: &str = "\x1b[9m";
+const DIM: &str = "\x1b[2m";
+const CODE: &str = "\x1b[36m";
+const LINK: &str = "\x1b[4;34m";
+
+pub struct TerminalOptions {
+    width: usize,
+    colour: bool,
+}
+
+// Part of a word in one style, escape sequences are added when the word is written.
+struct Piece {
+    text: String,
+    open: String,
+    link: Option<String>,
+}
+
+struct Word {
+    width: usize,
+    pieces: Vec<Piece>,
+}
+
+struct WordStyle<'a> {
+    open: String,
+    link: Option<&'a str>,
+}
+
+impl TerminalOptions {
+    pub fn new(width: usize, colour: bool) -> TerminalOptions {
+        TerminalOptions {
+            // Anything narrower can not fit a code block border and a character.
+            width: width.max(8),
+            colour,
+        }
+    }
+
+    pub fn plain(width: usize) -> TerminalOptions {
+        TerminalOptions::new(width, false)
+    }
+}
+
+// Wide (East Asian and emoji) ranges take two columns, combining marks and zero width
+// characters none.
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115F),
+    (0x231A, 0x231B),
+    (0x2329, 0x232A),
+    (0x23E9, 0x23EC),
+    (0x23F0, 0x23F0),
+    (0x23F3, 0x23F3),
+    (0x25FD, 0x25FE),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267F, 0x267F),
+    (0x2693, 0x2693),
+    (0x26A1, 0x26A1),
+    (0x26AA, 0x26AB),
+    (0x26BD, 0x26BE),
+    (0x26C4, 0x26C5),
+    (0x26CE, 0x26CE),
+    (0x26D4, 0x26D4),
+    (0x26EA, 0x26EA),
+    (0x26F2, 0x26F5),
+    (0x26FA, 0x26FD),
+    (0x2705, 0x2705),
+    (0x270A, 0x270B),
+    (0x2728, 0x2728),
+    (0x274C, 0x274C),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27B0, 0x27B0),
+    (0x27BF, 0x27BF),
+    (0x2B1B, 0x2B1C),
+    (0x2B50, 0x2B50),
+    (0x2B55, 0x2B55),
+    (0x2E80, 0x303E),
+    (0x3041, 0x33FF),
+    (0x3400, 0x4DBF),
+    (0x4E00, 0x9FFF),
+    (0xA000, 0xA4CF),
+    (0xA960, 0xA97F),
+    (0xAC00, 0xD7A3),
+    (0xF900, 0xFAFF),
+    (0xFE10, 0xFE19),
+    (0xFE30, 0xFE6F),
+    (0xFF00, 0xFF60),
+    (0xFFE0, 0xFFE6),
+    (0x16FE0, 0x16FE4),
+    (0x17000, 0x18CFF),
+    (0x1B000, 0x1B2FF),
+    (0x1F004, 0x1F004),
+    (0x1F0CF, 0x1F0CF),
+    (0x1F18E, 0x1F18E),
+    (0x1F191, 0x1F19A),
+    (0x1F200, 0x1F251),
+    (0x1F300, 0x1F320),
+    (0x1F32D, 0x1F335),
+    (0x1F337, 0x1F37C),
+    (0x1F37E, 0x1F393),
+    (0x1F3A0, 0x1F3CA),
+    (0x1F3CF, 0x1F3D3),
+    (0x1F3E0, 0x1F3F0),
+    (0x1F3F4, 0x1F3F4),
+    (0x1F3F8, 0x1F43E),
+    (0x1F440, 0x1F440),
+    (0x1F442, 0x1F4FC),
+    (0x1F4FF, 0x1F53D),
+    (0x1F54B, 0x1F54E),
+    (0x1F550, 0x1F567),
+    (0x1F57A, 0x1F57A),
+    (0x1F595, 0x1F596),
+    (0x1F5A4, 0x1F5A4),
+    (0x1F5FB, 0x1F64F),
+    (0x1F680, 0x1F6C5),
+    (0x1F6CC, 0x1F6CC),
+    (0x1F6D0, 0x1F6D2),
+    (0x1F6D5, 0x1F6D7),
+    (0x1F6EB, 0x1F6EC),
+    (0x1F6F4, 0x1F6FC),
+    (0x1F7E0, 0x1F7EB),
+    (0x1F90C, 0x1F93A),
+    (0x1F93C, 0x1F945),
+    (0x1F947, 0x1F9FF),
+    (0x1FA70, 0x1FAFF),
+    (0x20000, 0x2FFFD),
+    (0x30000, 0x3FFFD),
+];
+
+// Control characters aren't listed, they are replaced by `sanitise` before anything is measured.
+const ZERO_WIDTH: &[(u32, u32)] = &[
+    (0x0300, 0x036F),
+    (0x0483, 0x0489),
+    (0x0591, 0x05BD),
+    (0x0610, 0x061A),
+    (0x064B, 0x065F),
+    (0x0E31, 0x0E31),
+    (0x0E34, 0x0E3A),
+    (0x0E47, 0x0E4E),
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x200B, 0x200F),
+    (0x2028, 0x202E),
+    (0x2060, 0x2064),
+    (0x20D0, 0x20FF),
+    (0x302A, 0x302D),
+    (0x3099, 0x309A),
+    (0xFE00, 0xFE0F),
+    (0xFE20, 0xFE2F),
+    (0xFEFF, 0xFEFF),
+    (0xE0100, 0xE01EF),
+];
+
+fn in_table(table: &[(u32, u32)], c: char) -> bool {
+    table
+        .binary_search_by(|(start, end)| match (*end < c as u32, *start > c as u32) {
+            (true, _) => std::cmp::Ordering::Less,
+            (_, true) => std::cmp::Ordering::Greater,
+            _ => std::cmp::Ordering::Equal,
+        })
+        .is_ok()
+}
+
+fn char_width(c: char) -> usize {
+    match (in_table(ZERO_WIDTH, c), in_table(WIDE, c)) {
+        (true, _) => 0,
+        (false, true) => 2,
+        (false, false) => 1,
+    }
+}
+
+// Control characters in the document could start escape sequences of their own, so they are
+// shown as U+FFFD.
+fn sanitise(text: &str) -> String {
+    text.chars()
+        .map(|c| match c.is_control() {
+            true => '\u{FFFD}',
+            false => c,
+        })
+        .collect()
+}
+
+// URLs are only written into hyperlink sequences if they can't end them early.
+fn safe_url(url: &str) -> Option<&str> {
+    match url.contains(char::is_control) {
+        true => None,
+        false => Some(url),
+    }
+}
+
+// The number of columns the text takes, it must not contain escape sequences.
+fn visible_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+fn span_style(style: &Style) -> String {
+    match (
+        style.has_class("b"),
+        style.has_class("i"),
+        style.has_class("code"),
+    ) {
+        (_, _, true) => CODE.to_string(),
+        (b, i, false) => format!(
+            "{}{}{}",
+            if b { BOLD } else { "" },
+            if i { ITALIC } else { "" },
+            if style.has_class("s") { STRIKE } else { "" }
+        ),
+    }
+}
+
+fn push_words(words: &mut Vec<Word>, glue: &mut bool, text: &str, style: &WordStyle) {
+    for (i, part) in text.split(char::is_whitespace).enumerate() {
+        if i > 0 {
+            *glue = false;
+        }
+
+        if part.is_empty() {
+            continue;
+        }
+
+        let part = sanitise(part);
+        let piece = Piece {
+            text: part.clone(),
+            open: style.open.clone(),
+            link: style.link.map(|l| l.to_string()),
+        };
+
+        match (*glue, words.last_mut()) {
+            (true, Some(w)) => {
+                w.width += visible_width(&part);
+                w.pieces.push(piece);
+            }
+            _ => words.push(Word {
+                width: visible_width(&part),
+                pieces: vec![piece],
+            }),
+        }
+
+        *glue = true;
+    }
+}
+
+fn render_word(word: &Word, options: &TerminalOptions) -> String {
+    word.pieces
+        .iter()
+        .map(|p| match (options.colour, &p.link) {
+            (false, _) => p.text.clone(),
+            // OSC 8 hyperlink, each word is linked so wrapping does not break the sequence.
+            (true, Some(url)) => format!(
+                "\x1b]8;;{}\x1b\\{}{}{}\x1b]8;;\x1b\\",
+                url, p.open, p.text, RESET
+            ),
+            (true, None) if p.open.is_empty() => p.text.clone(),
+            (true, None) => format!("{}{}{}", p.open, p.text, RESET),
+        })
+        .collect()
+}
+
+// Splits a word wider than `width` columns into words that fit, keeping the style of each
+// piece.
+fn break_word(word: Word, width: usize) -> Vec<Word> {
+    let mut words = Vec::<Word>::new();
+    let mut current = Word {
+        width: 0,
+        pieces: vec![],
+    };
+
+    for piece in word.pieces {
+        let mut text = String::new();
+        let mut text_width = 0;
+
+        for c in piece.text.chars() {
+            if current.width + text_width + char_width(c) > width && current.width + text_width > 0
+            {
+                if !text.is_empty() {
+                    current.pieces.push(Piece {
+                        text: std::mem::take(&mut text),
+                        open: piece.open.clone(),
+                        link: piece.link.clone(),
+                    });
+                }
+                current.width += text_width;
+                text_width = 0;
+                words.push(std::mem::replace(
+                    &mut current,
+                    Word {
+                        width: 0,
+                        pieces: vec![],
+                    },
+                ));
+            }
+
+            text.push(c);
+            text_width += char_width(c);
+        }
+
+        current.width += text_width;
+        current.pieces.push(Piece {
+            text,
+            open: piece.open,
+            link: piece.link,
+        });
+    }
+
+    words.push(current);
+    words
+}
+
+fn inline_words(content: Vec<InlineContent>, base: &str, options: &TerminalOptions) -> Vec<Word> {
+    let mut words = Vec::<Word>::new();
+    let mut glue = false;
+
+    for (link, runs) in InlineRun::group_by_link(InlineContent::runs(content)) {
+        let open = match link {
+            Some(_) => format!("{}{}", base, LINK),
+            None => base.to_string(),
+        };
+
+        for run in runs {
+            let style = WordStyle {
+                open: format!("{}{}", open, span_style(&run.style)),
+                link: link.as_deref().and_then(safe_url),
+            };
+            push_words(&mut words, &mut glue, &run.text, &style);
+        }
+
+        // Without hyperlink support the target has to be shown.
+        if let (Some(url), false) = (&link, options.colour) {
+            let url = format!("({})", url);
+            let plain = WordStyle {
+                open: "".to_string(),
+                link: None,
+            };
+            glue = false;
+            push_words(&mut words, &mut glue, &url, &plain);
+        }
+    }
+
+    words
+}
+
+fn wrap(words: Vec<Word>, options: &TerminalOptions, first: &str, rest: &str) -> String {
+    let mut lines = Vec::<String>::new();
+    let mut line = first.to_string();
+    let mut line_width = visible_width(first);
+    let mut empty = true;
+
+    // Words too long for a line of their own are broken, they would overflow otherwise.
+    let available = options.width.saturating_sub(visible_width(rest)).max(1);
+    let words = words.into_iter().flat_map(|w| match w.width > available {
+        true => break_word(w, available),
+        false => vec![w],
+    });
+
+    for w in words {
+        if !empty && line_width + 1 + w.width > options.width {
+            lines.push(line);
+            line = rest.to_string();
+            line_width = visible_width(rest);
+            empty = true;
+        }
+
+        if !empty {
+            line.push(' ');
+            line_width += 1;
+        }
+
+        line.push_str(&render_word(&w, options));
+        line_width += w.width;
+        empty = false;
+    }
+
+    lines.push(line);
+    lines.join("\n")
+}
+
+fn render_header(block: HeaderBlock, options: &TerminalOptions) -> String {
+    let (colour, rule) = match block.level {
+        HeaderLevel::H1 => ("\x1b[1;35m", Some('=')),
+        HeaderLevel::H2 => ("\x1b[1;34m", Some('-')),
+        HeaderLevel::H3 => ("\x1b[1;36m", None),
+        HeaderLevel::H4 => (BOLD, None),
+        HeaderLevel::H5 => (BOLD, None),
+        HeaderLevel::H6 => (BOLD, None),
+    };
+
+    let words = inline_words(block.content, colour, options);
+    let text_width = words.iter().map(|w| w.width + 1).sum::<usize>();
+    let text = wrap(words, options, "", "");
+
+    match (options.colour, rule) {
+        (false, Some(r)) => format!(
+            "{}\n{}",
+            text,
+            r.to_string()
+                .repeat(text_width.saturating_sub(1).min(options.width))
+        ),
+        _ => text,
+    }
+}
+
+fn render_paragraph(block: ParagraphBlock, options: &TerminalOptions) -> String {
+    wrap(inline_words(block.content, "", options), options, "", "")
+}
+
+fn split_to_width(line: &str, width: usize) -> Vec<String> {
+    let mut lines = vec!["".to_string()];
+    let mut line_width = 0;
+
+    for c in line.chars() {
+        if line_width + char_width(c) > width && line_width > 0 {
+            lines.push("".to_string());
+            line_width = 0;
+        }
+
+        lines.last_mut().unwrap().push(c);
+        line_width += char_width(c);
+    }
+
+    lines
+}
+
+fn render_code_block(block: CodeBlock, options: &TerminalOptions) -> String {
+    // Borders and padding take four columns.
+    let lines: Vec<String> = block
+        .content
+        .split('\n')
+        .flat_map(|l| split_to_width(&sanitise(&l.replace('\t', "    ")), options.width - 4))
+        .collect();
+
+    let label = match &block.language {
+        Some(l) if !l.is_empty() => format!(" {} ", sanitise(l)),
+        _ => "".to_string(),
+    };
+
+    let inner = lines
+        .iter()
+        .map(|l| visible_width(l))
+        .max()
+        .unwrap_or(0)
+        .max(visible_width(&label) + 1)
+        .min(options.width - 4);
+
+    let (open, close) = match options.colour {
+        true => (DIM, RESET),
+        false => ("", ""),
+    };
+
+    let top = format!(
+        "{}┌─{}{}┐{}",
+        open,
+        label,
+        "─".repeat((inner + 1).saturating_sub(visible_width(&label))),
+        close
+    );
+
+    let bottom = format!("{}└{}┘{}", open, "─".repeat(inner + 2), close);
+
+    let body = lines
+        .iter()
+        .map(|l| {
+            format!(
+                "{}│{} {}{} {}│{}",
+                open,
+                close,
+                l,
+                " ".repeat(inner - visible_width(l)),
+                open,
+                close
+            )
+        })
+        .collect::<Vec<String>>();
+
+    format!("{}\n{}\n{}", top, body.join("\n"), bottom)
+}
+
+fn render_list_block(block: ListBlock, options: &TerminalOptions) -> String {
+    let ordered = block.ordered;
+    let count = block.items.len();
+
+    block
+        .items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let marker = match ordered {
+                true => format!("{:>w$}.", i + 1, w = count.to_string().len()),
+                false => "•".to_string(),
+            };
+
+            let first = format!("  {} ", marker);
+            let rest = " ".repeat(visible_width(&first));
+
+            wrap(
+                inline_words(item.content, "", options),
+                options,
+                &first,
+                &rest,
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn render_image_block(block: ImageBlock, options: &TerminalOptions) -> String {
+    let alt = match block.alt_text.is_empty() {
+        true => "image".to_string(),
+        false => format!("image: {}", sanitise(&block.alt_text)),
+    };
+
+    match (options.colour, safe_url(&block.source)) {
+        (true, Some(url)) => format!(
+            "\x1b]8;;{}\x1b\\{}[{}]{}\x1b]8;;\x1b\\",
+            url, DIM, alt, RESET
+        ),
+        (true, None) => format!("{}[{}]{}", DIM, alt, RESET),
+        (false, _) => format!("[{}] ({})", alt, sanitise(&block.source)),
+    }
+}
+
+pub fn render(blocks: Vec<Block>, options: &TerminalOptions) -> Vec<String> {
+    blocks
+        .into_iter()
+        .map(|b| match b {
+            Block::Header(h) => render_header(h, options),
+            Block::Paragraph(p) => render_paragraph(p, options),
+            Block::Code(c) => render_code_block(c, options),
+            Block::List(l) => render_list_block(l, options),
+            Block::Image(i) => render_image_block(i, options),
+            Block::Custom(c) => render(c.content, options).join("\n\n"),
+        })
+        .collect::<Vec<String>>()
+}
+
+pub fn render_document(blocks: Vec<Block>, options: &TerminalOptions) -> String {
+    format!("{}\n", render(blocks, options).join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::documents::{InlineLink, InlineText, ListItem};
+
+    fn paragraph(text: &str) -> Block {
+        Block::paragraph(ParagraphBlock::new(
+            Style::Default,
+            vec![InlineContent::Text(InlineText::new(text.to_string()))],
+        ))
+    }
+
+    #[test]
+    fn display_width() {
+        assert_eq!(visible_width("abc"), 3);
+        assert_eq!(visible_width("日本語"), 6);
+        assert_eq!(visible_width("한글"), 4);
+        assert_eq!(visible_width("ｗｉｄｅ"), 8);
+        assert_eq!(visible_width("🎉🚀"), 4);
+        assert_eq!(visible_width("e\u{301}"), 1);
+        assert_eq!(visible_width("a\u{200B}b"), 2);
+        assert_eq!(visible_width("€→•"), 3);
+    }
+
+    #[test]
+    fn wide_text_wraps_by_columns() {
+        let text = render(
+            vec![paragraph("日本語 日本語 日本語")],
+            &TerminalOptions::plain(14),
+        );
+
+        assert_eq!(text, vec!["日本語 日本語\n日本語"]);
+    }
+
+    #[test]
+    fn long_words_are_broken() {
+        let text = render(
+            vec![paragraph("see abcdefghijklmnopqrstuvwxyz now")],
+            &TerminalOptions::plain(10),
+        );
+        assert_eq!(text, vec!["see\nabcdefghij\nklmnopqrst\nuvwxyz now"]);
+
+        let wide = render(vec![paragraph("日本語日本語")], &TerminalOptions::plain(8));
+        assert_eq!(wide, vec!["日本語日\n本語"]);
+
+        let list = render(
+            vec![Block::list(ListBlock::new_unordered(
+                Style::Default,
+                vec![ListItem::new(
+                    Style::Default,
+                    vec![InlineContent::Text(InlineText::new(
+                        "abcdefghijklmnop".to_string(),
+                    ))],
+                )],
+            ))],
+            &TerminalOptions::plain(10),
+        );
+        assert_eq!(list, vec!["  • abcdef\n    ghijkl\n    mnop"]);
+    }
+
+    #[test]
+    fn broken_links_keep_their_escape_sequences() {
+        let text = render(
+            vec![Block::paragraph(ParagraphBlock::new(
+                Style::Default,
+                vec![InlineContent::Link(InlineLink::from_text(
+                    Style::Default,
+                    "u".to_string(),
+                    "abcdefghij".to_string(),
+                ))],
+            ))],
+            &TerminalOptions::new(8, true),
+        );
+
+        assert_eq!(
+            text,
+            vec![
+                "\x1b]8;;u\x1b\\\x1b[4;34mabcdefgh\x1b[0m\x1b]8;;\x1b\\\n\x1b]8;;u\x1b\\\x1b[4;34mij\x1b[0m\x1b]8;;\x1b\\"
+            ]
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_padded_by_columns() {
+        let text = render(
+            vec![Block::code(CodeBlock::new(
+                Style::Default,
+                "日本\nab".to_string(),
+                None,
+            ))],
+            &TerminalOptions::plain(20),
+        );
+
+        assert_eq!(text, vec!["┌──────┐\n│ 日本 │\n│ ab   │\n└──────┘"]);
+
+        let narrow = render(
+            vec![Block::code(CodeBlock::new(
+                Style::Default,
+                "日本語日本".to_string(),
+                None,
+            ))],
+            &TerminalOptions::plain(8),
+        );
+
+        assert_eq!(
+            narrow,
+            vec!["┌──────┐\n│ 日本 │\n│ 語日 │\n│ 本   │\n└──────┘"]
+        );
+    }
+
+    #[test]
+    fn control_characters_are_replaced() {
+        let options = TerminalOptions::new(40, true);
+
+        assert_eq!(
+            render(vec![paragraph("a\x1b]0;title\x07b \u{9b}2J")], &options),
+            vec!["a\u{FFFD}]0;title\u{FFFD}b \u{FFFD}2J"]
+        );
+
+        let code = render(
+            vec![Block::code(CodeBlock::new(
+                Style::Default,
+                "\x1b[2J".to_string(),
+                Some("sh\x1b".to_string()),
+            ))],
+            &TerminalOptions::plain(20),
+        );
+        assert_eq!(
+            code,
+            vec!["┌─ sh\u{FFFD} ──┐\n│ \u{FFFD}[2J   │\n└────────┘"]
+        );
+    }
+
+    #[test]
+    fn urls_with_control_characters_are_not_linked() {
+        let link = |url: &str| {
+            Block::paragraph(ParagraphBlock::new(
+                Style::Default,
+                vec![InlineContent::Link(InlineLink::from_text(
+                    Style::Default,
+                    url.to_string(),
+                    "x".to_string(),
+                ))],
+            ))
+        };
+
+        assert_eq!(
+            render(
+                vec![link("u\x1b\\\x1b[2J")],
+                &TerminalOptions::new(40, true)
+            ),
+            vec!["\x1b[4;34mx\x1b[0m"]
+        );
+        assert_eq!(
+            render(vec![link("u\x1b[2J")], &TerminalOptions::plain(40)),
+            vec!["x (u\u{FFFD}[2J)"]
+        );
+
+        let image = Block::image(ImageBlock::new(
+            Style::Default,
+            "a\x07.png".to_string(),
+            "".to_string(),
+            "A\x1b".to_string(),
+            None,
+            None,
+        ));
+
+        assert_eq!(
+            render(vec![image.clone()], &TerminalOptions::new(40, true)),
+            vec!["\x1b[2m[image: A\u{FFFD}]\x1b[0m"]
+        );
+        assert_eq!(
+            render(vec![image], &TerminalOptions::plain(40)),
+            vec!["[image: A\u{FFFD}] (a\u{FFFD}.png)"]
+        );
+    }
+}